@@ -1,23 +1,17 @@
-use actix_web::error::InternalError;
-use actix_web::http::StatusCode;
-use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use actix_web::{web, App, HttpRequest, HttpServer};
 use sailfish::TemplateSimple;
 
 #[derive(TemplateSimple)]
 #[template(path = "actix.stpl")]
-struct Greet<'a> {
-    name: &'a str,
+struct Greet {
+    name: String,
 }
 
-async fn greet(req: HttpRequest) -> actix_web::Result<HttpResponse> {
+async fn greet(req: HttpRequest) -> Greet {
     let name = req.match_info().get("name").unwrap_or("World");
-    let body = Greet { name }
-        .render_once()
-        .map_err(|e| InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR))?;
-
-    Ok(HttpResponse::Ok()
-        .content_type("text/html; charset=utf-8")
-        .body(body))
+    Greet {
+        name: name.to_owned(),
+    }
 }
 
 #[actix_rt::main]
@@ -0,0 +1,122 @@
+use lsp_types::{Position, Range};
+use sailfish_compiler::{Parser, Token, TokenKind};
+use std::path::PathBuf;
+
+/// Template file opened in the editor
+pub struct Document {
+    pub path: PathBuf,
+    pub text: String,
+    line_starts: Vec<usize>,
+}
+
+impl Document {
+    pub fn new(path: PathBuf, text: String) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self {
+            path,
+            text,
+            line_starts,
+        }
+    }
+
+    /// Position in the editor, whose column is counted in UTF-16 code units
+    pub fn position(&self, offset: usize) -> Position {
+        let offset = floor_char_boundary(&self.text, offset);
+        let line = self.line_starts.partition_point(|&s| s <= offset) - 1;
+        let start = self.line_starts[line];
+        let character = self.text[start..offset].encode_utf16().count();
+        Position::new(line as u32, character as u32)
+    }
+
+    pub fn range(&self, offset: usize, length: usize) -> Range {
+        Range::new(self.position(offset), self.position(offset + length))
+    }
+
+    /// Byte offset of the position, which is clamped into the line
+    pub fn offset(&self, position: Position) -> usize {
+        let line = position.line as usize;
+        let Some(&start) = self.line_starts.get(line) else {
+            return self.text.len();
+        };
+        let end = self
+            .line_starts
+            .get(line + 1)
+            .map_or(self.text.len(), |&next| next - 1);
+
+        let mut units = 0;
+        for (i, c) in self.text[start..end].char_indices() {
+            if units >= position.character as usize {
+                return start + i;
+            }
+            units += c.len_utf16();
+        }
+        end
+    }
+
+    /// Tag which contains the offset
+    pub fn token_at(&self, delimiter: char, offset: usize) -> Option<Token<'_>> {
+        Parser::new()
+            .delimiter(delimiter)
+            .parse(&self.text)
+            .filter_map(Result::ok)
+            .filter(|t| t.kind() != TokenKind::Text)
+            .find(|t| t.offset() <= offset && offset <= t.offset() + t.as_str().len())
+    }
+
+    /// Code tag which is being edited at the offset
+    ///
+    /// The tag does not need to be closed yet. Returns the code in the tag before
+    /// the offset.
+    pub fn code_before(&self, delimiter: char, offset: usize) -> Option<&str> {
+        let offset = floor_char_boundary(&self.text, offset);
+        // close the tag at the offset (the space prevents `_` before the offset
+        // from being parsed as a trim marker)
+        let close = format!("{}>", delimiter);
+        let source = format!("{} {}", &self.text[..offset], close);
+        let last = Parser::new()
+            .delimiter(delimiter)
+            .parse(&source)
+            .filter_map(Result::ok)
+            .last()?;
+        let end = last.offset() + last.as_str().len();
+
+        match last.kind() {
+            TokenKind::Code
+            | TokenKind::BufferedCode { .. }
+            | TokenKind::NestedTemplateOnce
+                if source[end..].trim_start() == close =>
+            {
+                // whitespaces at the beginning of the tag are not included
+                Some(&self.text[last.offset().min(offset)..offset])
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Range of the identifier at the offset
+pub fn word_at(text: &str, offset: usize) -> Option<(usize, usize)> {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    let offset = floor_char_boundary(text, offset);
+    let start = text[..offset]
+        .char_indices()
+        .rev()
+        .take_while(|&(_, c)| is_ident(c))
+        .last()
+        .map_or(offset, |(i, _)| i);
+    let end = text[offset..]
+        .char_indices()
+        .find(|&(_, c)| !is_ident(c))
+        .map_or(text.len(), |(i, _)| offset + i);
+    (start < end).then_some((start, end))
+}
+
+fn floor_char_boundary(text: &str, offset: usize) -> usize {
+    let mut offset = offset.min(text.len());
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}
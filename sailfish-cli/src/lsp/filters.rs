@@ -0,0 +1,58 @@
+/// Built-in filters in `sailfish::runtime::filter` and their documentation
+const FILTERS: &[(&str, &str)] = &[
+    (
+        "disp",
+        "```rust\nfn disp<T: Display + ?Sized>(expr: &T)\n```\n\
+         Render using `std::fmt::Display` trait\n\n\
+         ```rhtml\nfilename: <%= filename.display() | disp %>\n```",
+    ),
+    (
+        "dbg",
+        "```rust\nfn dbg<T: Debug + ?Sized>(expr: &T)\n```\n\
+         Render using `std::fmt::Debug` trait\n\n\
+         ```rhtml\ntable content: <%= table | dbg %>\n```",
+    ),
+    (
+        "upper",
+        "```rust\nfn upper<T: Render + ?Sized>(expr: &T)\n```\n\
+         Convert the rendered contents to uppercase\n\n\
+         ```rhtml\n<%= \"tschüß\" | upper %>\n```\n\
+         result: `TSCHÜSS`",
+    ),
+    (
+        "lower",
+        "```rust\nfn lower<T: Render + ?Sized>(expr: &T)\n```\n\
+         Convert the rendered contents to lowercase\n\n\
+         ```rhtml\n<%= \"ὈΔΥΣΣΕΎΣ\" | lower %>\n```\n\
+         result: `ὀδυσσεύς`",
+    ),
+    (
+        "trim",
+        "```rust\nfn trim<T: Render + ?Sized>(expr: &T)\n```\n\
+         Remove leading and trailing whitespaces from the rendered contents\n\n\
+         ```rhtml\n<%= \" Hello world\\n\" | trim %>\n```\n\
+         result: `Hello world`",
+    ),
+    (
+        "truncate",
+        "```rust\nfn truncate<T: Render + ?Sized>(expr: &T, limit: usize)\n```\n\
+         Limit the length of the rendered contents to `limit` characters, and \
+         append `...` if truncated\n\n\
+         ```rhtml\n<%= \"Hello, world!\" | truncate(5) %>\n```\n\
+         result: `Hello...`",
+    ),
+    (
+        "json",
+        "```rust\nfn json<T: Serialize + ?Sized>(expr: &T)\n```\n\
+         Serialize the data structure as JSON (requires `json` feature)\n\n\
+         ```rhtml\n\"data\": <%- data | json %>\n```",
+    ),
+];
+
+/// Documentation of the built-in filter in Markdown
+pub fn doc(name: &str) -> Option<&'static str> {
+    FILTERS
+        .iter()
+        .find(|(filter, _)| *filter == name)
+        .map(|(_, doc)| *doc)
+}
@@ -0,0 +1,254 @@
+use lsp_types::Position;
+use quote::ToTokens;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::{Attribute, Expr, ExprLit, Fields, Ident, Item, Lit, Meta, Token};
+
+/// Structs and enums defined in the Rust source files of a crate
+#[derive(Default)]
+pub struct SourceIndex {
+    files: HashMap<PathBuf, (SystemTime, Vec<TypeDef>)>,
+}
+
+/// Struct or enum in the source files
+pub struct TypeDef {
+    pub name: String,
+    pub file: PathBuf,
+    pub position: Position,
+    /// Templates specified in the `template` attributes of the type
+    pub templates: Vec<TemplateDef>,
+}
+
+/// Template specified by the `path` option, and the fields available in it
+pub struct TemplateDef {
+    pub path: String,
+    pub fields: Vec<Field>,
+    /// Whether the fields are bound to the local variables (`TemplateSimple` and
+    /// enums), or accessed through `self`
+    pub local: bool,
+}
+
+pub struct Field {
+    /// Name of the local variable or the field
+    pub name: String,
+    pub ty: String,
+}
+
+impl SourceIndex {
+    /// Parse the source files of the crate which were changed since the last update
+    pub fn update(&mut self, manifest_dir: &Path) {
+        let mut files = Vec::new();
+        for dir in ["src", "examples", "tests", "benches"] {
+            collect_sources(&manifest_dir.join(dir), &mut files);
+        }
+
+        let existing: HashSet<&PathBuf> = files.iter().collect();
+        self.files.retain(|path, _| existing.contains(path));
+
+        for path in files {
+            let Ok(modified) = fs::metadata(&path).and_then(|m| m.modified()) else {
+                continue;
+            };
+            if matches!(self.files.get(&path), Some((t, _)) if *t == modified) {
+                continue;
+            }
+
+            let types = parse_file(&path);
+            self.files.insert(path, (modified, types));
+        }
+    }
+
+    pub fn types(&self) -> impl Iterator<Item = &TypeDef> {
+        self.files.values().flat_map(|(_, types)| types)
+    }
+}
+
+fn collect_sources(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let hidden = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if hidden {
+            continue;
+        }
+
+        if path.is_dir() {
+            collect_sources(&path, files);
+        } else if path.extension().is_some_and(|ext| ext == "rs") {
+            files.push(path);
+        }
+    }
+}
+
+fn parse_file(path: &Path) -> Vec<TypeDef> {
+    let mut types = Vec::new();
+    let Ok(content) = fs::read_to_string(path) else {
+        return types;
+    };
+
+    if let Ok(file) = syn::parse_file(&content) {
+        let mut collector = Collector {
+            file: path,
+            content: &content,
+            types: &mut types,
+        };
+        collector.collect(&file.items);
+    }
+
+    // spans are kept in a thread local source map until they are invalidated
+    proc_macro2::extra::invalidate_current_thread_spans();
+    types
+}
+
+struct Collector<'a> {
+    file: &'a Path,
+    content: &'a str,
+    types: &'a mut Vec<TypeDef>,
+}
+
+impl Collector<'_> {
+    fn collect(&mut self, items: &[Item]) {
+        for item in items {
+            match *item {
+                Item::Struct(ref s) => {
+                    let options = template_options(&s.attrs);
+                    let local = derives(&s.attrs, "TemplateSimple");
+                    let templates = string_option(&options, "path")
+                        .map(|path| TemplateDef {
+                            path,
+                            fields: fields(&s.fields, local),
+                            local,
+                        })
+                        .into_iter()
+                        .collect();
+                    self.push(&s.ident, templates);
+                }
+                Item::Enum(ref e) => {
+                    let options = template_options(&e.attrs);
+                    let default = string_option(&options, "path");
+                    let templates = e
+                        .variants
+                        .iter()
+                        .filter_map(|v| {
+                            let options = template_options(&v.attrs);
+                            let path = match string_option(&options, "path") {
+                                Some(path) => path,
+                                None if string_option(&options, "source").is_some() => {
+                                    return None;
+                                }
+                                None => default.clone()?,
+                            };
+                            Some(TemplateDef {
+                                path,
+                                fields: fields(&v.fields, true),
+                                local: true,
+                            })
+                        })
+                        .collect();
+                    self.push(&e.ident, templates);
+                }
+                Item::Mod(ref m) => {
+                    if let Some((_, ref items)) = m.content {
+                        self.collect(items);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn push(&mut self, ident: &Ident, templates: Vec<TemplateDef>) {
+        // columns of the spans are counted in characters
+        let start = ident.span().start();
+        let character = self.content.lines().nth(start.line - 1).map_or(0, |line| {
+            line.chars()
+                .take(start.column)
+                .map(char::len_utf16)
+                .sum::<usize>()
+        });
+
+        self.types.push(TypeDef {
+            name: ident.to_string(),
+            file: self.file.to_owned(),
+            position: Position::new(start.line as u32 - 1, character as u32),
+            templates,
+        });
+    }
+}
+
+/// Fields of the struct or the enum variant
+///
+/// If `local` is true, the names of the local variables which the fields are bound
+/// to are returned in the same way as the derive macros.
+fn fields(fields: &Fields, local: bool) -> Vec<Field> {
+    let mut result = Vec::new();
+    for (i, field) in fields.iter().enumerate() {
+        let options = template_options(&field.attrs);
+        let skip = options
+            .iter()
+            .any(|m| matches!(*m, Meta::Path(ref p) if p.is_ident("skip")));
+        if local && skip {
+            continue;
+        }
+
+        let name = match (string_option(&options, "rename"), &field.ident) {
+            (Some(name), _) if local => name,
+            (_, Some(ident)) => ident.to_string(),
+            (_, None) if local => format!("_{}", i),
+            (_, None) => i.to_string(),
+        };
+        let ty = field
+            .ty
+            .span()
+            .source_text()
+            .unwrap_or_else(|| field.ty.to_token_stream().to_string());
+        result.push(Field { name, ty });
+    }
+    result
+}
+
+fn derives(attrs: &[Attribute], name: &str) -> bool {
+    attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("derive"))
+        .filter_map(|attr| {
+            attr.parse_args_with(Punctuated::<syn::Path, Token![,]>::parse_terminated)
+                .ok()
+        })
+        .flatten()
+        .any(|path| path.segments.last().is_some_and(|s| s.ident == name))
+}
+
+fn template_options(attrs: &[Attribute]) -> Vec<Meta> {
+    attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("template"))
+        .filter_map(|attr| {
+            attr.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+                .ok()
+        })
+        .flatten()
+        .collect()
+}
+
+fn string_option(options: &[Meta], key: &str) -> Option<String> {
+    options.iter().find_map(|meta| match *meta {
+        Meta::NameValue(ref nv) if nv.path.is_ident(key) => match nv.value {
+            Expr::Lit(ExprLit {
+                lit: Lit::Str(ref s),
+                ..
+            }) => Some(s.value()),
+            _ => None,
+        },
+        _ => None,
+    })
+}
@@ -0,0 +1,463 @@
+//! Language server for the template files
+//!
+//! Diagnostics are published when the templates are opened or saved. Other
+//! features work on the contents in the editor.
+
+mod document;
+mod filters;
+mod index;
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+    DidSaveTextDocument, Notification as _, PublishDiagnostics,
+};
+use lsp_types::request::{Completion, GotoDefinition, HoverRequest, Request as _};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionParams, CompletionResponse, Diagnostic,
+    DiagnosticRelatedInformation, DiagnosticSeverity, DidChangeTextDocumentParams,
+    DidCloseTextDocumentParams, DidOpenTextDocumentParams, GotoDefinitionParams,
+    GotoDefinitionResponse, Hover, HoverContents, HoverParams, Location, MarkupContent,
+    MarkupKind, OneOf, PublishDiagnosticsParams, Range, SaveOptions, ServerCapabilities,
+    TextDocumentPositionParams, TextDocumentSyncCapability, TextDocumentSyncKind,
+    TextDocumentSyncOptions, TextDocumentSyncSaveOptions, Url,
+};
+use sailfish_compiler::{Error, TokenKind};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use syn::LitStr;
+
+use self::document::{Document, word_at};
+use self::index::SourceIndex;
+use crate::project::Project;
+
+/// Run the language server on stdin and stdout until the client exits
+pub fn run() -> Result<(), Error> {
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Options(
+            TextDocumentSyncOptions {
+                open_close: Some(true),
+                change: Some(TextDocumentSyncKind::FULL),
+                save: Some(TextDocumentSyncSaveOptions::SaveOptions(SaveOptions {
+                    include_text: Some(false),
+                })),
+                ..Default::default()
+            },
+        )),
+        definition_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(true.into()),
+        completion_provider: Some(Default::default()),
+        ..Default::default()
+    };
+    connection
+        .initialize(serde_json::to_value(capabilities).unwrap())
+        .map_err(|e| Error::from(e.to_string()))?;
+
+    let mut server = Server {
+        connection,
+        documents: HashMap::new(),
+        indexes: HashMap::new(),
+    };
+    server.main_loop()?;
+
+    // the connection must be dropped to stop the writer thread
+    drop(server);
+    io_threads.join()?;
+    Ok(())
+}
+
+type RequestError = (ErrorCode, String);
+
+struct Server {
+    connection: Connection,
+    documents: HashMap<Url, Document>,
+    /// Rust source files of each crate, indexed by the manifest directories
+    indexes: HashMap<PathBuf, SourceIndex>,
+}
+
+impl Server {
+    fn main_loop(&mut self) -> Result<(), Error> {
+        while let Ok(message) = self.connection.receiver.recv() {
+            match message {
+                Message::Request(req) => {
+                    let shutdown = self
+                        .connection
+                        .handle_shutdown(&req)
+                        .map_err(|e| Error::from(e.to_string()))?;
+                    if shutdown {
+                        return Ok(());
+                    }
+
+                    let response = self.handle_request(req);
+                    self.send(response.into())?;
+                }
+                Message::Notification(not) => self.handle_notification(not)?,
+                Message::Response(_) => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn send(&self, message: Message) -> Result<(), Error> {
+        self.connection
+            .sender
+            .send(message)
+            .map_err(|e| Error::from(e.to_string()))
+    }
+
+    fn handle_request(&mut self, req: Request) -> Response {
+        let result = match req.method.as_str() {
+            GotoDefinition::METHOD => {
+                self.dispatch::<GotoDefinition>(req.params, Self::definition)
+            }
+            HoverRequest::METHOD => {
+                self.dispatch::<HoverRequest>(req.params, Self::hover)
+            }
+            Completion::METHOD => {
+                self.dispatch::<Completion>(req.params, Self::completion)
+            }
+            method => Err((
+                ErrorCode::MethodNotFound,
+                format!("Unsupported method: {}", method),
+            )),
+        };
+
+        match result {
+            Ok(value) => Response::new_ok(req.id, value),
+            Err((code, message)) => Response::new_err(req.id, code as i32, message),
+        }
+    }
+
+    fn dispatch<R: lsp_types::request::Request>(
+        &mut self,
+        params: serde_json::Value,
+        handler: fn(&mut Self, R::Params) -> R::Result,
+    ) -> Result<serde_json::Value, RequestError> {
+        let params = parse_params(params)?;
+        serde_json::to_value(handler(self, params))
+            .map_err(|e| (ErrorCode::InternalError, e.to_string()))
+    }
+
+    fn handle_notification(&mut self, not: Notification) -> Result<(), Error> {
+        match not.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let Ok(params) = parse_params::<DidOpenTextDocumentParams>(not.params)
+                else {
+                    return Ok(());
+                };
+                let uri = params.text_document.uri;
+                if let Some(path) = file_path(&uri) {
+                    let document = Document::new(path, params.text_document.text);
+                    self.documents.insert(uri.clone(), document);
+                    self.publish_diagnostics(&uri)?;
+                }
+            }
+            DidChangeTextDocument::METHOD => {
+                let Ok(params) = parse_params::<DidChangeTextDocumentParams>(not.params)
+                else {
+                    return Ok(());
+                };
+                let uri = params.text_document.uri;
+                if let (Some(document), Some(change)) = (
+                    self.documents.remove(&uri),
+                    params.content_changes.into_iter().last(),
+                ) {
+                    let document = Document::new(document.path, change.text);
+                    self.documents.insert(uri, document);
+                }
+            }
+            DidSaveTextDocument::METHOD => {
+                // templates which include the saved template are checked again
+                let uris: Vec<Url> = self.documents.keys().cloned().collect();
+                for uri in uris {
+                    self.publish_diagnostics(&uri)?;
+                }
+            }
+            DidCloseTextDocument::METHOD => {
+                let Ok(params) = parse_params::<DidCloseTextDocumentParams>(not.params)
+                else {
+                    return Ok(());
+                };
+                let uri = params.text_document.uri;
+                if self.documents.remove(&uri).is_some() {
+                    let params = PublishDiagnosticsParams::new(uri, Vec::new(), None);
+                    self.send(
+                        Notification::new(PublishDiagnostics::METHOD.to_owned(), params)
+                            .into(),
+                    )?;
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn publish_diagnostics(&self, uri: &Url) -> Result<(), Error> {
+        let Some(document) = self.documents.get(uri) else {
+            return Ok(());
+        };
+
+        let params = PublishDiagnosticsParams::new(uri.clone(), check(document), None);
+        self.send(Notification::new(PublishDiagnostics::METHOD.to_owned(), params).into())
+    }
+
+    /// Jump to the templates in `include!` macros and the directives, or the
+    /// types in `<%+ %>` tags
+    fn definition(
+        &mut self,
+        params: GotoDefinitionParams,
+    ) -> Option<GotoDefinitionResponse> {
+        let (document, project, offset) =
+            document_at(&self.documents, &params.text_document_position_params)?;
+        let token = document.token_at(project.config().delimiter, offset)?;
+        let code = token.as_str();
+        let offset = offset - token.offset();
+
+        let arg = match token.kind() {
+            TokenKind::Extends | TokenKind::Import => {
+                Some(syn::parse_str::<LitStr>(code).ok()?.value())
+            }
+            TokenKind::Code
+            | TokenKind::BufferedCode { .. }
+            | TokenKind::NestedTemplateOnce => include_arg_at(code, offset),
+            _ => None,
+        };
+        if let Some(arg) = arg {
+            let path = include_path(&document.path, &arg, &project);
+            let uri = Url::from_file_path(path.canonicalize().ok()?).ok()?;
+            return Some(Location::new(uri, Range::default()).into());
+        }
+
+        if token.kind() != TokenKind::NestedTemplateOnce {
+            return None;
+        }
+        let (start, end) = word_at(code, offset)?;
+        let name = &code[start..end];
+        if !name.starts_with(char::is_uppercase) {
+            return None;
+        }
+
+        let index = update_index(&mut self.indexes, &project);
+        let locations: Vec<Location> = index
+            .types()
+            .filter(|t| t.name == name)
+            .filter_map(|t| {
+                let uri = Url::from_file_path(&t.file).ok()?;
+                Some(Location::new(uri, Range::new(t.position, t.position)))
+            })
+            .collect();
+        (!locations.is_empty()).then_some(GotoDefinitionResponse::Array(locations))
+    }
+
+    /// Show the documentation of the built-in filters
+    fn hover(&mut self, params: HoverParams) -> Option<Hover> {
+        let (document, project, offset) =
+            document_at(&self.documents, &params.text_document_position_params)?;
+        let token = document.token_at(project.config().delimiter, offset)?;
+        if !matches!(token.kind(), TokenKind::BufferedCode { .. }) {
+            return None;
+        }
+
+        let code = token.as_str();
+        let (start, end) = word_at(code, offset - token.offset())?;
+        let before = code[..start].trim_end();
+        if !before.ends_with('|') || before.ends_with("||") {
+            return None;
+        }
+
+        let doc = filters::doc(&code[start..end])?;
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: doc.to_owned(),
+            }),
+            range: Some(document.range(token.offset() + start, end - start)),
+        })
+    }
+
+    /// Complete the fields of the types which render the template
+    fn completion(&mut self, params: CompletionParams) -> Option<CompletionResponse> {
+        let (document, project, offset) =
+            document_at(&self.documents, &params.text_document_position)?;
+        let code = document.code_before(project.config().delimiter, offset)?;
+        let before = code.trim_end_matches(|c: char| c.is_alphanumeric() || c == '_');
+        let after_self = before.ends_with("self.");
+        if !after_self && (before.ends_with('.') || before.ends_with("::")) {
+            return None;
+        }
+
+        let index = update_index(&mut self.indexes, &project);
+        let mut items: Vec<CompletionItem> = Vec::new();
+        let templates = index.types().flat_map(|t| &t.templates).filter(|t| {
+            t.local != after_self
+                && project
+                    .resolve_template(Path::new(&t.path))
+                    .and_then(|p| p.canonicalize().ok())
+                    .is_some_and(|p| p == document.path)
+        });
+        for field in templates.flat_map(|t| &t.fields) {
+            if items.iter().all(|item| item.label != field.name) {
+                items.push(CompletionItem {
+                    label: field.name.clone(),
+                    kind: Some(CompletionItemKind::FIELD),
+                    detail: Some(field.ty.clone()),
+                    ..Default::default()
+                });
+            }
+        }
+
+        Some(CompletionResponse::Array(items))
+    }
+}
+
+fn parse_params<T: DeserializeOwned>(
+    params: serde_json::Value,
+) -> Result<T, RequestError> {
+    serde_json::from_value(params).map_err(|e| (ErrorCode::InvalidParams, e.to_string()))
+}
+
+fn file_path(uri: &Url) -> Option<PathBuf> {
+    let path = uri.to_file_path().ok()?;
+    Some(path.canonicalize().unwrap_or(path))
+}
+
+fn document_at<'a>(
+    documents: &'a HashMap<Url, Document>,
+    params: &TextDocumentPositionParams,
+) -> Option<(&'a Document, Project, usize)> {
+    let document = documents.get(&params.text_document.uri)?;
+    let project = load_project(document).ok()?;
+    let offset = document.offset(params.position);
+    Some((document, project, offset))
+}
+
+fn load_project(document: &Document) -> Result<Project, Error> {
+    let dir = document.path.parent().unwrap_or(Path::new("."));
+    Project::load(dir)
+}
+
+fn update_index<'a>(
+    indexes: &'a mut HashMap<PathBuf, SourceIndex>,
+    project: &Project,
+) -> &'a SourceIndex {
+    let manifest_dir = project.manifest_dir();
+    let index = indexes.entry(manifest_dir.to_owned()).or_default();
+    index.update(manifest_dir);
+    index
+}
+
+/// Compile the template saved in the file, and report the errors
+fn check(document: &Document) -> Vec<Diagnostic> {
+    let result = load_project(document).and_then(|project| {
+        let compiler = project.compiler_for(&document.path);
+        let (tsource, _) = compiler.resolve_file(&document.path)?;
+        compiler.compile_file_to_string(&document.path, tsource)
+    });
+
+    match result {
+        Ok(_) => Vec::new(),
+        Err(e) => std::iter::once(&e)
+            .chain(e.related())
+            .map(|r| diagnostic(&document.path, r, &e))
+            .collect(),
+    }
+}
+
+/// Errors in the other templates (e.g. included templates) are reported at the
+/// beginning of the template, with the locations of the errors
+fn diagnostic(path: &Path, e: &Error, primary: &Error) -> Diagnostic {
+    let mut message = e.kind().to_string();
+    for cause in e.iter().skip(1) {
+        write!(message, "\ncaused by: {}", cause).unwrap();
+    }
+    for note in e.notes() {
+        write!(message, "\nnote: {}", note).unwrap();
+    }
+    if let Some(help) = e.help() {
+        write!(message, "\nhelp: {}", help).unwrap();
+    }
+
+    // offsets are based on the contents which were compiled
+    let file = e.source_file().or_else(|| primary.source_file());
+    let range = match (file, e.offset()) {
+        (Some(file), Some(offset)) => fs::read_to_string(file).map_or_else(
+            |_| Range::default(),
+            |content| {
+                Document::new(file.to_owned(), content)
+                    .range(offset, e.length().unwrap_or(0))
+            },
+        ),
+        _ => Range::default(),
+    };
+
+    let mut diagnostic = Diagnostic {
+        severity: Some(DiagnosticSeverity::ERROR),
+        source: Some("sailfish".to_owned()),
+        message,
+        ..Default::default()
+    };
+    match file.and_then(|f| Url::from_file_path(f).ok()) {
+        Some(uri) if file != Some(path) => {
+            diagnostic.related_information = Some(vec![DiagnosticRelatedInformation {
+                location: Location::new(uri, range),
+                message: "error occurred here".to_owned(),
+            }]);
+        }
+        _ => diagnostic.range = range,
+    }
+    diagnostic
+}
+
+/// Argument of the `include!` macro at the offset
+fn include_arg_at(code: &str, offset: usize) -> Option<String> {
+    code.match_indices("include!").find_map(|(start, name)| {
+        if code[..start].ends_with(|c: char| c.is_alphanumeric() || c == '_') {
+            return None;
+        }
+
+        let rest = code[start + name.len()..].trim_start();
+        let rest = rest.strip_prefix(['(', '[', '{'])?.trim_start();
+        let literal_start = code.len() - rest.len();
+        let literal_end = literal_start + string_literal_len(rest)?;
+        if !(start..=literal_end).contains(&offset) {
+            return None;
+        }
+
+        let literal = syn::parse_str::<LitStr>(&code[literal_start..literal_end]);
+        literal.ok().map(|l| l.value())
+    })
+}
+
+fn string_literal_len(s: &str) -> Option<usize> {
+    let mut chars = s.char_indices();
+    if chars.next()?.1 != '"' {
+        return None;
+    }
+
+    let mut escaped = false;
+    for (i, c) in chars {
+        match c {
+            '\\' if !escaped => escaped = true,
+            '"' if !escaped => return Some(i + 1),
+            _ => escaped = false,
+        }
+    }
+    None
+}
+
+/// Template file specified by the argument of `include!` macro or the directives
+///
+/// Relative paths are resolved from the directory of the template in the same way
+/// as the compiler.
+fn include_path(template: &Path, arg: &str, project: &Project) -> PathBuf {
+    match arg.strip_prefix('/') {
+        Some(path) => project.manifest_dir().join(path),
+        None => template.parent().unwrap_or(Path::new("")).join(arg),
+    }
+}
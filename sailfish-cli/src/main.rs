@@ -0,0 +1,241 @@
+//! Command line tool to check and inspect sailfish templates
+
+mod lsp;
+mod output;
+mod project;
+
+use sailfish_compiler::Error;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use crate::output::{Format, Outcome};
+use crate::project::Project;
+
+const USAGE: &str = "\
+Usage: sailfish <COMMAND> [OPTIONS] [PATHS]...
+
+Commands:
+  check   Check the templates for errors
+  expand  Print the Rust code generated from the templates
+  deps    Print the templates included by each template
+  fmt     Format the templates
+  lsp     Run the language server, which communicates over stdin and stdout
+
+Arguments:
+  [PATHS]...  Template files or directories. Paths which do not exist are
+              searched in the template directories. All `.stpl` files in the
+              template directories are used by default.
+
+Options:
+  -C, --dir <DIR>        Directory of the crate (default: current directory)
+      --format <FORMAT>  Output format: human or json (default: human)
+      --check            Do not write the formatted templates, and fail if any
+                         template is not formatted (`fmt` command)
+  -h, --help             Print help
+  -V, --version          Print version
+";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Command {
+    Check,
+    Expand,
+    Deps,
+    Fmt,
+}
+
+impl Command {
+    fn parse(name: &str) -> Option<Command> {
+        match name {
+            "check" => Some(Command::Check),
+            "expand" => Some(Command::Expand),
+            "deps" => Some(Command::Deps),
+            "fmt" => Some(Command::Fmt),
+            _ => None,
+        }
+    }
+
+    fn run(self, project: &Project, path: PathBuf, check: bool) -> Outcome {
+        let compiler = project.compiler_for(&path);
+        let mut outcome = Outcome {
+            path,
+            code: None,
+            deps: None,
+            changed: None,
+            error: None,
+        };
+        let path = &outcome.path;
+
+        let result = if self == Command::Fmt {
+            compiler.format_file(path).and_then(|formatted| {
+                let changed = fs::read_to_string(path)? != formatted;
+                if changed && !check {
+                    fs::write(path, formatted)?;
+                }
+                outcome.changed = Some(changed);
+                Ok(())
+            })
+        } else {
+            compiler.resolve_file(path).and_then(|(tsource, report)| {
+                let code = compiler.compile_file_to_string(path, tsource)?;
+                outcome.code = (self == Command::Expand).then_some(code);
+                outcome.deps = (self == Command::Deps).then_some(report.deps);
+                Ok(())
+            })
+        };
+
+        outcome.error = result.err();
+        outcome
+    }
+}
+
+struct Options {
+    command: Command,
+    dir: Option<PathBuf>,
+    format: Format,
+    check: bool,
+    paths: Vec<PathBuf>,
+}
+
+enum Args {
+    Run(Options),
+    Lsp,
+    Help,
+    Version,
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Args, String> {
+    let command = match args.next().as_deref() {
+        Some("-h" | "--help" | "help") => return Ok(Args::Help),
+        Some("-V" | "--version") => return Ok(Args::Version),
+        Some("lsp") => {
+            // `--stdio` is passed by some language clients
+            return match args.find(|arg| arg != "--stdio") {
+                Some(arg) => Err(format!("unknown option `{}`", arg)),
+                None => Ok(Args::Lsp),
+            };
+        }
+        Some(name) => {
+            Command::parse(name).ok_or_else(|| format!("unknown command `{}`", name))?
+        }
+        None => return Err("no command specified".to_owned()),
+    };
+
+    let mut options = Options {
+        command,
+        dir: None,
+        format: Format::Human,
+        check: false,
+        paths: Vec::new(),
+    };
+
+    while let Some(arg) = args.next() {
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => {
+                (name.to_owned(), Some(value.to_owned()))
+            }
+            _ => (arg.clone(), None),
+        };
+        let mut value = || {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("option `{}` requires a value", name))
+        };
+
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Args::Help),
+            "-V" | "--version" => return Ok(Args::Version),
+            "-C" | "--dir" => options.dir = Some(PathBuf::from(value()?)),
+            "--format" => {
+                let format = value()?;
+                options.format = Format::parse(&format)
+                    .ok_or_else(|| format!("unknown output format `{}`", format))?;
+            }
+            "--check" if command == Command::Fmt => options.check = true,
+            "--" => {
+                options.paths.extend(args.by_ref().map(PathBuf::from));
+            }
+            _ if name.starts_with('-') && name != "-" => {
+                return Err(format!("unknown option `{}`", name));
+            }
+            _ => options.paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    if options.command == Command::Expand && options.paths.is_empty() {
+        return Err("`expand` command requires the template paths".to_owned());
+    }
+
+    Ok(Args::Run(options))
+}
+
+fn run(options: Options) -> Result<bool, Error> {
+    let dir = match options.dir {
+        Some(dir) => dir,
+        None => env::current_dir()?,
+    };
+    let project = Project::load(&dir)?;
+    let templates = project.templates(&options.paths)?;
+
+    let outcomes: Vec<Outcome> = templates
+        .into_iter()
+        .map(|path| options.command.run(&project, path, options.check))
+        .collect();
+    let success = outcomes
+        .iter()
+        .all(|o| o.error.is_none() && !(options.check && o.changed == Some(true)));
+
+    match options.format {
+        Format::Json => output::print_json(&outcomes, success)?,
+        Format::Human => {
+            for outcome in &outcomes {
+                outcome.print_human();
+            }
+            match options.command {
+                Command::Check => output::print_summary(&outcomes),
+                Command::Fmt if options.check => output::print_unformatted(&outcomes),
+                _ => {}
+            }
+        }
+    }
+
+    Ok(success)
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(Args::Run(options)) => options,
+        Ok(Args::Lsp) => {
+            return match lsp::run() {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(e) => {
+                    output::print_error(&e);
+                    ExitCode::from(2)
+                }
+            };
+        }
+        Ok(Args::Help) => {
+            print!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Ok(Args::Version) => {
+            println!("sailfish {}", env!("CARGO_PKG_VERSION"));
+            return ExitCode::SUCCESS;
+        }
+        Err(msg) => {
+            eprintln!("error: {}\n\n{}", msg, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    match run(options) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            output::print_error(&e);
+            ExitCode::from(2)
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use sailfish_compiler::Error;
+use serde::Serialize;
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Human,
+    Json,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Option<Format> {
+        match name {
+            "human" => Some(Format::Human),
+            "json" => Some(Format::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Result of the command for a template file
+pub struct Outcome {
+    pub path: PathBuf,
+    /// Generated Rust code (`expand` command)
+    pub code: Option<String>,
+    /// Templates included by the template (`deps` command)
+    pub deps: Option<Vec<PathBuf>>,
+    /// Whether the template was not formatted (`fmt` command)
+    pub changed: Option<bool>,
+    pub error: Option<Error>,
+}
+
+impl Outcome {
+    pub fn print_human(&self) {
+        if let Some(ref e) = self.error {
+            print_error(e);
+            eprintln!();
+        }
+
+        if let Some(ref code) = self.code {
+            print!("{}", code);
+        }
+
+        if let Some(ref deps) = self.deps {
+            println!("{}", display_path(&self.path));
+            for dep in deps {
+                println!("    {}", display_path(dep));
+            }
+        }
+    }
+}
+
+pub fn print_summary(outcomes: &[Outcome]) {
+    let failed = outcomes.iter().filter(|o| o.error.is_some()).count();
+    let plural = if outcomes.len() == 1 { "" } else { "s" };
+    if failed == 0 {
+        eprintln!("Checked {} template{}", outcomes.len(), plural);
+    } else {
+        eprintln!(
+            "Checked {} template{}: {} failed",
+            outcomes.len(),
+            plural,
+            failed
+        );
+    }
+}
+
+pub fn print_unformatted(outcomes: &[Outcome]) {
+    for outcome in outcomes.iter().filter(|o| o.changed == Some(true)) {
+        println!("{}", display_path(&outcome.path));
+    }
+}
+
+pub fn print_error(e: &Error) {
+    let color = io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none();
+    if color {
+        eprint!("\x1b[1;31merror\x1b[0m: {}", e.report().color(true));
+    } else {
+        eprint!("error: {}", e);
+    }
+}
+
+#[derive(Serialize)]
+struct JsonOutput<'a> {
+    success: bool,
+    templates: Vec<JsonTemplate<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonTemplate<'a> {
+    path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    deps: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    changed: Option<bool>,
+    errors: Vec<JsonError>,
+}
+
+#[derive(Serialize)]
+struct JsonError {
+    message: String,
+    causes: Vec<String>,
+    file: Option<String>,
+    line: Option<usize>,
+    column: Option<usize>,
+    offset: Option<usize>,
+    length: Option<usize>,
+    notes: Vec<String>,
+    help: Option<String>,
+}
+
+impl JsonError {
+    // related errors without the template file belong to the file of `primary`
+    fn new(e: &Error, primary: &Error) -> Self {
+        let file = e.source_file().or_else(|| primary.source_file());
+        let (line, column) = e.line_column().unzip();
+        Self {
+            message: e.kind().to_string(),
+            causes: e.iter().skip(1).map(|k| k.to_string()).collect(),
+            file: file.map(display_path),
+            line,
+            column,
+            offset: e.offset(),
+            length: e.length(),
+            notes: e.notes().to_vec(),
+            help: e.help().map(str::to_owned),
+        }
+    }
+}
+
+pub fn print_json(outcomes: &[Outcome], success: bool) -> io::Result<()> {
+    let output = JsonOutput {
+        success,
+        templates: outcomes
+            .iter()
+            .map(|o| JsonTemplate {
+                path: display_path(&o.path),
+                code: o.code.as_deref(),
+                deps: o
+                    .deps
+                    .as_ref()
+                    .map(|deps| deps.iter().map(|p| display_path(p)).collect()),
+                changed: o.changed,
+                errors: o
+                    .error
+                    .iter()
+                    .flat_map(|e| {
+                        std::iter::once(e)
+                            .chain(e.related())
+                            .map(move |r| JsonError::new(r, e))
+                    })
+                    .collect(),
+            })
+            .collect(),
+    };
+
+    let mut stdout = io::stdout().lock();
+    serde_json::to_writer_pretty(&mut stdout, &output)?;
+    writeln!(stdout)
+}
+
+/// Path relative to the current directory if the file is inside it
+fn display_path(path: &Path) -> String {
+    let relative = env::current_dir()
+        .ok()
+        .and_then(|cwd| cwd.canonicalize().ok())
+        .and_then(|cwd| path.strip_prefix(cwd).ok().map(Path::to_owned));
+    relative.as_deref().unwrap_or(path).display().to_string()
+}
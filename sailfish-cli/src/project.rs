@@ -0,0 +1,132 @@
+use sailfish_compiler::{Compiler, Config, Error};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Crate which contains the templates
+pub struct Project {
+    manifest_dir: PathBuf,
+    config: Config,
+}
+
+impl Project {
+    /// Read the configuration of the crate in `dir`
+    ///
+    /// The configuration files are searched from the directory which contains
+    /// `Cargo.toml`, in the same way as the derive macros.
+    pub fn load(dir: &Path) -> Result<Self, Error> {
+        let dir = dir
+            .canonicalize()
+            .map_err(|e| Error::from(format!("Failed to open {:?}: {}", dir, e)))?;
+        let manifest_dir = dir
+            .ancestors()
+            .find(|p| p.join("Cargo.toml").is_file())
+            .unwrap_or(&dir)
+            .to_owned();
+
+        let mut config = Config::search_file_and_read(&manifest_dir)?;
+
+        // `templates` directory is searched after the configured directories
+        let fallback = manifest_dir.join("templates");
+        if fallback.is_dir() && !config.template_dirs.contains(&fallback) {
+            config.template_dirs.insert(0, fallback);
+        }
+
+        Ok(Self {
+            manifest_dir,
+            config,
+        })
+    }
+
+    /// Directory which contains `Cargo.toml`
+    pub fn manifest_dir(&self) -> &Path {
+        &self.manifest_dir
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Compiler for the template file, which uses the escaping mode for the file
+    pub fn compiler_for(&self, template: &Path) -> Compiler {
+        let mut config = self.config.clone();
+        if let Some(escape) = config.escape_for(template) {
+            config.escape = escape.clone();
+        }
+        Compiler::with_config(config)
+    }
+
+    /// Template files specified by `paths`
+    ///
+    /// Directories are searched recursively for `.stpl` files. If `paths` is
+    /// empty, all the templates in the template directories are returned.
+    pub fn templates(&self, paths: &[PathBuf]) -> Result<Vec<PathBuf>, Error> {
+        let mut templates = Vec::new();
+        if paths.is_empty() {
+            for dir in &self.config.template_dirs {
+                if dir.is_dir() {
+                    collect_templates(dir, &mut templates)?;
+                }
+            }
+        }
+
+        for path in paths {
+            let path = self.resolve(path).ok_or_else(|| {
+                Error::from(format!("Template file {:?} not found", path))
+            })?;
+            if path.is_dir() {
+                collect_templates(&path, &mut templates)?;
+            } else {
+                templates.push(path);
+            }
+        }
+
+        let mut templates = templates
+            .into_iter()
+            .map(|p| p.canonicalize().unwrap_or(p))
+            .collect::<Vec<_>>();
+        templates.sort();
+        templates.dedup();
+        Ok(templates)
+    }
+
+    /// Template file specified by the `path` option of the derive macros
+    pub fn resolve_template(&self, path: &Path) -> Option<PathBuf> {
+        self.config
+            .template_dirs
+            .iter()
+            .rev()
+            .map(|dir| dir.join(path))
+            .find(|p| p.is_file())
+    }
+
+    // same as the `path` option of the derive macros if `path` does not exist
+    fn resolve(&self, path: &Path) -> Option<PathBuf> {
+        if path.exists() {
+            return Some(path.to_owned());
+        }
+        self.resolve_template(path)
+    }
+}
+
+fn collect_templates(dir: &Path, templates: &mut Vec<PathBuf>) -> Result<(), Error> {
+    let entries = fs::read_dir(dir)
+        .map_err(|e| Error::from(format!("Failed to read {:?}: {}", dir, e)))?;
+
+    for entry in entries {
+        let path = entry?.path();
+        let hidden = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if hidden {
+            continue;
+        }
+
+        if path.is_dir() {
+            collect_templates(&path, templates)?;
+        } else if path.extension().is_some_and(|ext| ext == "stpl") {
+            templates.push(path);
+        }
+    }
+
+    Ok(())
+}
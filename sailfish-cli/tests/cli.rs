@@ -0,0 +1,386 @@
+use serde_json::{Value, json};
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdout, Command, Output, Stdio};
+
+/// Crate directory with the template files, which is removed on drop
+struct TempCrate(PathBuf);
+
+impl TempCrate {
+    fn new(name: &str, templates: &[(&str, &str)]) -> Self {
+        let dir = std::env::temp_dir().join(format!(
+            "sailfish-cli-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("templates")).unwrap();
+        fs::write(dir.join("Cargo.toml"), "[package]\nname = \"app\"\n").unwrap();
+        for (path, content) in templates {
+            let path = dir.join("templates").join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        Self(dir)
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_sailfish"))
+            .args(args)
+            .current_dir(&self.0)
+            .env("NO_COLOR", "1")
+            .output()
+            .unwrap()
+    }
+
+    fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempCrate {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).unwrap()
+}
+
+#[test]
+fn check() {
+    let krate = TempCrate::new(
+        "check",
+        &[
+            ("good.stpl", "<%= 1 %>"),
+            ("bad.stpl", "<% if x { %>\n<%= a. %>\n<% } %>\n<%- foo"),
+            ("README.md", "<%"),
+        ],
+    );
+
+    let output = krate.run(&["check", "good.stpl"]);
+    assert!(output.status.success());
+    assert_eq!(stderr(&output), "Checked 1 template\n");
+
+    let output = krate.run(&["check"]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = stderr(&output);
+    assert!(stderr.contains("bad.stpl:2:5"), "{}", stderr);
+    assert!(
+        stderr.ends_with("Checked 2 templates: 1 failed\n"),
+        "{}",
+        stderr
+    );
+
+    let output = krate.run(&["check", "--format", "json"]);
+    let json: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(json["success"], false);
+    let templates = json["templates"].as_array().unwrap();
+    assert_eq!(templates[0]["path"], "templates/bad.stpl");
+    assert_eq!(templates[1]["path"], "templates/good.stpl");
+    assert_eq!(templates[1]["errors"].as_array().unwrap().len(), 0);
+
+    let errors = templates[0]["errors"].as_array().unwrap();
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0]["file"], "templates/bad.stpl");
+    assert_eq!(
+        (&errors[0]["line"], &errors[0]["column"]),
+        (&2.into(), &5.into())
+    );
+    assert_eq!(
+        errors[1]["message"],
+        "Parse error (Unterminated code block)"
+    );
+    assert_eq!(
+        (&errors[1]["line"], &errors[1]["length"]),
+        (&4.into(), &2.into())
+    );
+    assert_eq!(errors[1]["help"], "close the tag with `%>`");
+}
+
+#[test]
+fn expand() {
+    let krate = TempCrate::new("expand", &[("hello.stpl", "Hello, <%= name %>!")]);
+
+    let output = krate.run(&["expand", "hello.stpl"]);
+    assert!(output.status.success());
+    let code = stdout(&output);
+    assert!(code.starts_with("// Template compiled from: "), "{}", code);
+    assert!(code.contains("render_escaped!(__sf_buf, name"), "{}", code);
+
+    let output = krate.run(&["expand"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).starts_with("error: `expand` command requires"));
+
+    let output = krate.run(&["expand", "missing.stpl"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        stderr(&output),
+        "error: Template file \"missing.stpl\" not found\n"
+    );
+}
+
+#[test]
+fn deps() {
+    let krate = TempCrate::new(
+        "deps",
+        &[
+            (
+                "page.stpl",
+                "<%@ extends \"layouts/base.stpl\" %>\
+                 <%@ block body %><% include!(\"nav.stpl\"); %><%@ endblock %>",
+            ),
+            ("layouts/base.stpl", "<%@ block body %><%@ endblock %>"),
+            ("nav.stpl", "<nav></nav>"),
+        ],
+    );
+
+    let output = krate.run(&["deps", "page.stpl", "nav.stpl"]);
+    assert!(output.status.success());
+    assert_eq!(
+        stdout(&output),
+        "templates/nav.stpl\n\
+         templates/page.stpl\n    templates/nav.stpl\n    templates/layouts/base.stpl\n"
+    );
+
+    let dir = krate.path().to_str().unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_sailfish"))
+        .args(["deps", "--format=json", "-C", dir, "nav.stpl"])
+        .output()
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(json["templates"][0]["deps"], serde_json::json!([]));
+}
+
+#[test]
+fn fmt() {
+    let krate = TempCrate::new(
+        "fmt",
+        &[
+            ("good.stpl", "<ul>\n  <li><%= a %></li>\n</ul>\n"),
+            (
+                "bad.stpl",
+                "<ul>\n<% for x in xs {%>\n<li><%=x%></li>\n<%}%>\n</ul>\n",
+            ),
+        ],
+    );
+    let formatted =
+        "<ul>\n  <% for x in xs { %>\n    <li><%= x %></li>\n  <% } %>\n</ul>\n";
+
+    let output = krate.run(&["fmt", "--check"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stdout(&output), "templates/bad.stpl\n");
+    let bad = krate.path().join("templates/bad.stpl");
+    assert_ne!(fs::read_to_string(&bad).unwrap(), formatted);
+
+    let output = krate.run(&["fmt"]);
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(&bad).unwrap(), formatted);
+
+    let output = krate.run(&["fmt", "--check", "--format", "json"]);
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(json["templates"][0]["changed"], false);
+
+    let output = krate.run(&["check", "--check"]);
+    assert_eq!(output.status.code(), Some(2));
+
+    // templates are formatted in the same way regardless of the escaping mode
+    fs::write(
+        krate.path().join("sailfish.toml"),
+        "[escape_patterns]\n\"*.xml.stpl\" = \"xml\"\n",
+    )
+    .unwrap();
+    let feed = krate.path().join("templates/feed.xml.stpl");
+    fs::write(
+        &feed,
+        "<feed>\n<% for x in xs { %>\n<entry><%= x %></entry>  \n<% } %>\n</feed>\n",
+    )
+    .unwrap();
+    let output = krate.run(&["fmt", "feed.xml.stpl"]);
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read_to_string(&feed).unwrap(),
+        "<feed>\n  <% for x in xs { %>\n    <entry><%= x %></entry>  \n  <% } %>\n</feed>\n"
+    );
+}
+
+/// Client which talks to `sailfish lsp` over stdin and stdout
+struct LspClient {
+    child: Child,
+    stdout: BufReader<ChildStdout>,
+    next_id: u64,
+}
+
+impl LspClient {
+    fn new(dir: &Path) -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_sailfish"))
+            .arg("lsp")
+            .current_dir(dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        Self {
+            child,
+            stdout,
+            next_id: 0,
+        }
+    }
+
+    fn send(&mut self, message: Value) {
+        let body = message.to_string();
+        let stdin = self.child.stdin.as_mut().unwrap();
+        write!(stdin, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+        stdin.flush().unwrap();
+    }
+
+    fn receive(&mut self) -> Value {
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            self.stdout.read_line(&mut line).unwrap();
+            match line.trim_end().split_once(": ") {
+                Some(("Content-Length", value)) => length = value.parse().unwrap(),
+                Some(_) => {}
+                None => break,
+            }
+        }
+
+        let mut body = vec![0; length];
+        self.stdout.read_exact(&mut body).unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    fn request(&mut self, method: &str, params: Value) -> Value {
+        self.next_id += 1;
+        let id = self.next_id;
+        self.send(
+            json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params}),
+        );
+        loop {
+            let message = self.receive();
+            if message["id"] == id {
+                return message["result"].clone();
+            }
+        }
+    }
+
+    fn notify(&mut self, method: &str, params: Value) {
+        self.send(json!({"jsonrpc": "2.0", "method": method, "params": params}));
+    }
+
+    fn wait_notification(&mut self, method: &str) -> Value {
+        loop {
+            let message = self.receive();
+            if message["method"] == method {
+                return message["params"].clone();
+            }
+        }
+    }
+}
+
+#[test]
+fn lsp() {
+    let krate = TempCrate::new(
+        "lsp",
+        &[
+            (
+                "page.stpl",
+                "<%+ Header { title } %>\n<% include!(\"nav.stpl\"); %>\n\
+                 <%= title | upper %>\n",
+            ),
+            ("nav.stpl", "<nav></nav>"),
+            ("bad.stpl", "<%= a. %>"),
+        ],
+    );
+    fs::create_dir(krate.path().join("src")).unwrap();
+    fs::write(
+        krate.path().join("src/lib.rs"),
+        "#[derive(TemplateSimple)]\n#[template(path = \"page.stpl\")]\n\
+         struct Page {\n    title: String,\n    #[template(rename = \"items\")]\n    \
+         entries: Vec<String>,\n    #[template(skip)]\n    hidden: bool,\n}\n\n\
+         struct Header {\n    title: String,\n}\n",
+    )
+    .unwrap();
+
+    let dir = krate.path().canonicalize().unwrap();
+    let uri = |path: &str| format!("file://{}", dir.join(path).display());
+    let mut client = LspClient::new(&dir);
+
+    let result = client.request("initialize", json!({"capabilities": {}}));
+    assert_eq!(result["capabilities"]["definitionProvider"], true);
+    client.notify("initialized", json!({}));
+
+    // diagnostics are reported for the saved files, and the unfinished tag in the
+    // editor is used for the completion
+    for (path, editing) in [("bad.stpl", ""), ("page.stpl", "<%= ")] {
+        let text =
+            fs::read_to_string(dir.join("templates").join(path)).unwrap() + editing;
+        let document = json!({
+            "uri": uri(&format!("templates/{}", path)),
+            "languageId": "sailfish",
+            "version": 1,
+            "text": text
+        });
+        client.notify("textDocument/didOpen", json!({ "textDocument": document }));
+    }
+
+    let params = client.wait_notification("textDocument/publishDiagnostics");
+    assert_eq!(params["uri"], uri("templates/bad.stpl"));
+    let diagnostics = params["diagnostics"].as_array().unwrap();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(
+        diagnostics[0]["range"]["start"],
+        json!({"line": 0, "character": 4})
+    );
+
+    let params = client.wait_notification("textDocument/publishDiagnostics");
+    assert_eq!(params["diagnostics"], json!([]));
+
+    let position = |line: u32, character: u32| {
+        json!({
+            "textDocument": {"uri": uri("templates/page.stpl")},
+            "position": {"line": line, "character": character}
+        })
+    };
+
+    // go to definition
+    let result = client.request("textDocument/definition", position(1, 15));
+    assert_eq!(result["uri"], uri("templates/nav.stpl"));
+
+    let result = client.request("textDocument/definition", position(0, 6));
+    assert_eq!(result[0]["uri"], uri("src/lib.rs"));
+    assert_eq!(
+        result[0]["range"]["start"],
+        json!({"line": 10, "character": 7})
+    );
+
+    // hover
+    let result = client.request("textDocument/hover", position(2, 14));
+    let doc = result["contents"]["value"].as_str().unwrap();
+    assert!(doc.contains("uppercase"), "{}", doc);
+    let result = client.request("textDocument/hover", position(2, 6));
+    assert_eq!(result, Value::Null);
+
+    // completion
+    let result = client.request("textDocument/completion", position(3, 4));
+    let labels: Vec<&str> = result
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["label"].as_str().unwrap())
+        .collect();
+    assert_eq!(labels, ["title", "items"]);
+
+    client.request("shutdown", Value::Null);
+    client.notify("exit", Value::Null);
+    assert!(client.child.wait().unwrap().success());
+}
@@ -8,10 +8,13 @@ use syn::Block;
 use crate::analyzer::Analyzer;
 use crate::config::Config;
 use crate::error::*;
+use crate::formatter::Formatter;
 use crate::optimizer::Optimizer;
 use crate::parser::Parser;
 use crate::resolver::Resolver;
-use crate::translator::{TranslatedSource, Translator};
+use crate::translator::{TranslatedSource, Translator, locations_to_comments};
+#[cfg(feature = "procmacro")]
+use crate::translator::{strip_locations, template_token_marks};
 use crate::util::{read_to_string, rustfmt_block};
 
 #[derive(Default)]
@@ -23,6 +26,28 @@ pub struct CompilationReport {
     pub deps: Vec<PathBuf>,
 }
 
+/// Generated code which is parsed by the derive macros
+#[cfg(feature = "procmacro")]
+pub(crate) struct CompiledCode {
+    pub code: String,
+    /// Whether each token of `code` comes from the template code, in the
+    /// depth-first order of the token trees
+    pub template_tokens: Vec<bool>,
+}
+
+#[cfg(feature = "procmacro")]
+impl CompiledCode {
+    fn new(ast: Block) -> Self {
+        let tokens = strip_locations(ast.into_token_stream());
+        let mut template_tokens = Vec::new();
+        template_token_marks(tokens.clone(), &mut template_tokens);
+        Self {
+            code: tokens.to_string(),
+            template_tokens,
+        }
+    }
+}
+
 impl Compiler {
     pub fn new() -> Self {
         Self::default()
@@ -34,12 +59,36 @@ impl Compiler {
 
     fn translate_file_contents(&self, input: &Path) -> Result<TranslatedSource, Error> {
         let parser = Parser::new().delimiter(self.config.delimiter);
-        let translator = Translator::new().escape(self.config.escape);
+        let translator = Translator::new()
+            .escape(self.config.escape.clone())
+            .filters(self.config.filters.clone())
+            .path(self.template_name(input));
         let content = read_to_string(input)
             .chain_err(|| format!("Failed to open template file: {:?}", input))?;
 
         let stream = parser.parse(&content);
-        translator.translate(stream)
+        translator.translate(stream).map_err(|mut e| {
+            e.set_source_file(input, &content);
+            e
+        })
+    }
+
+    /// Path to the template file which is reported in the rendering errors
+    ///
+    /// The path is relative to the crate directory, or the template directory if
+    /// the file is outside of the crate.
+    fn template_name(&self, input: &Path) -> String {
+        let manifest_dir = std::env::var_os("CARGO_MANIFEST_DIR").map(PathBuf::from);
+        manifest_dir
+            .iter()
+            .chain(&self.config.template_dirs)
+            .find_map(|dir| {
+                let dir = dir.canonicalize().unwrap_or_else(|_| dir.clone());
+                input.strip_prefix(dir).ok()
+            })
+            .unwrap_or(input)
+            .to_string_lossy()
+            .into_owned()
     }
 
     pub fn resolve_file(
@@ -65,70 +114,132 @@ impl Compiler {
         tsource: TranslatedSource,
         output: &Path,
     ) -> Result<(), Error> {
-        let analyzer = Analyzer::new();
-        let optimizer = Optimizer::new()
-            .rm_whitespace(self.config.rm_whitespace)
-            .rm_newline(self.config.rm_newline);
-
-        let compile_file = |mut tsource: TranslatedSource,
-                            output: &Path|
-         -> Result<(), Error> {
-            analyzer.analyze(&mut tsource.ast)?;
-            optimizer.optimize(&mut tsource.ast);
+        let code = self.compile_file_to_string(input, tsource)?;
 
+        let write_file = || -> Result<(), Error> {
             if let Some(parent) = output.parent() {
                 fs::create_dir_all(parent)
                     .chain_err(|| format!("Failed to save artifacts in {:?}", parent))?;
             }
 
-            let string = tsource.ast.into_token_stream().to_string();
-
             let mut f = fs::File::create(output)
                 .chain_err(|| format!("Failed to create artifact: {:?}", output))?;
-            writeln!(f, "// Template compiled from: {}", input.display())
-                .chain_err(|| format!("Failed to write artifact into {:?}", output))?;
-            writeln!(f, "{}", rustfmt_block(&string).unwrap_or(string))
+            write!(f, "{}", code)
                 .chain_err(|| format!("Failed to write artifact into {:?}", output))?;
-            drop(f);
+            Ok(())
+        };
+
+        write_file()
+            .chain_err(|| "Failed to compile template.")
+            .map_err(|mut e| {
+                e.source_file = Some(input.to_owned());
+                e
+            })
+    }
 
+    /// Same as `compile_file`, but returns the generated code instead of writing it
+    /// into the file
+    pub fn compile_file_to_string(
+        &self,
+        input: &Path,
+        tsource: TranslatedSource,
+    ) -> Result<String, Error> {
+        let ast = self.optimize_file(input, tsource)?;
+        let string = ast.into_token_stream().to_string();
+        let code = rustfmt_block(&string).unwrap_or(string);
+        Ok(format!(
+            "// Template compiled from: {}\n{}",
+            input.display(),
+            locations_to_comments(&code)
+        ))
+    }
+
+    fn optimize_file(
+        &self,
+        input: &Path,
+        mut tsource: TranslatedSource,
+    ) -> Result<Block, Error> {
+        let analyzer = Analyzer::new();
+        let optimizer = Optimizer::new()
+            .rm_whitespace(self.config.rm_whitespace)
+            .rm_newline(self.config.rm_newline);
+
+        let mut compile = || -> Result<(), Error> {
+            analyzer.analyze(&mut tsource.ast)?;
+            optimizer.optimize(&mut tsource.ast);
             Ok(())
         };
 
-        compile_file(tsource, output)
+        compile()
             .chain_err(|| "Failed to compile template.")
             .map_err(|mut e| {
                 e.source = fs::read_to_string(input).ok();
                 e.source_file = Some(input.to_owned());
                 e
-            })
+            })?;
+        Ok(tsource.ast)
+    }
+
+    /// Format the template file with [`Formatter`]
+    pub fn format_file(&self, input: &Path) -> Result<String, Error> {
+        let formatter = Formatter::new().delimiter(self.config.delimiter);
+        let content = fs::read_to_string(input)
+            .chain_err(|| format!("Failed to open template file: {:?}", input))?;
+
+        formatter.format(&content).map_err(|mut e| {
+            e.set_source_file(input, &content);
+            e
+        })
     }
 
     pub fn compile_str(&self, input: &str) -> Result<String, Error> {
+        let ast = self.compile_inline(input, None)?;
+        Ok(ast.into_token_stream().to_string())
+    }
+
+    /// Same as `compile_str`, but the rendering errors are reported with `name`
+    #[cfg(feature = "procmacro")]
+    pub(crate) fn compile_named_str(
+        &self,
+        input: &str,
+        name: &str,
+    ) -> Result<CompiledCode, Error> {
+        self.compile_inline(input, Some(name))
+            .map(CompiledCode::new)
+    }
+
+    fn compile_inline(&self, input: &str, name: Option<&str>) -> Result<Block, Error> {
         let dummy_path = Path::new(env!("CARGO_MANIFEST_DIR"));
 
         let include_handler = Arc::new(|_: &Path| -> Result<Block, Error> {
             Err(make_error!(
                 ErrorKind::AnalyzeError(
-                    "include! macro is not allowed in inline template".to_owned()
+                    "include! macro, extends and import directives are not allowed in inline template"
+                        .to_owned()
                 ),
                 source = input.to_owned()
             ))
         });
 
         let parser = Parser::new().delimiter(self.config.delimiter);
-        let translator = Translator::new().escape(self.config.escape);
+        let mut translator = Translator::new()
+            .escape(self.config.escape.clone())
+            .filters(self.config.filters.clone());
+        if let Some(name) = name {
+            translator = translator.path(name.to_owned());
+        }
         let resolver = Resolver::new().include_handler(include_handler);
         let optimizer = Optimizer::new()
             .rm_whitespace(self.config.rm_whitespace)
             .rm_newline(self.config.rm_newline);
 
-        let compile = || -> Result<String, Error> {
+        let compile = || -> Result<Block, Error> {
             let stream = parser.parse(input);
             let mut tsource = translator.translate(stream)?;
             resolver.resolve(dummy_path, &mut tsource.ast)?;
 
             optimizer.optimize(&mut tsource.ast);
-            Ok(tsource.ast.into_token_stream().to_string())
+            Ok(tsource.ast)
         };
 
         compile()
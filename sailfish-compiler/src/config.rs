@@ -1,12 +1,74 @@
 use std::path::{Path, PathBuf};
 
+/// Escaping strategy applied to the values rendered with `<%= %>` tag
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub enum EscapeMode {
+    /// HTML escaping with context detection (`"html"`)
+    #[default]
+    Html,
+    /// XML escaping (`"xml"`)
+    Xml,
+    /// escaping for JSON string literals (`"json"`)
+    Json,
+    /// no escaping (`"none"`)
+    None,
+    /// path to the user-defined type implementing `Escaper` trait
+    Custom(String),
+}
+
+impl EscapeMode {
+    /// Parse the value of `escape` option
+    ///
+    /// Values other than the builtin modes must be paths containing `::`
+    /// (e.g. `crate::Latex`).
+    pub fn parse(value: &str) -> Option<EscapeMode> {
+        match value {
+            "html" => Some(EscapeMode::Html),
+            "xml" => Some(EscapeMode::Xml),
+            "json" => Some(EscapeMode::Json),
+            "none" => Some(EscapeMode::None),
+            _ if value.contains("::") && syn::parse_str::<syn::Path>(value).is_ok() => {
+                Some(EscapeMode::Custom(value.to_owned()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Path to the type implementing `Escaper` trait, or `None` if the values are
+    /// not escaped
+    pub(crate) fn escaper(&self) -> Option<&str> {
+        match *self {
+            EscapeMode::Html => Some("__sf_rt::escape::Html"),
+            EscapeMode::Xml => Some("__sf_rt::escape::Xml"),
+            EscapeMode::Json => Some("__sf_rt::escape::Json"),
+            EscapeMode::None => None,
+            EscapeMode::Custom(ref path) => Some(path),
+        }
+    }
+}
+
+impl From<bool> for EscapeMode {
+    #[inline]
+    fn from(escape: bool) -> Self {
+        if escape {
+            EscapeMode::Html
+        } else {
+            EscapeMode::None
+        }
+    }
+}
+
 #[derive(Clone, Debug, Hash)]
 pub struct Config {
     pub delimiter: char,
-    pub escape: bool,
+    pub escape: EscapeMode,
+    /// Escaping modes for the template files whose names match the patterns
+    pub escape_patterns: Vec<(String, EscapeMode)>,
     pub rm_whitespace: bool,
     pub rm_newline: bool,
     pub template_dirs: Vec<PathBuf>,
+    /// Paths to the modules which contain user-defined filters
+    pub filters: Vec<String>,
     #[doc(hidden)]
     pub cache_dir: PathBuf,
     #[doc(hidden)]
@@ -17,8 +79,10 @@ impl Default for Config {
     fn default() -> Self {
         Self {
             template_dirs: Vec::new(),
+            filters: Vec::new(),
             delimiter: '%',
-            escape: true,
+            escape: EscapeMode::Html,
+            escape_patterns: Vec::new(),
             cache_dir: Path::new(env!("OUT_DIR")).join("cache"),
             rm_whitespace: false,
             rm_newline: false,
@@ -27,9 +91,39 @@ impl Default for Config {
     }
 }
 
+impl Config {
+    /// Escaping mode for the template file specified in `escape_patterns`
+    ///
+    /// If multiple patterns match the file name, the longest pattern is used.
+    pub fn escape_for(&self, path: &Path) -> Option<&EscapeMode> {
+        let name = path.file_name()?.to_str()?;
+        self.escape_patterns
+            .iter()
+            .filter(|(pattern, _)| matches_pattern(pattern, name))
+            .max_by_key(|(pattern, _)| pattern.len())
+            .map(|(_, mode)| mode)
+    }
+}
+
+/// Check if the file name matches the pattern, in which `*` matches any string
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == name,
+        Some((prefix, rest)) => {
+            let Some(name) = name.strip_prefix(prefix) else {
+                return false;
+            };
+            (0..=name.len())
+                .filter(|&i| name.is_char_boundary(i))
+                .any(|i| matches_pattern(rest, &name[i..]))
+        }
+    }
+}
+
 #[cfg(feature = "config")]
 mod imp {
     use serde::Deserialize;
+    use std::collections::BTreeMap;
     use std::fs;
 
     use super::*;
@@ -77,7 +171,25 @@ mod imp {
                     }
 
                     if let Some(escape) = config_file.escape {
-                        config.escape = escape;
+                        config.escape = escape.into_mode().map_err(|mut e| {
+                            e.source_file = Some(path.to_owned());
+                            e
+                        })?;
+                    }
+
+                    if let Some(escape_patterns) = config_file.escape_patterns {
+                        for (pattern, escape) in escape_patterns {
+                            let mode = escape.into_mode().map_err(|mut e| {
+                                e.source_file = Some(path.to_owned());
+                                e
+                            })?;
+                            config.escape_patterns.retain(|(p, _)| *p != pattern);
+                            config.escape_patterns.push((pattern, mode));
+                        }
+                    }
+
+                    if let Some(filters) = config_file.filters {
+                        config.filters.extend(filters.into_vec());
                     }
 
                     if let Some(optimizations) = config_file.optimizations {
@@ -105,12 +217,47 @@ mod imp {
         rm_newline: Option<bool>,
     }
 
+    #[derive(Clone, Deserialize, Debug)]
+    #[serde(untagged)]
+    enum Escape {
+        Enabled(bool),
+        Mode(String),
+    }
+
+    impl Escape {
+        fn into_mode(self) -> Result<EscapeMode, Error> {
+            match self {
+                Escape::Enabled(escape) => Ok(EscapeMode::from(escape)),
+                Escape::Mode(mode) => EscapeMode::parse(&mode)
+                    .ok_or_else(|| error(format!("Unknown escape mode: {:?}", mode))),
+            }
+        }
+    }
+
+    #[derive(Deserialize, Debug)]
+    #[serde(untagged)]
+    enum Filters {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    impl Filters {
+        fn into_vec(self) -> Vec<String> {
+            match self {
+                Filters::One(module) => vec![module],
+                Filters::Many(modules) => modules,
+            }
+        }
+    }
+
     #[derive(Deserialize, Debug)]
     #[serde(deny_unknown_fields)]
     struct ConfigFile {
         template_dirs: Option<Vec<String>>,
         delimiter: Option<char>,
-        escape: Option<bool>,
+        escape: Option<Escape>,
+        escape_patterns: Option<BTreeMap<String, Escape>>,
+        filters: Option<Filters>,
         optimizations: Option<Optimizations>,
     }
 
@@ -194,7 +341,8 @@ mod imp {
     #[cfg(test)]
     mod tests {
 
-        use crate::config::imp::expand_env_vars;
+        use crate::config::EscapeMode;
+        use crate::config::imp::{ConfigFile, expand_env_vars};
         use std::env;
 
         #[test]
@@ -235,5 +383,73 @@ mod imp {
             let output = expand_env_vars(input);
             assert!(output.is_err());
         }
+
+        #[test]
+        fn parses_filters() {
+            let config = ConfigFile::from_string("filters = \"crate::a\"").unwrap();
+            assert_eq!(config.filters.unwrap().into_vec(), ["crate::a"]);
+
+            let config =
+                ConfigFile::from_string("filters = [\"crate::a\", \"b\"]").unwrap();
+            assert_eq!(config.filters.unwrap().into_vec(), ["crate::a", "b"]);
+        }
+
+        #[test]
+        fn parses_escape_mode() {
+            let mode = |s: &str| {
+                ConfigFile::from_string(s)
+                    .unwrap()
+                    .escape
+                    .unwrap()
+                    .into_mode()
+            };
+            assert_eq!(mode("escape = false").unwrap(), EscapeMode::None);
+            assert_eq!(mode("escape = \"xml\"").unwrap(), EscapeMode::Xml);
+            assert_eq!(
+                mode("escape = \"crate::Latex\"").unwrap(),
+                EscapeMode::Custom("crate::Latex".to_owned())
+            );
+            assert!(mode("escape = \"htm\"").is_err());
+        }
+
+        #[test]
+        fn parses_escape_patterns() {
+            let config = ConfigFile::from_string(
+                "[escape_patterns]\n\"*.txt.stpl\" = \"none\"\n\"*.xml.stpl\" = \"xml\"",
+            )
+            .unwrap();
+            let patterns = config.escape_patterns.unwrap();
+            assert_eq!(patterns.len(), 2);
+            assert_eq!(
+                patterns["*.xml.stpl"].clone().into_mode().unwrap(),
+                EscapeMode::Xml
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_for() {
+        let config = Config {
+            escape_patterns: vec![
+                ("*.stpl".to_owned(), EscapeMode::Html),
+                ("*.txt.stpl".to_owned(), EscapeMode::None),
+                ("feed*.xml".to_owned(), EscapeMode::Xml),
+            ],
+            ..Config::default()
+        };
+
+        let escape_for = |path: &str| config.escape_for(Path::new(path)).cloned();
+        assert_eq!(
+            escape_for("templates/mail.txt.stpl"),
+            Some(EscapeMode::None)
+        );
+        assert_eq!(escape_for("templates/index.stpl"), Some(EscapeMode::Html));
+        assert_eq!(escape_for("feed.rss.xml"), Some(EscapeMode::Xml));
+        assert_eq!(escape_for("templates/txt.stpl.bak"), None);
     }
 }
@@ -0,0 +1,518 @@
+/// Context of HTML document where the value is rendered
+///
+/// Each variant corresponds to `sailfish::runtime::escape::Context`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EscapeContext {
+    Html,
+    Script,
+    ScriptAttr,
+    ScriptString,
+    Style,
+    Url,
+    UrlPart,
+}
+
+impl EscapeContext {
+    /// Name of the variant in `sailfish::runtime::escape::Context`
+    pub fn name(self) -> &'static str {
+        match self {
+            EscapeContext::Html => "Html",
+            EscapeContext::Script => "Script",
+            EscapeContext::ScriptAttr => "ScriptAttr",
+            EscapeContext::ScriptString => "ScriptString",
+            EscapeContext::Style => "Style",
+            EscapeContext::Url => "Url",
+            EscapeContext::UrlPart => "UrlPart",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Js {
+    // beginning of expression, where `/` starts a regular expression literal
+    Code,
+    // after operand, where `/` is a division operator
+    Operand,
+    Str(u8),
+    // after backslash inside string literal
+    StrEscape(u8),
+    LineComment,
+    BlockComment,
+    // regular expression literal, or its character class if `class` is true
+    Regex { class: bool },
+    // after backslash inside regular expression literal
+    RegexEscape { class: bool },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum State {
+    Text,
+    Comment,
+    // doctype, processing instruction or end tag
+    Markup,
+    TagName(String),
+    Tag(String),
+    AttrName {
+        tag: String,
+        name: String,
+    },
+    AfterAttrName {
+        tag: String,
+        name: String,
+    },
+    BeforeValue {
+        tag: String,
+        name: String,
+    },
+    Value {
+        tag: String,
+        name: String,
+        quote: Option<u8>,
+        started: bool,
+        js: Js,
+    },
+    Script(Js),
+    Style,
+}
+
+const URL_ATTRS: &[&str] = &[
+    "action",
+    "archive",
+    "background",
+    "cite",
+    "classid",
+    "codebase",
+    "data",
+    "formaction",
+    "href",
+    "longdesc",
+    "manifest",
+    "ping",
+    "poster",
+    "profile",
+    "src",
+    "srcset",
+    "usemap",
+    "xlink:href",
+];
+
+/// Track the HTML context through the template text
+///
+/// This is not a complete HTML parser. Text inside the branches of `if` or
+/// `match` statements is simply scanned in order.
+#[derive(Clone, Debug)]
+pub struct ContextTracker {
+    state: State,
+}
+
+impl ContextTracker {
+    #[inline]
+    pub fn new() -> Self {
+        Self { state: State::Text }
+    }
+
+    /// Context of the value rendered at the current position
+    pub fn context(&self) -> EscapeContext {
+        match self.state {
+            State::Value {
+                ref name,
+                started,
+                js,
+                ..
+            } => attr_context(name, started, js),
+            State::BeforeValue { ref name, .. } => attr_context(name, false, Js::Code),
+            State::Script(js) => js_context(js),
+            State::Style => EscapeContext::Style,
+            _ => EscapeContext::Html,
+        }
+    }
+
+    /// Whether the current position is inside the attribute value without quotes
+    pub fn is_unquoted(&self) -> bool {
+        matches!(
+            self.state,
+            State::BeforeValue { .. } | State::Value { quote: None, .. }
+        )
+    }
+
+    /// Notify that a value was rendered at the current position
+    pub fn value_rendered(&mut self) {
+        match self.state {
+            State::Value {
+                ref mut started,
+                ref mut js,
+                ..
+            } => {
+                *started = true;
+                *js = js.after_value();
+            }
+            State::Script(ref mut js) => *js = js.after_value(),
+            _ => {}
+        }
+    }
+
+    /// Update the context with the template text
+    pub fn feed(&mut self, text: &str) {
+        let bytes = text.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() {
+            i += self.step(&bytes[i..]);
+        }
+    }
+
+    // consume the first byte (or more) of `rest` and return the consumed length
+    fn step(&mut self, rest: &[u8]) -> usize {
+        let c = rest[0];
+        let state = std::mem::replace(&mut self.state, State::Text);
+        let (state, consumed) = match state {
+            State::Text => match c {
+                b'<' if rest.starts_with(b"<!--") => (State::Comment, 4),
+                b'<' if rest.get(1).is_some_and(|c| c.is_ascii_alphabetic()) => {
+                    (State::TagName(String::new()), 1)
+                }
+                b'<' if rest.get(1).is_some_and(|c| b"!?/".contains(c)) => {
+                    (State::Markup, 2)
+                }
+                _ => (State::Text, 1),
+            },
+            State::Comment if rest.starts_with(b"-->") => (State::Text, 3),
+            State::Comment => (State::Comment, 1),
+            State::Markup if c == b'>' => (State::Text, 1),
+            State::Markup => (State::Markup, 1),
+            State::TagName(mut tag) => match c {
+                b'>' => (content_state(&tag), 1),
+                c if c.is_ascii_whitespace() || c == b'/' => (State::Tag(tag), 1),
+                c => {
+                    tag.push(c.to_ascii_lowercase() as char);
+                    (State::TagName(tag), 1)
+                }
+            },
+            State::Tag(tag) => match c {
+                b'>' => (content_state(&tag), 1),
+                c if c.is_ascii_whitespace() || c == b'/' => (State::Tag(tag), 1),
+                c => (
+                    State::AttrName {
+                        tag,
+                        name: (c.to_ascii_lowercase() as char).to_string(),
+                    },
+                    1,
+                ),
+            },
+            State::AttrName { tag, mut name } => match c {
+                b'>' => (content_state(&tag), 1),
+                b'=' => (State::BeforeValue { tag, name }, 1),
+                b'/' => (State::Tag(tag), 1),
+                c if c.is_ascii_whitespace() => (State::AfterAttrName { tag, name }, 1),
+                c => {
+                    name.push(c.to_ascii_lowercase() as char);
+                    (State::AttrName { tag, name }, 1)
+                }
+            },
+            State::AfterAttrName { tag, name } => match c {
+                b'>' => (content_state(&tag), 1),
+                b'=' => (State::BeforeValue { tag, name }, 1),
+                c if c.is_ascii_whitespace() => (State::AfterAttrName { tag, name }, 1),
+                // attribute without value
+                _ => (State::Tag(tag), 0),
+            },
+            State::BeforeValue { tag, name } => match c {
+                b'>' => (content_state(&tag), 1),
+                c if c.is_ascii_whitespace() => (State::BeforeValue { tag, name }, 1),
+                b'"' | b'\'' => (
+                    State::Value {
+                        tag,
+                        name,
+                        quote: Some(c),
+                        started: false,
+                        js: Js::Code,
+                    },
+                    1,
+                ),
+                _ => (
+                    State::Value {
+                        tag,
+                        name,
+                        quote: None,
+                        started: false,
+                        js: Js::Code,
+                    },
+                    0,
+                ),
+            },
+            State::Value {
+                tag,
+                name,
+                quote,
+                js,
+                ..
+            } => match (quote, c) {
+                (Some(q), c) if c == q => (State::Tag(tag), 1),
+                (None, b'>') => (content_state(&tag), 1),
+                (None, c) if c.is_ascii_whitespace() => (State::Tag(tag), 1),
+                _ => {
+                    let (js, consumed) = if is_event_handler(&name) {
+                        step_js(js, rest)
+                    } else {
+                        (js, 1)
+                    };
+                    let state = State::Value {
+                        tag,
+                        name,
+                        quote,
+                        started: true,
+                        js,
+                    };
+                    (state, consumed)
+                }
+            },
+            // end tag closes the script even inside string literals
+            State::Script(_) if starts_with_end_tag(rest, b"script") => {
+                (State::Markup, 2)
+            }
+            State::Script(js) => {
+                let (js, consumed) = step_js(js, rest);
+                (State::Script(js), consumed)
+            }
+            State::Style if starts_with_end_tag(rest, b"style") => (State::Markup, 2),
+            State::Style => (State::Style, 1),
+        };
+
+        self.state = state;
+        consumed
+    }
+}
+
+impl Default for ContextTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn content_state(tag: &str) -> State {
+    match tag {
+        "script" => State::Script(Js::Code),
+        "style" => State::Style,
+        _ => State::Text,
+    }
+}
+
+fn starts_with_end_tag(rest: &[u8], tag: &[u8]) -> bool {
+    rest.starts_with(b"</")
+        && rest.len() >= tag.len() + 2
+        && rest[2..tag.len() + 2].eq_ignore_ascii_case(tag)
+}
+
+fn is_event_handler(name: &str) -> bool {
+    name.len() > 2 && name.starts_with("on")
+}
+
+fn attr_context(name: &str, started: bool, js: Js) -> EscapeContext {
+    if is_event_handler(name) {
+        // the quotes around the values must not close the attribute
+        match js_context(js) {
+            EscapeContext::Script => EscapeContext::ScriptAttr,
+            ctx => ctx,
+        }
+    } else if name == "style" {
+        EscapeContext::Style
+    } else if URL_ATTRS.contains(&name) {
+        if started {
+            EscapeContext::UrlPart
+        } else {
+            EscapeContext::Url
+        }
+    } else {
+        EscapeContext::Html
+    }
+}
+
+fn js_context(js: Js) -> EscapeContext {
+    match js {
+        Js::Code | Js::Operand => EscapeContext::Script,
+        _ => EscapeContext::ScriptString,
+    }
+}
+
+// keywords after which `/` starts a regular expression literal
+const JS_KEYWORDS: &[&[u8]] = &[
+    b"await",
+    b"case",
+    b"delete",
+    b"do",
+    b"else",
+    b"in",
+    b"instanceof",
+    b"new",
+    b"of",
+    b"return",
+    b"throw",
+    b"typeof",
+    b"void",
+    b"yield",
+];
+
+impl Js {
+    // state after the value rendered as a literal in the code
+    fn after_value(self) -> Js {
+        match self {
+            Js::Code => Js::Operand,
+            js => js,
+        }
+    }
+}
+
+fn is_js_word(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_' || c == b'$' || c >= 0x80
+}
+
+fn step_js(js: Js, rest: &[u8]) -> (Js, usize) {
+    let c = rest[0];
+    match js {
+        Js::Code | Js::Operand => match c {
+            b'"' | b'\'' | b'`' => (Js::Str(c), 1),
+            b'/' if rest.get(1) == Some(&b'/') => (Js::LineComment, 2),
+            b'/' if rest.get(1) == Some(&b'*') => (Js::BlockComment, 2),
+            b'/' if js == Js::Code => (Js::Regex { class: false }, 1),
+            c if is_js_word(c) => {
+                let len = rest.iter().take_while(|&&c| is_js_word(c)).count();
+                if JS_KEYWORDS.contains(&&rest[..len]) {
+                    (Js::Code, len)
+                } else {
+                    (Js::Operand, len)
+                }
+            }
+            b')' | b']' => (Js::Operand, 1),
+            c if c.is_ascii_whitespace() => (js, 1),
+            _ => (Js::Code, 1),
+        },
+        Js::Str(q) if c == q => (Js::Operand, 1),
+        Js::Str(q) if c == b'\\' => (Js::StrEscape(q), 1),
+        Js::Str(q) | Js::StrEscape(q) => (Js::Str(q), 1),
+        Js::LineComment if c == b'\n' => (Js::Code, 1),
+        Js::BlockComment if rest.starts_with(b"*/") => (Js::Code, 2),
+        Js::LineComment | Js::BlockComment => (js, 1),
+        Js::Regex { class } => match c {
+            b'\\' => (Js::RegexEscape { class }, 1),
+            b'[' => (Js::Regex { class: true }, 1),
+            b']' => (Js::Regex { class: false }, 1),
+            b'/' if !class => (Js::Operand, 1),
+            // regular expression literal cannot contain line breaks
+            b'\n' => (Js::Code, 1),
+            _ => (js, 1),
+        },
+        Js::RegexEscape { class } => (Js::Regex { class }, 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context_after(text: &str) -> EscapeContext {
+        let mut tracker = ContextTracker::new();
+        tracker.feed(text);
+        tracker.context()
+    }
+
+    #[test]
+    fn html() {
+        assert_eq!(context_after("<p class=\"a\">"), EscapeContext::Html);
+        assert_eq!(context_after("<div title=\""), EscapeContext::Html);
+        assert_eq!(context_after("<!-- <script> -->"), EscapeContext::Html);
+        assert_eq!(
+            context_after("<script>a = 1;</script><p>"),
+            EscapeContext::Html
+        );
+        assert_eq!(context_after("<style>a {}</STYLE >"), EscapeContext::Html);
+    }
+
+    #[test]
+    fn script() {
+        assert_eq!(context_after("<script>var a = "), EscapeContext::Script);
+        assert_eq!(
+            context_after("<script type=\"module\">var a = '"),
+            EscapeContext::ScriptString
+        );
+        assert_eq!(
+            context_after("<script>var a = \"\\\"</p>"),
+            EscapeContext::ScriptString
+        );
+        assert_eq!(
+            context_after("<script>var a = 'x'; // '\nvar b = "),
+            EscapeContext::Script
+        );
+        assert_eq!(
+            context_after("<button onclick=\"f('"),
+            EscapeContext::ScriptString
+        );
+        assert_eq!(
+            context_after("<button onclick='f("),
+            EscapeContext::ScriptAttr
+        );
+        assert_eq!(
+            context_after("<button onclick=\"f("),
+            EscapeContext::ScriptAttr
+        );
+    }
+
+    #[test]
+    fn regex() {
+        assert_eq!(
+            context_after("<script>var a = /'/.test(s) ? "),
+            EscapeContext::Script
+        );
+        assert_eq!(
+            context_after("<script>if (/[/'\\]]\\//.test(s)) f("),
+            EscapeContext::Script
+        );
+        assert_eq!(
+            context_after("<script>return /\"/g; var b = "),
+            EscapeContext::Script
+        );
+        assert_eq!(
+            context_after("<script>var a = b / 2, c = '"),
+            EscapeContext::ScriptString
+        );
+        assert_eq!(
+            context_after("<button onclick=\"f(x / 2, /'/, '"),
+            EscapeContext::ScriptString
+        );
+
+        let mut tracker = ContextTracker::new();
+        tracker.feed("<script>var a = ");
+        tracker.value_rendered();
+        tracker.feed(" / 2, b = '");
+        assert_eq!(tracker.context(), EscapeContext::ScriptString);
+    }
+
+    #[test]
+    fn unquoted() {
+        let mut tracker = ContextTracker::new();
+        tracker.feed("<button onclick=f(");
+        assert_eq!(tracker.context(), EscapeContext::ScriptAttr);
+        assert!(tracker.is_unquoted());
+        tracker.feed(") title=");
+        assert_eq!(tracker.context(), EscapeContext::Html);
+        assert!(tracker.is_unquoted());
+        tracker.feed("a class=\"");
+        assert!(!tracker.is_unquoted());
+        tracker.feed("b\">");
+        assert!(!tracker.is_unquoted());
+    }
+
+    #[test]
+    fn style_and_url() {
+        assert_eq!(context_after("<style>p { color: "), EscapeContext::Style);
+        assert_eq!(context_after("<p style=\"color: "), EscapeContext::Style);
+        assert_eq!(context_after("<a href=\""), EscapeContext::Url);
+        assert_eq!(context_after("<a id=x href="), EscapeContext::Url);
+        assert_eq!(context_after("<img SRC=\"/a?b="), EscapeContext::UrlPart);
+
+        let mut tracker = ContextTracker::new();
+        tracker.feed("<a href=\"");
+        tracker.value_rendered();
+        assert_eq!(tracker.context(), EscapeContext::UrlPart);
+        tracker.feed("\">");
+        assert_eq!(tracker.context(), EscapeContext::Html);
+    }
+}
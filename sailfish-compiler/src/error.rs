@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::env;
 use std::fmt;
 use std::fs;
@@ -57,7 +58,19 @@ pub struct Error {
     pub(crate) source_file: Option<PathBuf>,
     pub(crate) source: Option<String>,
     pub(crate) offset: Option<usize>,
+    // length of the erroneous range starting at `offset`
+    pub(crate) length: Option<usize>,
     pub(crate) chains: Vec<ErrorKind>,
+    // boxed to keep `Error` small since they are rarely set
+    pub(crate) details: Box<Details>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Details {
+    pub(crate) notes: Vec<String>,
+    pub(crate) help: Option<String>,
+    // other errors found in the same template
+    pub(crate) related: Vec<Error>,
 }
 
 impl Error {
@@ -75,70 +88,219 @@ impl Error {
     pub fn iter(&self) -> impl Iterator<Item = &ErrorKind> {
         self.chains.iter().rev()
     }
-}
 
-impl<T> From<T> for Error
-where
-    ErrorKind: From<T>,
-{
-    fn from(other: T) -> Self {
-        Self::from_kind(ErrorKind::from(other))
+    /// Template file where the error occurred
+    pub fn source_file(&self) -> Option<&Path> {
+        self.source_file.as_deref()
     }
-}
 
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let source = match (self.source.as_ref(), self.source_file.as_deref()) {
+    /// Byte offset of the error position in the template source
+    pub fn offset(&self) -> Option<usize> {
+        self.offset
+    }
+
+    /// Length of the erroneous range in bytes
+    pub fn length(&self) -> Option<usize> {
+        self.length
+    }
+
+    /// Line and column number (starting from 1) of the error position
+    ///
+    /// The template file is read if the error does not hold the template source.
+    pub fn line_column(&self) -> Option<(usize, usize)> {
+        let offset = self.offset?;
+        let source = match self.source {
+            Some(ref source) => Cow::Borrowed(source.as_str()),
+            None => Cow::Owned(fs::read_to_string(self.source_file.as_ref()?).ok()?),
+        };
+        if offset > source.len() {
+            return None;
+        }
+
+        let snippet = Snippet::new(&source, offset, 0);
+        Some((snippet.lineno, snippet.colno))
+    }
+
+    /// Notes attached to the error
+    pub fn notes(&self) -> &[String] {
+        &self.details.notes
+    }
+
+    /// Suggestion to fix the error
+    pub fn help(&self) -> Option<&str> {
+        self.details.help.as_deref()
+    }
+
+    /// Other errors reported together with this error
+    pub fn related(&self) -> &[Error] {
+        &self.details.related
+    }
+
+    /// Display the error with the options
+    ///
+    /// `Display` implementation of `Error` is the same as `error.report()`.
+    pub fn report(&self) -> Report<'_> {
+        Report {
+            error: self,
+            color: false,
+        }
+    }
+
+    /// Combine the errors into one error
+    ///
+    /// The first error becomes the primary error, and the others are reported as
+    /// its related errors. Returns `None` if `errors` is empty.
+    pub fn from_errors<I: IntoIterator<Item = Error>>(errors: I) -> Option<Self> {
+        let mut errors = errors.into_iter();
+        let mut primary = errors.next()?;
+        for mut e in errors {
+            let related = std::mem::take(&mut e.details.related);
+            primary.details.related.push(e);
+            primary.details.related.extend(related);
+        }
+        Some(primary)
+    }
+
+    /// Set the template file to the errors which are not bound to any file
+    pub(crate) fn set_source_file(&mut self, source_file: &Path, source: &str) {
+        let mut related = std::mem::take(&mut self.details.related);
+        for e in std::iter::once(&mut *self).chain(&mut related) {
+            if e.source_file.is_none() {
+                e.source_file = Some(source_file.to_owned());
+                e.source.get_or_insert_with(|| source.to_owned());
+            }
+        }
+        self.details.related = related;
+    }
+
+    fn fmt_single(
+        &self,
+        f: &mut fmt::Formatter,
+        parent: Option<&Error>,
+        color: bool,
+    ) -> fmt::Result {
+        // related errors inherit the source from the primary error
+        let (source, source_file) = match parent {
+            Some(p) if self.source.is_none() && self.source_file.is_none() => {
+                (p.source.as_ref(), p.source_file.as_deref())
+            }
+            _ => (self.source.as_ref(), self.source_file.as_deref()),
+        };
+        let source = match (source, source_file) {
             (Some(s), _) => Some(s.to_owned()),
             (None, Some(f)) => fs::read_to_string(f).ok(),
             (None, None) => None,
         };
 
-        writeln!(f, "{}", self.chains.last().unwrap())?;
+        paint(f, color, BOLD, self.chains.last().unwrap())?;
+        f.write_str("\n")?;
 
         for e in self.chains.iter().rev().skip(1) {
             writeln!(f, "caused by: {}", e)?;
         }
 
-        f.write_str("\n")?;
+        let source_file = source_file.map(|source_file| {
+            if env::var("SAILFISH_INTEGRATION_TESTS").is_ok_and(|s| s == "1") {
+                match source_file.file_name() {
+                    Some(f) => Path::new(f),
+                    None => Path::new(""),
+                }
+            } else {
+                source_file
+            }
+        });
+        let snippet = match (source.as_deref(), self.offset) {
+            (Some(source), Some(offset)) => {
+                Some(Snippet::new(source, offset, self.length.unwrap_or(0)))
+            }
+            _ => None,
+        };
+        let lpad = snippet
+            .as_ref()
+            .map_or(1, |s| count_digits(s.last_lineno()));
+
+        let position = snippet
+            .as_ref()
+            .map(|s| format!("{}:{}", s.lineno, s.colno));
+        let location = match (source_file, position) {
+            (Some(file), Some(position)) => {
+                Some(format!("{}:{}", file.display(), position))
+            }
+            (Some(file), None) => Some(file.display().to_string()),
+            (None, Some(position)) => Some(position),
+            (None, None) => None,
+        };
+        if let Some(location) = location {
+            write!(f, "{:lpad$}", "", lpad = lpad)?;
+            paint(f, color, BLUE, "-->")?;
+            writeln!(f, " {}", location)?;
+        }
 
-        if let Some(ref source_file) = self.source_file {
-            let source_file =
-                if env::var("SAILFISH_INTEGRATION_TESTS").map_or(false, |s| s == "1") {
-                    match source_file.file_name() {
-                        Some(f) => Path::new(f),
-                        None => Path::new(""),
-                    }
-                } else {
-                    source_file
-                };
-            writeln!(f, "file: {}", source_file.display())?;
+        if let Some(ref snippet) = snippet {
+            snippet.fmt(f, lpad, color)?;
         }
 
-        if let (Some(ref source), Some(offset)) = (source, self.offset) {
-            let (lineno, colno) = into_line_column(source, offset);
-            writeln!(f, "position: line {}, column {}\n", lineno, colno)?;
-
-            // TODO: display adjacent lines
-            let line = source.lines().nth(lineno - 1).unwrap();
-            let lpad = count_digits(lineno);
-
-            writeln!(f, "{:<lpad$} |", "", lpad = lpad)?;
-            writeln!(f, "{} | {}", lineno, line)?;
-            writeln!(
-                f,
-                "{:<lpad$} | {:<rpad$}^",
-                "",
-                "",
-                lpad = lpad,
-                rpad = colno - 1
-            )?;
+        let notes = self.details.notes.iter().map(|n| ("note", n));
+        for (i, (label, msg)) in notes
+            .chain(self.details.help.iter().map(|h| ("help", h)))
+            .enumerate()
+        {
+            if i == 0 && snippet.is_some() {
+                gutter(f, lpad, color)?;
+                f.write_str("\n")?;
+            }
+            write!(f, "{:lpad$} ", "", lpad = lpad)?;
+            paint(f, color, BLUE, "=")?;
+            f.write_str(" ")?;
+            paint(f, color, BOLD, label)?;
+            writeln!(f, ": {}", msg)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Formatter of [`Error`] with the options
+pub struct Report<'a> {
+    error: &'a Error,
+    color: bool,
+}
+
+impl<'a> Report<'a> {
+    /// Highlight the messages and the source snippets with ANSI escape codes
+    pub fn color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+}
+
+impl<'a> fmt::Display for Report<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.error.fmt_single(f, None, self.color)?;
+        for e in &self.error.details.related {
+            f.write_str("\n")?;
+            e.fmt_single(f, Some(self.error), self.color)?;
         }
 
         Ok(())
     }
 }
 
+impl<T> From<T> for Error
+where
+    ErrorKind: From<T>,
+{
+    fn from(other: T) -> Self {
+        Self::from_kind(ErrorKind::from(other))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.report().fmt(f)
+    }
+}
+
 impl std::error::Error for Error {}
 
 pub trait ResultExt<T> {
@@ -175,27 +337,114 @@ impl<T, E: Into<ErrorKind>> ResultExt<T> for Result<T, E> {
     }
 }
 
-fn into_line_column(source: &str, offset: usize) -> (usize, usize) {
-    assert!(
-        offset <= source.len(),
-        "Internal error: error position offset overflow (error code: 56066)"
-    );
-    let mut lineno = 1;
-    let mut colno = 1;
-    let mut current = 0;
-
-    for line in source.lines() {
-        let end = current + line.len() + 1;
-        if offset < end {
-            colno = offset - current + 1;
-            break;
+// number of lines displayed before the line where the error occurred
+const CONTEXT_LINES: usize = 2;
+
+const BOLD: &str = "1";
+const RED: &str = "1;31";
+const BLUE: &str = "1;34";
+
+fn paint<T: fmt::Display + ?Sized>(
+    f: &mut fmt::Formatter,
+    color: bool,
+    style: &str,
+    value: &T,
+) -> fmt::Result {
+    if color {
+        write!(f, "\x1b[{}m{}\x1b[0m", style, value)
+    } else {
+        write!(f, "{}", value)
+    }
+}
+
+// write the line number column without the line number
+fn gutter(f: &mut fmt::Formatter, lpad: usize, color: bool) -> fmt::Result {
+    write!(f, "{:lpad$} ", "", lpad = lpad)?;
+    paint(f, color, BLUE, "|")
+}
+
+/// Lines of the source around the error position
+struct Snippet<'a> {
+    // (line number, line) before the error line
+    before: Vec<(usize, &'a str)>,
+    line: &'a str,
+    lineno: usize,
+    colno: usize,
+    after: Option<&'a str>,
+    // byte range of the underline in `line`
+    underline: (usize, usize),
+}
+
+impl<'a> Snippet<'a> {
+    fn new(source: &'a str, offset: usize, length: usize) -> Self {
+        assert!(
+            offset <= source.len(),
+            "Internal error: error position offset overflow (error code: 56066)"
+        );
+
+        let lines: Vec<&str> = source
+            .split('\n')
+            .map(|l| l.strip_suffix('\r').unwrap_or(l))
+            .collect();
+        let index = source[..offset].matches('\n').count();
+        let line_start = source[..offset].rfind('\n').map_or(0, |p| p + 1);
+        let line = lines[index];
+
+        let start = (offset - line_start).min(line.len());
+        let end = (start + length).min(line.len());
+        let colno = line[..start].chars().count() + 1;
+
+        Snippet {
+            before: (index.saturating_sub(CONTEXT_LINES)..index)
+                .map(|i| (i + 1, lines[i]))
+                .collect(),
+            line,
+            lineno: index + 1,
+            colno,
+            after: lines.get(index + 1).copied().filter(|l| !l.is_empty()),
+            underline: (start, end),
         }
+    }
 
-        lineno += 1;
-        current = end;
+    fn last_lineno(&self) -> usize {
+        self.lineno + self.after.is_some() as usize
     }
 
-    (lineno, colno)
+    fn fmt(&self, f: &mut fmt::Formatter, lpad: usize, color: bool) -> fmt::Result {
+        let write_line = |f: &mut fmt::Formatter, lineno: usize, line: &str| {
+            paint(f, color, BLUE, &format!("{:>lpad$} |", lineno, lpad = lpad))?;
+            if line.is_empty() {
+                f.write_str("\n")
+            } else {
+                writeln!(f, " {}", line)
+            }
+        };
+
+        gutter(f, lpad, color)?;
+        f.write_str("\n")?;
+        for &(lineno, line) in &self.before {
+            write_line(f, lineno, line)?;
+        }
+        write_line(f, self.lineno, self.line)?;
+
+        // keep tabs in the padding so that the underline is aligned
+        let (start, end) = self.underline;
+        let padding: String = self.line[..start]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let width = self.line[start..end].chars().count().max(1);
+        gutter(f, lpad, color)?;
+        write!(f, " {}", padding)?;
+        paint(f, color, RED, &"^".repeat(width))?;
+        f.write_str("\n")?;
+
+        if let Some(after) = self.after {
+            write_line(f, self.lineno + 1, after)?;
+        }
+
+        Ok(())
+    }
 }
 
 fn count_digits(n: usize) -> usize {
@@ -252,17 +501,75 @@ mod tests {
         );
         err.chains.push(ErrorKind::Other("some error".to_owned()));
         assert!(matches!(err.kind(), &ErrorKind::Other(_)));
+        assert_eq!(err.line_column(), Some((2, 5)));
         assert_eq!(
             err.to_string(),
             r#"some error
 caused by: Analyzation error (mismatched types)
-
-file: apple.rs
-position: line 2, column 5
-
+ --> apple.rs:2:5
   |
+1 | fn func() {
 2 |     1
   |     ^
+3 | }
+"#
+        );
+    }
+
+    #[test]
+    fn display_snippet() {
+        let source = "a\nb\nc\n\td ü <%= value %>\n";
+        let mut err = make_error!(
+            ErrorKind::ParseError("invalid tag".to_owned()),
+            source = source.to_owned(),
+            offset = source.find("<%").unwrap(),
+            length = 15usize
+        );
+        err.details.notes.push("tags cannot be nested".to_owned());
+        err.details.help = Some("remove the tag".to_owned());
+        assert_eq!(
+            err.to_string(),
+            "Parse error (invalid tag)
+ --> 4:6
+  |
+2 | b
+3 | c
+4 | \td ü <%= value %>
+  | \t    ^^^^^^^^^^^^
+  |
+  = note: tags cannot be nested
+  = help: remove the tag
+"
+        );
+
+        let colored = err.report().color(true).to_string();
+        assert!(colored.contains("\x1b[1;31m^^^^^^^^^^^^\x1b[0m"));
+    }
+
+    #[test]
+    fn display_related_errors() {
+        let errors = vec![
+            make_error!(ErrorKind::ParseError("first".to_owned()), offset = 3usize),
+            make_error!(ErrorKind::ParseError("second".to_owned()), offset = 9usize),
+        ];
+        let mut err = Error::from_errors(errors).unwrap();
+        err.source = Some("<% a %>\n<% b %>".to_owned());
+        assert_eq!(err.related().len(), 1);
+        assert_eq!(
+            err.to_string(),
+            r#"Parse error (first)
+ --> 1:4
+  |
+1 | <% a %>
+  |    ^
+2 | <% b %>
+
+Parse error (second)
+ --> 2:2
+  |
+1 | <% a %>
+2 | <% b %>
+  |  ^
 "#
         );
     }
@@ -0,0 +1,1080 @@
+use proc_macro2::{TokenStream, TokenTree};
+
+use crate::error::*;
+use crate::parser::{Parser, TokenKind};
+use crate::util::rustfmt;
+
+/// HTML elements which have no end tag
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source",
+    "track", "wbr",
+];
+
+/// HTML elements whose contents are left unchanged
+const RAW_ELEMENTS: &[&str] = &["pre", "script", "style", "textarea"];
+
+/// Formatter of template sources
+///
+/// The formatter normalizes the spaces inside the tags, formats the Rust code
+/// inside the tags with `rustfmt`, and re-indents the lines along the HTML
+/// elements and the blocks in the template. Text is left unchanged except the
+/// indentation of the lines. The contents of comments and `pre`, `script`,
+/// `style` and `textarea` elements are not re-indented.
+///
+/// Like escaping, this is not a complete HTML parser. Text inside the branches
+/// of `if` or `match` statements is simply scanned in order.
+#[derive(Clone, Debug)]
+pub struct Formatter {
+    delimiter: char,
+    indent_width: usize,
+    reindent: bool,
+}
+
+impl Formatter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// change delimiter
+    #[inline]
+    pub fn delimiter(mut self, new: char) -> Self {
+        self.delimiter = new;
+        self
+    }
+
+    /// Number of spaces for each indentation level (default: 2)
+    #[inline]
+    pub fn indent_width(mut self, new: usize) -> Self {
+        self.indent_width = new;
+        self
+    }
+
+    /// Re-indent the lines along the HTML structure (default: `true`)
+    ///
+    /// Disable this option for non-HTML templates, in which whitespaces are
+    /// significant. Only the tags are formatted in that case.
+    #[inline]
+    pub fn reindent(mut self, new: bool) -> Self {
+        self.reindent = new;
+        self
+    }
+
+    /// Format the template source
+    ///
+    /// Returns an error if the template contains syntax errors.
+    pub fn format(&self, source: &str) -> Result<String, Error> {
+        let items = self.split_tags(source)?;
+        let fragments: Vec<_> = items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Tag(tag) => Some(tag),
+                Item::Text(_) => None,
+            })
+            .filter(|tag| tag.is_code())
+            .collect();
+        let mut formatted = format_code(&fragments).into_iter();
+
+        // whitespaces may be significant in the templates without HTML elements
+        let reindent = self.reindent
+            && items.iter().any(|item| match item {
+                Item::Text(text) => contains_html_tag(text),
+                Item::Tag(_) => false,
+            });
+
+        let mut writer = Writer::new(reindent, " ".repeat(self.indent_width));
+        for item in &items {
+            match item {
+                Item::Text(text) => writer.write_text(text),
+                Item::Tag(tag) => {
+                    let lines = if tag.is_code() {
+                        formatted.next().flatten()
+                    } else {
+                        None
+                    };
+                    writer.write_tag(self.format_tag(tag, lines));
+                }
+            }
+        }
+
+        Ok(writer.finish())
+    }
+
+    // split the source into the text and the tags
+    fn split_tags<'a>(&self, source: &'a str) -> Result<Vec<Item<'a>>, Error> {
+        let open = format!("<{}", self.delimiter);
+        let close = format!("{}>", self.delimiter);
+        let tokens = Parser::new()
+            .delimiter(self.delimiter)
+            .parse(source)
+            .into_vec()?;
+
+        let mut items = Vec::new();
+        let mut pos = 0;
+        for token in tokens.iter().filter(|t| t.kind() != TokenKind::Text) {
+            let content_start = token.offset();
+            let content_end = content_start + token.as_str().len();
+            // the tag prefix contains no other opening delimiter
+            let start = pos + source[pos..content_start].rfind(&open).unwrap();
+            let inner_end = content_end + source[content_end..].find(&close).unwrap();
+            let end = inner_end + close.len();
+
+            if start > pos {
+                items.push(Item::Text(&source[pos..start]));
+            }
+            items.push(Item::Tag(Tag {
+                kind: token.kind(),
+                content: token.as_str(),
+                raw: &source[start..end],
+                trim_start: source[start + open.len()..].starts_with('_'),
+                trim_end: inner_end > content_end && source[..inner_end].ends_with('_'),
+            }));
+            pos = end;
+        }
+
+        if pos < source.len() {
+            items.push(Item::Text(&source[pos..]));
+        }
+
+        Ok(items)
+    }
+
+    fn format_tag<'a>(
+        &self,
+        tag: &Tag<'a>,
+        lines: Option<Vec<String>>,
+    ) -> FormattedTag<'a> {
+        let (net, leading_close) = match tag.kind {
+            TokenKind::Comment => (0, false),
+            TokenKind::BlockStart | TokenKind::MacroStart => (1, false),
+            TokenKind::BlockEnd | TokenKind::MacroEnd => (-1, true),
+            TokenKind::Code => brace_balance(tag.content),
+            _ => (0, false),
+        };
+        let mut formatted = FormattedTag {
+            raw: tag.raw,
+            open: String::new(),
+            lines: Vec::new(),
+            close: String::new(),
+            net,
+            leading_close,
+        };
+
+        let flag = match tag.kind {
+            TokenKind::Comment => return formatted,
+            TokenKind::Code => "",
+            TokenKind::BufferedCode { escape: true } => "=",
+            TokenKind::BufferedCode { escape: false } => "-",
+            TokenKind::NestedTemplateOnce => "+",
+            _ => "@",
+        };
+
+        let lines = match lines {
+            // single line is not expanded (e.g. `<% if x { break; } %>`)
+            Some(lines) if lines.len() == 1 || tag.content.contains('\n') => lines,
+            _ if flag == "@" => match directive(tag, self.delimiter) {
+                Some(directive) => vec![directive],
+                None => return formatted,
+            },
+            // code which could not be formatted is left unchanged except the spaces
+            // around it
+            _ if !tag.content.contains('\n') => vec![tag.content.to_owned()],
+            _ => return formatted,
+        };
+
+        formatted.open = format!(
+            "<{}{}{}",
+            self.delimiter,
+            if tag.trim_start { "_" } else { "" },
+            flag
+        );
+        formatted.close =
+            format!("{}{}>", if tag.trim_end { "_" } else { "" }, self.delimiter);
+        formatted.lines = lines;
+        formatted
+    }
+}
+
+impl Default for Formatter {
+    fn default() -> Self {
+        Self {
+            delimiter: '%',
+            indent_width: 2,
+            reindent: true,
+        }
+    }
+}
+
+enum Item<'a> {
+    Text(&'a str),
+    Tag(Tag<'a>),
+}
+
+struct Tag<'a> {
+    kind: TokenKind,
+    content: &'a str,
+    raw: &'a str,
+    // whether the tag has `_` modifiers
+    trim_start: bool,
+    trim_end: bool,
+}
+
+impl<'a> Tag<'a> {
+    fn is_code(&self) -> bool {
+        matches!(
+            self.kind,
+            TokenKind::Code
+                | TokenKind::BufferedCode { .. }
+                | TokenKind::NestedTemplateOnce
+        )
+    }
+}
+
+struct FormattedTag<'a> {
+    raw: &'a str,
+    open: String,
+    // formatted content, or empty if the tag is left unchanged
+    lines: Vec<String>,
+    close: String,
+    // change of the indentation level after the tag
+    net: isize,
+    // whether the tag closes the block opened by the previous tags
+    leading_close: bool,
+}
+
+impl<'a> FormattedTag<'a> {
+    fn write(&self, buf: &mut String, indent: &str, unit: &str) {
+        match self.lines.as_slice() {
+            [] => buf.push_str(self.raw),
+            [line] if line.is_empty() => {
+                buf.push_str(&self.open);
+                buf.push(' ');
+                buf.push_str(&self.close);
+            }
+            [line] => {
+                buf.push_str(&self.open);
+                buf.push(' ');
+                buf.push_str(line);
+                buf.push(' ');
+                buf.push_str(&self.close);
+            }
+            lines => {
+                buf.push_str(&self.open);
+                for line in lines {
+                    buf.push('\n');
+                    if !line.is_empty() {
+                        buf.push_str(indent);
+                        buf.push_str(unit);
+                        buf.push_str(line);
+                    }
+                }
+                buf.push('\n');
+                buf.push_str(indent);
+                buf.push_str(&self.close);
+            }
+        }
+    }
+}
+
+/// Directive with a single space between the name and the arguments
+fn directive(tag: &Tag<'_>, delimiter: char) -> Option<String> {
+    let close = format!("{}>", delimiter);
+    let inner = tag.raw.strip_suffix(&close)?;
+    let inner = if tag.trim_end {
+        inner.strip_suffix('_')?
+    } else {
+        inner
+    };
+    let (_, directive) = inner.split_once('@')?;
+    let directive = directive.trim();
+    if directive.contains('\n') {
+        return None;
+    }
+
+    let name_len = directive
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(directive.len());
+    let (name, args) = directive.split_at(name_len);
+    let args = args.trim_start();
+    Some(if args.is_empty() || args.starts_with('(') {
+        format!("{}{}", name, args)
+    } else {
+        format!("{} {}", name, args)
+    })
+}
+
+/// Format the code inside the tags with a single `rustfmt` invocation
+///
+/// Returns the formatted lines for each tag, or `None` if the code could not be
+/// formatted (e.g. `<% for x in xs { %>`).
+fn format_code(tags: &[&Tag<'_>]) -> Vec<Option<Vec<String>>> {
+    let mut results = vec![None; tags.len()];
+    let mut source = String::new();
+    for (i, tag) in tags.iter().enumerate() {
+        let valid = if tag.kind == TokenKind::Code {
+            syn::parse_str::<syn::Block>(&format!("{{\n{}\n}}", tag.content)).is_ok()
+        } else {
+            syn::parse_str::<syn::Expr>(tag.content).is_ok()
+        };
+
+        // lines inside the string literals cannot be re-indented
+        let has_multiline_literal = tag
+            .content
+            .parse::<TokenStream>()
+            .map_or(true, has_multiline_literal);
+
+        if valid && !has_multiline_literal && !tag.content.trim().is_empty() {
+            source.push_str(&format!("fn __sf_fmt_{}() {{\n{}\n}}\n", i, tag.content));
+        }
+    }
+
+    if source.is_empty() {
+        return results;
+    }
+
+    let output = match rustfmt(&source) {
+        Ok(output) => output,
+        Err(_) => return results,
+    };
+
+    let mut lines = output.lines();
+    while let Some(line) = lines.next() {
+        let Some((index, rest)) = line
+            .strip_prefix("fn __sf_fmt_")
+            .and_then(|rest| rest.split_once("()"))
+        else {
+            continue;
+        };
+        let Ok(index) = index.parse::<usize>() else {
+            continue;
+        };
+
+        let body: Vec<&str> = match rest.trim() {
+            "{" => lines.by_ref().take_while(|l| *l != "}").collect(),
+            rest => match rest.strip_prefix("{ ").and_then(|r| r.strip_suffix(" }")) {
+                Some(body) => vec![body],
+                None => continue,
+            },
+        };
+
+        let indent = body
+            .iter()
+            .filter(|l| !l.trim().is_empty())
+            .map(|l| l.len() - l.trim_start().len())
+            .min();
+        if let (Some(indent), Some(result)) = (indent, results.get_mut(index)) {
+            *result = Some(
+                body.iter()
+                    .map(|l| l.get(indent..).unwrap_or("").trim_end().to_owned())
+                    .collect(),
+            );
+        }
+    }
+
+    results
+}
+
+fn contains_html_tag(text: &str) -> bool {
+    text.match_indices('<').any(|(i, _)| {
+        let rest = &text[i + 1..];
+        rest.strip_prefix('/')
+            .unwrap_or(rest)
+            .starts_with(|c: char| c.is_ascii_alphabetic())
+    })
+}
+
+fn has_multiline_literal(tokens: TokenStream) -> bool {
+    tokens.into_iter().any(|tt| match tt {
+        TokenTree::Group(g) => has_multiline_literal(g.stream()),
+        TokenTree::Literal(l) => l.to_string().contains('\n'),
+        _ => false,
+    })
+}
+
+/// Count the braces in the code outside of literals and comments
+///
+/// Returns the change of the nesting level, and whether the code starts with a
+/// closing brace.
+fn brace_balance(code: &str) -> (isize, bool) {
+    let leading_close = code.trim_start().starts_with('}');
+    let mut net = 0;
+    let mut chars = code.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => net += 1,
+            '}' => net -= 1,
+            '"' => {
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            // character literal (not lifetime)
+            '\'' => {
+                let mut lookahead = chars.clone();
+                let literal_len = match lookahead.next() {
+                    Some('\\') => lookahead.position(|c| c == '\''),
+                    Some(_) => (lookahead.next() == Some('\'')).then_some(0),
+                    None => None,
+                };
+                if let Some(len) = literal_len {
+                    for _ in 0..len + 2 {
+                        chars.next();
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                chars.by_ref().find(|&c| c == '\n');
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                while let Some(c) = chars.next() {
+                    if c == '*' && chars.peek() == Some(&'/') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    (net, leading_close)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Html {
+    Text,
+    // `<!-- -->`
+    Comment,
+    // doctype or processing instruction
+    Markup,
+    StartTag {
+        name: String,
+        name_done: bool,
+        quote: Option<char>,
+        self_closing: bool,
+    },
+    EndTag {
+        name: String,
+        name_done: bool,
+    },
+    // content of the elements in `RAW_ELEMENTS`
+    Raw(String),
+}
+
+/// Track the nesting of the HTML elements through the template text
+struct HtmlScanner {
+    state: Html,
+    elements: Vec<String>,
+}
+
+impl HtmlScanner {
+    // whether the lines starting at the current position must be left unchanged
+    fn is_verbatim(&self) -> bool {
+        match self.state {
+            Html::Comment | Html::Markup | Html::Raw(_) => true,
+            Html::StartTag { quote, .. } => quote.is_some(),
+            _ => false,
+        }
+    }
+
+    // whether the current position is inside the tag
+    fn in_tag(&self) -> bool {
+        matches!(self.state, Html::StartTag { .. } | Html::EndTag { .. })
+    }
+
+    // whether the rest starts the end tag, or continues the end tag
+    fn is_closing(&self, rest: &str) -> bool {
+        match self.state {
+            Html::Text => rest.starts_with("</"),
+            Html::EndTag { .. } => true,
+            _ => false,
+        }
+    }
+
+    // consume the first character (or more) of `rest` and return the consumed
+    // length
+    fn step(&mut self, rest: &str) -> usize {
+        let c = rest.chars().next().unwrap();
+        let state = std::mem::replace(&mut self.state, Html::Text);
+        let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == ':';
+        let (state, consumed) = match state {
+            Html::Text => {
+                let next = rest[c.len_utf8()..].chars().next();
+                if rest.starts_with("<!--") {
+                    (Html::Comment, 4)
+                } else if rest.starts_with("</") {
+                    let name = String::new();
+                    (
+                        Html::EndTag {
+                            name,
+                            name_done: false,
+                        },
+                        2,
+                    )
+                } else if c == '<' && next.is_some_and(|c| c.is_ascii_alphabetic()) {
+                    let start_tag = Html::StartTag {
+                        name: String::new(),
+                        name_done: false,
+                        quote: None,
+                        self_closing: false,
+                    };
+                    (start_tag, 1)
+                } else if c == '<' && next.is_some_and(|c| c == '!' || c == '?') {
+                    (Html::Markup, 2)
+                } else {
+                    (Html::Text, c.len_utf8())
+                }
+            }
+            Html::Comment if rest.starts_with("-->") => (Html::Text, 3),
+            Html::Comment => (Html::Comment, c.len_utf8()),
+            Html::Markup if c == '>' => (Html::Text, 1),
+            Html::Markup => (Html::Markup, c.len_utf8()),
+            Html::StartTag {
+                mut name,
+                mut name_done,
+                mut quote,
+                mut self_closing,
+            } => {
+                match (quote, c) {
+                    (Some(q), c) if c == q => quote = None,
+                    (Some(_), _) => {}
+                    (None, '>') => return self.open_element(name, self_closing),
+                    (None, c) => {
+                        if !name_done && is_name_char(c) {
+                            name.push(c.to_ascii_lowercase());
+                        } else {
+                            name_done = true;
+                        }
+                        if c == '"' || c == '\'' {
+                            quote = Some(c);
+                        }
+                        if !c.is_whitespace() {
+                            self_closing = c == '/';
+                        }
+                    }
+                }
+                let start_tag = Html::StartTag {
+                    name,
+                    name_done,
+                    quote,
+                    self_closing,
+                };
+                (start_tag, c.len_utf8())
+            }
+            Html::EndTag { name, .. } if c == '>' => {
+                if let Some(pos) = self.elements.iter().rposition(|e| *e == name) {
+                    self.elements.truncate(pos);
+                }
+                (Html::Text, 1)
+            }
+            Html::EndTag {
+                mut name,
+                mut name_done,
+            } => {
+                if !name_done && is_name_char(c) {
+                    name.push(c.to_ascii_lowercase());
+                } else {
+                    name_done = true;
+                }
+                (Html::EndTag { name, name_done }, c.len_utf8())
+            }
+            Html::Raw(name) => {
+                let is_end_tag = rest.starts_with("</")
+                    && rest
+                        .get(2..2 + name.len())
+                        .is_some_and(|n| n.eq_ignore_ascii_case(&name));
+                if is_end_tag {
+                    let name = String::new();
+                    (
+                        Html::EndTag {
+                            name,
+                            name_done: false,
+                        },
+                        2,
+                    )
+                } else {
+                    (Html::Raw(name), c.len_utf8())
+                }
+            }
+        };
+
+        self.state = state;
+        consumed
+    }
+
+    // number of the elements closed by the start tag at the beginning of `rest`
+    fn implied_end_tags(&self, rest: &str) -> usize {
+        if self.state != Html::Text {
+            return 0;
+        }
+        let Some(name) = rest.strip_prefix('<') else {
+            return 0;
+        };
+        let name_len = name
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(name.len());
+        let closed = closed_by(&name[..name_len].to_ascii_lowercase());
+        self.elements
+            .iter()
+            .rev()
+            .take_while(|e| closed.contains(&e.as_str()))
+            .count()
+    }
+
+    fn open_element(&mut self, name: String, self_closing: bool) -> usize {
+        let closed = closed_by(&name);
+        while self
+            .elements
+            .last()
+            .is_some_and(|e| closed.contains(&e.as_str()))
+        {
+            self.elements.pop();
+        }
+
+        if self_closing || VOID_ELEMENTS.contains(&name.as_str()) {
+            self.state = Html::Text;
+        } else if RAW_ELEMENTS.contains(&name.as_str()) {
+            self.elements.push(name.clone());
+            self.state = Html::Raw(name);
+        } else {
+            self.elements.push(name);
+            self.state = Html::Text;
+        }
+        1
+    }
+}
+
+// elements whose end tags can be omitted before the start tag of `name`
+fn closed_by(name: &str) -> &'static [&'static str] {
+    match name {
+        "li" => &["li"],
+        "p" => &["p"],
+        "option" => &["option"],
+        "dt" | "dd" => &["dt", "dd"],
+        "tr" => &["tr", "td", "th"],
+        "td" | "th" => &["td", "th"],
+        _ => &[],
+    }
+}
+
+enum Piece<'a> {
+    Text(&'a str),
+    Tag(FormattedTag<'a>),
+}
+
+/// Nesting levels opened by a single line, which are indented together
+struct Frame {
+    // first nesting level opened by the line
+    start: usize,
+    line: usize,
+    // indentation level of the line
+    indent: usize,
+}
+
+/// Write the formatted lines with the indentation
+///
+/// The lines inside the elements and blocks are indented one level deeper than
+/// the line opening them, even if the line opens multiple levels. The lines
+/// starting with the closing tags are aligned with the opening lines.
+struct Writer<'a> {
+    buf: String,
+    reindent: bool,
+    unit: String,
+    html: HtmlScanner,
+    // nesting level of the blocks in the template code
+    code_depth: isize,
+    // nesting levels grouped by the lines opening them
+    frames: Vec<Frame>,
+    // nesting level tracked by `frames`
+    level: usize,
+    line_no: usize,
+    line: Vec<Piece<'a>>,
+    // indentation level of the current line
+    indent_depth: usize,
+    // whether the current line is aligned with the line opening the closed level
+    aligned: bool,
+    // whether the current line contains only whitespaces and closing tags
+    leading: bool,
+    // whether the current line starts inside the HTML tag
+    continuation: bool,
+    // whether the current line must be left unchanged
+    verbatim: bool,
+}
+
+impl<'a> Writer<'a> {
+    fn new(reindent: bool, unit: String) -> Self {
+        let mut writer = Self {
+            buf: String::new(),
+            reindent,
+            unit,
+            html: HtmlScanner {
+                state: Html::Text,
+                elements: Vec::new(),
+            },
+            code_depth: 0,
+            frames: Vec::new(),
+            level: 0,
+            line_no: 0,
+            line: Vec::new(),
+            indent_depth: 0,
+            aligned: false,
+            leading: true,
+            continuation: false,
+            verbatim: false,
+        };
+        writer.start_line(false);
+        writer
+    }
+
+    fn depth(&self) -> isize {
+        self.html.elements.len() as isize + self.code_depth
+    }
+
+    // indentation level of the lines inside the innermost frame
+    fn body_indent(&self) -> usize {
+        self.frames.last().map_or(0, |f| f.indent + 1)
+    }
+
+    // update the frames with the nesting level
+    //
+    // `implied` is true if the levels are closed by omitted end tags
+    fn move_to(&mut self, depth: isize, implied: bool) {
+        let level = depth.max(0) as usize;
+        if level < self.level {
+            let closed = self.frames.iter().rposition(|f| f.start <= level);
+            let indent = closed.map_or(0, |i| self.frames[i].indent);
+            self.frames.retain(|f| f.start < level);
+
+            if self.leading && !self.aligned {
+                if implied {
+                    self.indent_depth = self.body_indent();
+                } else {
+                    self.indent_depth = indent;
+                    self.aligned = true;
+                }
+            }
+        } else if level > self.level
+            && self.frames.last().is_none_or(|f| f.line != self.line_no)
+        {
+            self.frames.push(Frame {
+                start: self.level,
+                line: self.line_no,
+                indent: self.indent_depth,
+            });
+        }
+        self.level = level;
+    }
+
+    fn start_line(&mut self, after_trim: bool) {
+        self.line_no += 1;
+        self.indent_depth = self.body_indent();
+        self.aligned = false;
+        self.leading = true;
+        self.continuation = self.html.in_tag();
+        // `_%>` removes the newline, so the indentation may be the only whitespace
+        // between the texts
+        self.verbatim = !self.reindent || self.html.is_verbatim() || after_trim;
+    }
+
+    fn write_text(&mut self, text: &'a str) {
+        for (i, line) in text.split('\n').enumerate() {
+            if i > 0 {
+                self.html.step("\n");
+                self.end_line(true);
+            }
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut pos = 0;
+            while pos < line.len() {
+                let rest = &line[pos..];
+                if self.leading
+                    && !rest.starts_with(char::is_whitespace)
+                    && !self.html.is_closing(rest)
+                {
+                    let closed = self.html.implied_end_tags(rest) as isize;
+                    self.move_to(self.depth() - closed, true);
+                    self.leading = false;
+                }
+                pos += self.html.step(rest);
+                self.move_to(self.depth(), false);
+            }
+            self.line.push(Piece::Text(line));
+        }
+    }
+
+    fn write_tag(&mut self, tag: FormattedTag<'a>) {
+        if tag.leading_close {
+            self.code_depth -= 1;
+            self.move_to(self.depth(), false);
+            self.code_depth += 1;
+        }
+        self.leading = self.leading && tag.leading_close && tag.net < 0;
+        self.code_depth += tag.net;
+        self.move_to(self.depth(), false);
+        self.line.push(Piece::Tag(tag));
+    }
+
+    fn end_line(&mut self, newline: bool) {
+        let pieces = std::mem::take(&mut self.line);
+        let after_trim =
+            matches!(pieces.last(), Some(Piece::Tag(t)) if t.close.starts_with('_'));
+
+        let indent = if self.verbatim {
+            // indentation of the original line
+            match pieces.first() {
+                Some(Piece::Text(text)) => {
+                    let trimmed = text.trim_start_matches([' ', '\t']);
+                    text[..text.len() - trimmed.len()].to_owned()
+                }
+                _ => String::new(),
+            }
+        } else {
+            self.unit
+                .repeat(self.indent_depth + self.continuation as usize)
+        };
+
+        for (i, piece) in pieces.iter().enumerate() {
+            match piece {
+                Piece::Text(text) if i == 0 && !self.verbatim => {
+                    let text = text.trim_start_matches([' ', '\t']);
+                    // lines with only whitespaces are not indented
+                    if pieces.len() > 1 || !text.trim().is_empty() {
+                        self.buf.push_str(&indent);
+                    }
+                    self.buf.push_str(text);
+                }
+                Piece::Text(text) => self.buf.push_str(text),
+                Piece::Tag(tag) => {
+                    if i == 0 && !self.verbatim {
+                        self.buf.push_str(&indent);
+                    }
+                    tag.write(&mut self.buf, &indent, &self.unit);
+                }
+            }
+        }
+
+        if newline {
+            self.buf.push('\n');
+        }
+        self.start_line(after_trim);
+    }
+
+    fn finish(mut self) -> String {
+        self.end_line(false);
+        self.buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn format(source: &str) -> String {
+        Formatter::new().format(source).unwrap()
+    }
+
+    #[test]
+    fn tags() {
+        assert_eq!(format("<%=name%> <%+  a   %>"), "<%= name %> <%+ a %>");
+        assert_eq!(
+            format("<%_ let x = 1; _%>\n<%#  comment %>"),
+            "<%_ let x = 1; _%>\n<%#  comment %>"
+        );
+        assert_eq!(
+            format("<%@extends   \"base.stpl\"%><%@ super  ()%><%@ call  card(1) _%>"),
+            "<%@ extends \"base.stpl\" %><%@ super() %><%@ call card(1) _%>"
+        );
+        assert_eq!(
+            Formatter::new()
+                .delimiter('?')
+                .format("<?=x?><%=x%>")
+                .unwrap(),
+            "<?= x ?><%=x%>"
+        );
+    }
+
+    #[test]
+    fn reindent() {
+        let source = "\
+<!DOCTYPE html>
+<html>
+<body>
+      <ul class=\"items\">
+    <% for item in items { %>
+  <li><%= item %></li>
+    <% } %>
+  </ul>
+<%@ block footer %>
+<footer
+class=\"x\">&copy;</footer>
+<%@ endblock %>
+</body>
+</html>";
+        let expected = "\
+<!DOCTYPE html>
+<html>
+  <body>
+    <ul class=\"items\">
+      <% for item in items { %>
+        <li><%= item %></li>
+      <% } %>
+    </ul>
+    <%@ block footer %>
+      <footer
+        class=\"x\">&copy;</footer>
+    <%@ endblock %>
+  </body>
+</html>";
+        assert_eq!(format(source), expected);
+        assert_eq!(format(expected), expected);
+    }
+
+    #[test]
+    fn reindent_macros() {
+        let source = "\
+<%@ macro badge(count: usize) %><span class=\"badge\"><%= count %></span><%@ endmacro %>
+<%@ macro card(title: &str, body: &str) %><div class=\"card\">
+<h2><%= title %></h2>
+      <p><%= body %></p>
+</div><%@ endmacro %>
+<%@ macro list(items: &[&str]) %>
+<ul>
+<% for item in items { %>
+<li><%= item %></li>
+<% } %>
+</ul>
+<%@ endmacro %>";
+        let expected = "\
+<%@ macro badge(count: usize) %><span class=\"badge\"><%= count %></span><%@ endmacro %>
+<%@ macro card(title: &str, body: &str) %><div class=\"card\">
+  <h2><%= title %></h2>
+  <p><%= body %></p>
+</div><%@ endmacro %>
+<%@ macro list(items: &[&str]) %>
+  <ul>
+    <% for item in items { %>
+      <li><%= item %></li>
+    <% } %>
+  </ul>
+<%@ endmacro %>";
+        assert_eq!(format(source), expected);
+        assert_eq!(format(expected), expected);
+    }
+
+    #[test]
+    fn reindent_text_after_close() {
+        let source = "\
+<ul>
+<% for item in items { %>  <li><%= item %></li>
+<% } %></ul>
+<div><% if x { %>
+<p>a</p>
+<% } else { %>
+<p>b</p>
+<% } %></div>";
+        let expected = "\
+<ul>
+  <% for item in items { %>  <li><%= item %></li>
+  <% } %></ul>
+<div><% if x { %>
+  <p>a</p>
+<% } else { %>
+  <p>b</p>
+<% } %></div>";
+        assert_eq!(format(source), expected);
+        assert_eq!(format(expected), expected);
+    }
+
+    #[test]
+    fn verbatim() {
+        let source = "\
+<div>
+<pre>
+  a
+    b</pre>
+<!--
+  comment
+-->
+<p title=\"a
+  b\"><% if x { _%>
+text<% } %></p>
+<script>
+  if (a) {
+  }
+</script>
+</div>";
+        let expected = "\
+<div>
+  <pre>
+  a
+    b</pre>
+  <!--
+  comment
+-->
+  <p title=\"a
+  b\"><% if x { _%>
+text<% } %></p>
+  <script>
+  if (a) {
+  }
+</script>
+</div>";
+        assert_eq!(format(source), expected);
+
+        assert_eq!(
+            format("<ul>\r\n<li>a  \r\n<li>b\r\n</ul>"),
+            "<ul>\r\n  <li>a  \r\n  <li>b\r\n</ul>"
+        );
+
+        let source = "<ul>\n<li>a\n</ul>  ";
+        let formatter = Formatter::new().reindent(false);
+        assert_eq!(formatter.format(source).unwrap(), source);
+
+        // templates without HTML elements are not re-indented
+        let source = "items:\n<% for x in xs { %>\n  - <%= x %>  \n<% } %>";
+        assert_eq!(format(source), source);
+    }
+
+    #[test]
+    fn braces() {
+        assert_eq!(brace_balance("if x {"), (1, false));
+        assert_eq!(brace_balance("} else if y == '{' {"), (0, true));
+        assert_eq!(brace_balance("match x { // }\n Some('a) => {"), (2, false));
+        assert_eq!(brace_balance("f(\"}\\\"}\"); }"), (-1, false));
+    }
+
+    #[test]
+    fn rustfmt_code() {
+        if rustfmt("").is_err() {
+            return;
+        }
+
+        assert_eq!(
+            format("<% let  x=vec![1,2]; %>\n<%= x.len( ) %><%- a |upper  %>"),
+            "<% let x = vec![1, 2]; %>\n<%= x.len() %><%- a | upper %>"
+        );
+        assert_eq!(
+            format("<div>\n<%\nlet a=1;\n\nlet b=2;\n%>\n</div>"),
+            "<div>\n  <%\n    let a = 1;\n\n    let b = 2;\n  %>\n</div>"
+        );
+        assert_eq!(format("<%if x{break;}%>"), "<% if x{break;} %>");
+        // code which cannot be formatted alone
+        assert_eq!(
+            format("<%   for x in  y {%><%}%>"),
+            "<% for x in  y { %><% } %>"
+        );
+        assert_eq!(
+            format("<% let s = \"a\n  b\"; %>"),
+            "<% let s = \"a\n  b\"; %>"
+        );
+    }
+}
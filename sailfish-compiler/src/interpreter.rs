@@ -0,0 +1,407 @@
+use std::path::{Path, PathBuf};
+use syn::{Expr, Lit, Macro, Member, Stmt};
+
+use crate::context::{ContextTracker, EscapeContext};
+use crate::error::*;
+use crate::optimizer::{remove_newlines, remove_whitespace};
+use crate::parser::{Parser, TokenKind};
+use crate::util::read_to_string;
+
+/// Instruction evaluated by the template interpreter
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    /// Render the text as is
+    Text(String),
+    /// Render the text with HTML escaping
+    Escaped(String),
+    /// Render the field of the template struct
+    Field { name: String, escape: bool },
+}
+
+/// Translate the template source into instructions without compiling it.
+///
+/// Only simple templates (texts, comments, fields, literals and `include!`
+/// macro) can be interpreted.
+#[derive(Clone, Debug)]
+pub struct Interpreter {
+    delimiter: char,
+    escape: bool,
+    rm_whitespace: bool,
+    rm_newline: bool,
+    local_fields: bool,
+}
+
+impl Interpreter {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub fn delimiter(mut self, new: char) -> Self {
+        self.delimiter = new;
+        self
+    }
+
+    #[inline]
+    pub fn escape(mut self, new: bool) -> Self {
+        self.escape = new;
+        self
+    }
+
+    #[inline]
+    pub fn rm_whitespace(mut self, new: bool) -> Self {
+        self.rm_whitespace = new;
+        self
+    }
+
+    #[inline]
+    pub fn rm_newline(mut self, new: bool) -> Self {
+        self.rm_newline = new;
+        self
+    }
+
+    /// If `true`, fields are accessed as local variables (`TemplateSimple`)
+    /// instead of `self.field`.
+    #[inline]
+    pub fn local_fields(mut self, new: bool) -> Self {
+        self.local_fields = new;
+        self
+    }
+
+    /// Translate the template file into instructions.
+    ///
+    /// Returns `Ok(None)` if the template contains code which cannot be
+    /// evaluated by the interpreter.
+    pub fn load(&self, input: &Path) -> Result<Option<Vec<Instruction>>, Error> {
+        let mut program = Vec::new();
+        let mut path_stack = Vec::new();
+
+        if self.load_file(input, &mut program, &mut path_stack)? {
+            Ok(Some(program))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn load_file(
+        &self,
+        input: &Path,
+        program: &mut Vec<Instruction>,
+        path_stack: &mut Vec<PathBuf>,
+    ) -> Result<bool, Error> {
+        if path_stack.iter().any(|p| p == input) {
+            return Err(make_error!(ErrorKind::AnalyzeError(format!(
+                "Circular include detected: {:?}",
+                input
+            ))));
+        }
+
+        let content = read_to_string(input)
+            .chain_err(|| format!("Failed to open template file: {:?}", input))?;
+
+        path_stack.push(input.to_owned());
+        let result = self
+            .interpret(input, &content, program, path_stack)
+            .map_err(|mut e| {
+                if e.source_file.is_none() {
+                    e.source = Some(content.clone());
+                    e.source_file = Some(input.to_owned());
+                }
+                e
+            });
+        path_stack.pop();
+
+        result
+    }
+
+    fn interpret(
+        &self,
+        input: &Path,
+        content: &str,
+        program: &mut Vec<Instruction>,
+        path_stack: &mut Vec<PathBuf>,
+    ) -> Result<bool, Error> {
+        let parser = Parser::new().delimiter(self.delimiter);
+        let mut text = String::new();
+        let mut context = ContextTracker::new();
+
+        for token in parser.parse(content) {
+            let token = token?;
+            match token.kind() {
+                TokenKind::Text => {
+                    text.push_str(token.as_str());
+                    context.feed(token.as_str());
+                }
+                TokenKind::Comment => {}
+                kind => {
+                    self.write_text(&mut text, program);
+
+                    let supported = match kind {
+                        // only HTML escaping is supported
+                        TokenKind::BufferedCode { escape }
+                            if self.escape
+                                && escape
+                                && (context.context() != EscapeContext::Html
+                                    || context.is_unquoted()) =>
+                        {
+                            false
+                        }
+                        TokenKind::BufferedCode { escape } => {
+                            context.value_rendered();
+                            self.write_buffered_code(
+                                token.as_str(),
+                                self.escape && escape,
+                                program,
+                            )
+                        }
+                        TokenKind::Code => {
+                            self.write_code(input, token.as_str(), program, path_stack)?
+                        }
+                        // the output is not streamed while hot reloading
+                        TokenKind::Flush => true,
+                        _ => false,
+                    };
+
+                    if !supported {
+                        return Ok(false);
+                    }
+                }
+            }
+        }
+
+        self.write_text(&mut text, program);
+        Ok(true)
+    }
+
+    fn write_text(&self, text: &mut String, program: &mut Vec<Instruction>) {
+        if text.is_empty() {
+            return;
+        }
+
+        // same as the optimizer, which applies to each text literal
+        let mut optimized = std::mem::take(text);
+        if self.rm_whitespace {
+            optimized = remove_whitespace(&optimized);
+        }
+        if self.rm_newline {
+            optimized = remove_newlines(&optimized);
+        }
+
+        push_text(program, optimized);
+    }
+
+    fn write_buffered_code(
+        &self,
+        code: &str,
+        escape: bool,
+        program: &mut Vec<Instruction>,
+    ) -> bool {
+        let mut expr = match syn::parse_str::<Expr>(code) {
+            Ok(expr) => expr,
+            Err(_) => return false,
+        };
+
+        loop {
+            expr = match expr {
+                Expr::Reference(e) => *e.expr,
+                Expr::Paren(e) => *e.expr,
+                Expr::Group(e) => *e.expr,
+                _ => break,
+            };
+        }
+
+        let instruction = match expr {
+            Expr::Lit(e) => match e.lit {
+                Lit::Str(s) if escape => Instruction::Escaped(s.value()),
+                Lit::Str(s) => Instruction::Text(s.value()),
+                Lit::Int(i) => Instruction::Text(i.base10_digits().to_owned()),
+                Lit::Bool(b) => Instruction::Text(b.value.to_string()),
+                _ => return false,
+            },
+            Expr::Field(e) if !self.local_fields => match (*e.base, e.member) {
+                (Expr::Path(base), Member::Named(name)) if base.path.is_ident("self") => {
+                    Instruction::Field {
+                        name: name.to_string(),
+                        escape,
+                    }
+                }
+                _ => return false,
+            },
+            Expr::Path(e) if self.local_fields && e.qself.is_none() => {
+                match e.path.get_ident() {
+                    Some(name) => Instruction::Field {
+                        name: name.to_string(),
+                        escape,
+                    },
+                    None => return false,
+                }
+            }
+            _ => return false,
+        };
+
+        match instruction {
+            Instruction::Text(text) => push_text(program, text),
+            _ => program.push(instruction),
+        }
+        true
+    }
+
+    fn write_code(
+        &self,
+        input: &Path,
+        code: &str,
+        program: &mut Vec<Instruction>,
+        path_stack: &mut Vec<PathBuf>,
+    ) -> Result<bool, Error> {
+        let code = code.trim();
+        if code.is_empty() {
+            return Ok(true);
+        }
+
+        let mac = match syn::parse_str::<Stmt>(code) {
+            Ok(Stmt::Macro(m)) => m.mac,
+            Ok(Stmt::Expr(Expr::Macro(m), _)) => m.mac,
+            _ => return Ok(false),
+        };
+
+        match include_path(&mac) {
+            Some(arg) if !arg.ends_with(".rs") => {
+                // same rule as `include!` macro resolved by the compiler
+                let child = if Path::new(&arg).is_absolute() {
+                    PathBuf::from(&arg[1..])
+                } else {
+                    input.parent().unwrap().join(&arg)
+                };
+
+                self.load_file(&child, program, path_stack)
+            }
+            _ => Ok(false),
+        }
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self {
+            delimiter: '%',
+            escape: true,
+            rm_whitespace: false,
+            rm_newline: false,
+            local_fields: false,
+        }
+    }
+}
+
+// concatenate repeated text instructions
+fn push_text(program: &mut Vec<Instruction>, text: String) {
+    if let Some(Instruction::Text(prev)) = program.last_mut() {
+        prev.push_str(&text);
+    } else {
+        program.push(Instruction::Text(text));
+    }
+}
+
+fn include_path(mac: &Macro) -> Option<String> {
+    if mac.path.is_ident("include") {
+        syn::parse2::<syn::LitStr>(mac.tokens.clone())
+            .ok()
+            .map(|l| l.value())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn load(
+        interpreter: &Interpreter,
+        name: &str,
+        files: &[(&str, &str)],
+    ) -> Option<Vec<Instruction>> {
+        let dir = std::env::temp_dir()
+            .join("sailfish-interpreter-tests")
+            .join(name);
+        fs::create_dir_all(&dir).unwrap();
+        for (file, content) in files {
+            fs::write(dir.join(file), content).unwrap();
+        }
+
+        interpreter.load(&dir.join(files[0].0)).unwrap()
+    }
+
+    fn text(s: &str) -> Instruction {
+        Instruction::Text(s.to_owned())
+    }
+
+    fn field(name: &str, escape: bool) -> Instruction {
+        Instruction::Field {
+            name: name.to_owned(),
+            escape,
+        }
+    }
+
+    #[test]
+    fn interpret_simple() {
+        let program = load(
+            &Interpreter::new(),
+            "simple",
+            &[
+                (
+                    "index.stpl",
+                    "<h1><%= self.title %></h1><%# comment %>\n<% include!(\"footer.stpl\"); %>",
+                ),
+                ("footer.stpl", "<%- &self.footer %> <%= 42 %><%= \"<>\" %>"),
+            ],
+        );
+
+        assert_eq!(
+            program,
+            Some(vec![
+                text("<h1>"),
+                field("title", true),
+                text("</h1>\n"),
+                field("footer", false),
+                text(" 42"),
+                Instruction::Escaped("<>".to_owned()),
+            ])
+        );
+    }
+
+    #[test]
+    fn interpret_local_fields() {
+        let interpreter = Interpreter::new().local_fields(true).escape(false);
+        let program = load(&interpreter, "local", &[("index.stpl", "<%= title %>")]);
+        assert_eq!(program, Some(vec![field("title", false)]));
+
+        let program = load(
+            &interpreter,
+            "local_self",
+            &[("index.stpl", "<%= self.title %>")],
+        );
+        assert_eq!(program, None);
+    }
+
+    #[test]
+    fn interpret_unsupported() {
+        let interpreter = Interpreter::new();
+        for (i, source) in [
+            "<% for _ in 0..3 { %>a<% } %>",
+            "<%= self.title | upper %>",
+            "<%= self.title.len() %>",
+            "<%+ self.child %>",
+            "<%@ extends \"base.stpl\" %>",
+            "<% include!(\"helper.rs\"); %>",
+            "<script>var title = <%= self.title %>;</script>",
+        ]
+        .iter()
+        .enumerate()
+        {
+            let name = format!("unsupported_{}", i);
+            assert_eq!(load(&interpreter, &name, &[("index.stpl", source)]), None);
+        }
+    }
+}
@@ -6,6 +6,9 @@ mod error;
 mod analyzer;
 mod compiler;
 mod config;
+mod context;
+mod formatter;
+mod interpreter;
 mod optimizer;
 mod parser;
 mod resolver;
@@ -13,8 +16,11 @@ mod translator;
 mod util;
 
 pub use compiler::Compiler;
-pub use config::Config;
-pub use error::{Error, ErrorKind};
+pub use config::{Config, EscapeMode};
+pub use error::{Error, ErrorKind, Report};
+pub use formatter::Formatter;
+pub use interpreter::{Instruction, Interpreter};
+pub use parser::{ParseStream, Parser, Token, TokenKind};
 
 #[cfg(feature = "procmacro")]
 #[doc(hidden)]
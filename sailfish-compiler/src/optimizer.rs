@@ -139,7 +139,10 @@ impl VisitMut for OptmizerImpl {
             let mut new_stmts = syn::parse2::<Block>(quote! {{
                 __sf_rt::render_text!(__sf_buf, #previous);
                 #stmt
-                unsafe { __sf_buf._set_len(__sf_buf.len() - #sf_len); }
+                unsafe {
+                    let __sf_b = __sf_rt::Sink::buffer(__sf_buf);
+                    __sf_b._set_len(__sf_b.len() - #sf_len);
+                }
             }})
             .unwrap();
 
@@ -150,29 +153,29 @@ impl VisitMut for OptmizerImpl {
     }
 
     fn visit_stmt_macro_mut(&mut self, i: &mut StmtMacro) {
-        if let Some(v) = get_rendertext_value(&i.mac) {
-            if let Some(ts) = self.apply_optimizations(v) {
-                i.mac.tokens = ts;
-                return;
-            }
+        if let Some(v) = get_rendertext_value(&i.mac)
+            && let Some(ts) = self.apply_optimizations(v)
+        {
+            i.mac.tokens = ts;
+            return;
         }
 
         syn::visit_mut::visit_stmt_macro_mut(self, i);
     }
 
     fn visit_expr_macro_mut(&mut self, i: &mut ExprMacro) {
-        if let Some(v) = get_rendertext_value(&i.mac) {
-            if let Some(ts) = self.apply_optimizations(v) {
-                i.mac.tokens = ts;
-                return;
-            }
+        if let Some(v) = get_rendertext_value(&i.mac)
+            && let Some(ts) = self.apply_optimizations(v)
+        {
+            i.mac.tokens = ts;
+            return;
         }
 
         syn::visit_mut::visit_expr_macro_mut(self, i);
     }
 }
 
-fn remove_whitespace(v: &str) -> String {
+pub(crate) fn remove_whitespace(v: &str) -> String {
     let mut buffer = String::new();
     let mut it = v.lines().peekable();
     if let Some(line) = it.next() {
@@ -199,7 +202,7 @@ fn remove_whitespace(v: &str) -> String {
     buffer
 }
 
-fn remove_newlines(v: &str) -> String {
+pub(crate) fn remove_newlines(v: &str) -> String {
     v.replace(['\n', '\r'], "")
 }
 
@@ -222,8 +225,8 @@ fn get_rendertext_value(mac: &Macro) -> Option<String> {
 
     let mut it = mac.path.segments.iter();
 
-    if it.next().map_or(false, |s| s.ident == "__sf_rt")
-        && it.next().map_or(false, |s| s.ident == "render_text")
+    if it.next().is_some_and(|s| s.ident == "__sf_rt")
+        && it.next().is_some_and(|s| s.ident == "render_text")
         && it.next().is_none()
     {
         let tokens = mac.tokens.clone();
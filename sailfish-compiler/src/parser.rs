@@ -44,6 +44,7 @@ impl Parser {
             original_source: source,
             source,
             delimiter: self.delimiter,
+            trim_next: false,
         }
     }
 }
@@ -56,6 +57,15 @@ impl Default for Parser {
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum TokenKind {
+    Extends,
+    BlockStart,
+    BlockEnd,
+    Super,
+    Flush,
+    MacroStart,
+    MacroEnd,
+    MacroCall,
+    Import,
     NestedTemplateOnce,
     BufferedCode { escape: bool },
     Code,
@@ -102,6 +112,8 @@ pub struct ParseStream<'a> {
     pub(crate) original_source: &'a str,
     source: &'a str,
     delimiter: char,
+    // whether the whitespaces after the last tag should be removed
+    trim_next: bool,
 }
 
 impl<'a> ParseStream<'a> {
@@ -114,24 +126,43 @@ impl<'a> ParseStream<'a> {
     //     self.source.is_empty()
     // }
 
+    /// Collect all tokens, or all errors if the source contains errors
     pub fn into_vec(self) -> Result<Vec<Token<'a>>, Error> {
         let mut vec = Vec::new();
+        let mut errors = Vec::new();
         for token in self {
-            vec.push(token?);
+            match token {
+                Ok(token) => vec.push(token),
+                Err(e) => errors.push(e),
+            }
         }
 
-        Ok(vec)
+        match Error::from_errors(errors) {
+            Some(e) => Err(e),
+            None => Ok(vec),
+        }
     }
 
-    fn error(&self, msg: &str) -> Error {
+    fn error(&self, msg: &str, length: usize) -> Error {
         let offset = self.original_source.len() - self.source.len();
         make_error!(
             ErrorKind::ParseError(msg.to_owned()),
             source = self.original_source.to_owned(),
-            offset
+            offset,
+            length
         )
     }
 
+    // unterminated tag, which cannot be recovered from, so the rest of the source
+    // is skipped
+    fn unterminated_error(&mut self, msg: &str) -> Error {
+        let mut err = self.error(msg, self.block_delimiter.0.len());
+        err.details.help =
+            Some(format!("close the tag with `{}`", self.block_delimiter.1));
+        self.source = &self.source[self.source.len()..];
+        err
+    }
+
     fn offset(&self) -> usize {
         self.original_source.len() - self.source.len()
     }
@@ -148,6 +179,11 @@ impl<'a> ParseStream<'a> {
         let mut start = self.block_delimiter.0.len();
         let mut token_kind = TokenKind::Code;
 
+        // whitespaces before the tag were already removed by `tokenize_text`
+        if has_trim_marker(&self.source[start..]) {
+            start += 1;
+        }
+
         // read flags
         match self.source.as_bytes().get(start).copied() {
             Some(b'#') => {
@@ -166,6 +202,9 @@ impl<'a> ParseStream<'a> {
                 token_kind = TokenKind::NestedTemplateOnce;
                 start += 1;
             }
+            Some(b'@') => {
+                return self.tokenize_directive(start + 1);
+            }
             _ => {}
         }
 
@@ -180,13 +219,14 @@ impl<'a> ParseStream<'a> {
         }
 
         if token_kind == TokenKind::Comment {
-            let pos = self.source[start..]
-                .find(&*self.block_delimiter.1)
-                .ok_or_else(|| self.error("Unterminated comment block"))?;
+            let pos = match self.source[start..].find(&*self.block_delimiter.1) {
+                Some(pos) => pos,
+                None => return Err(self.unterminated_error("Unterminated comment block")),
+            };
 
             self.take_n(start);
             let token = Token {
-                content: self.source[..pos].trim_end(),
+                content: self.strip_trim_marker(&self.source[..pos]).trim_end(),
                 offset: self.offset(),
                 kind: token_kind,
             };
@@ -200,9 +240,9 @@ impl<'a> ParseStream<'a> {
         {
             // closing bracket was found
             self.take_n(start);
-            let s = &self.source[..pos - self.block_delimiter.1.len()].trim_end_matches(
-                |c| matches!(c, ' ' | '\t' | '\r' | '\u{000B}' | '\u{000C}'),
-            );
+            let s = self
+                .strip_trim_marker(&self.source[..pos - self.block_delimiter.1.len()])
+                .trim_end_matches([' ', '\t', '\r', '\u{000B}', '\u{000C}']);
             let token = Token {
                 content: s,
                 offset: self.offset(),
@@ -211,8 +251,75 @@ impl<'a> ParseStream<'a> {
             self.take_n(pos);
             Ok(token)
         } else {
-            Err(self.error("Unterminated code block"))
+            Err(self.unterminated_error("Unterminated code block"))
+        }
+    }
+
+    fn tokenize_directive(&mut self, mut start: usize) -> Result<Token<'a>, Error> {
+        // skip whitespaces
+        for ch in self.source.bytes().skip(start) {
+            match ch {
+                b' ' | b'\t' | b'\n'..=b'\r' => {
+                    start += 1;
+                }
+                _ => break,
+            }
         }
+
+        let pos = match find_block_end(&self.source[start..], &self.block_delimiter.1) {
+            Some(pos) => pos,
+            None => return Err(self.unterminated_error("Unterminated directive block")),
+        };
+
+        self.take_n(start);
+        let directive = self
+            .strip_trim_marker(&self.source[..pos - self.block_delimiter.1.len()])
+            .trim_end();
+        let name_len = directive
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(directive.len());
+        let (name, args) = directive.split_at(name_len);
+        let args_offset =
+            self.offset() + name_len + (args.len() - args.trim_start().len());
+        let args = args.trim_start();
+
+        let kind = match (name, args) {
+            ("extends", _) => TokenKind::Extends,
+            ("block", _) => TokenKind::BlockStart,
+            ("endblock", "") => TokenKind::BlockEnd,
+            ("super", "") | ("super", "()") => TokenKind::Super,
+            ("flush", "") => TokenKind::Flush,
+            ("macro", _) => TokenKind::MacroStart,
+            ("endmacro", "") => TokenKind::MacroEnd,
+            ("call", _) => TokenKind::MacroCall,
+            ("import", _) => TokenKind::Import,
+            ("endblock", _) | ("super", _) | ("flush", _) | ("endmacro", _) => {
+                let msg = format!("Unexpected arguments for `{}` directive", name);
+                let err = self.error(&msg, directive.len());
+                // skip the directive and continue parsing
+                self.take_n(pos);
+                return Err(err);
+            }
+            _ => {
+                let mut err =
+                    self.error(&format!("Unknown directive: `{}`", name), name_len);
+                err.details.help = Some(
+                    "available directives are `extends`, `block`, `endblock`, `super`, \
+                     `flush`, `macro`, `endmacro`, `call` and `import`"
+                        .to_owned(),
+                );
+                self.take_n(pos);
+                return Err(err);
+            }
+        };
+
+        let token = Token {
+            content: if kind == TokenKind::Super { "" } else { args },
+            offset: args_offset,
+            kind,
+        };
+        self.take_n(pos);
+        Ok(token)
     }
 
     fn tokenize_text(&mut self) -> Result<Token<'a>, Error> {
@@ -222,13 +329,47 @@ impl<'a> ParseStream<'a> {
             .source
             .find(&*self.block_delimiter.0)
             .unwrap_or(self.source.len());
+        let mut content = self.take_n(end);
+
+        // `<%_` removes the spaces and tabs before the tag
+        if self
+            .source
+            .get(self.block_delimiter.0.len()..)
+            .is_some_and(has_trim_marker)
+        {
+            content = content.trim_end_matches([' ', '\t']);
+        }
+
         let token = Token {
-            content: self.take_n(end),
+            content,
             offset,
             kind: TokenKind::Text,
         };
         Ok(token)
     }
+
+    /// Strip `_` modifier before the closing delimiter
+    ///
+    /// `_%>` removes the spaces and tabs after the tag, and also the following
+    /// newline.
+    fn strip_trim_marker(&mut self, content: &'a str) -> &'a str {
+        match content.strip_suffix('_') {
+            Some(stripped) if !stripped.ends_with(is_ident_char) => {
+                self.trim_next = true;
+                stripped
+            }
+            _ => content,
+        }
+    }
+
+    fn skip_trimmed_whitespaces(&mut self) {
+        let trimmed = self.source.trim_start_matches([' ', '\t']);
+        let trimmed = trimmed
+            .strip_prefix("\r\n")
+            .or_else(|| trimmed.strip_prefix('\n'))
+            .unwrap_or(trimmed);
+        self.take_n(self.source.len() - trimmed.len());
+    }
 }
 
 impl<'a> Default for ParseStream<'a> {
@@ -238,6 +379,7 @@ impl<'a> Default for ParseStream<'a> {
             original_source: "",
             source: "",
             delimiter: '%',
+            trim_next: false,
         }
     }
 }
@@ -246,6 +388,11 @@ impl<'a> Iterator for ParseStream<'a> {
     type Item = Result<Token<'a>, Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if self.trim_next {
+            self.trim_next = false;
+            self.skip_trimmed_whitespaces();
+        }
+
         if self.source.is_empty() {
             return None;
         }
@@ -269,7 +416,11 @@ impl<'a> Iterator for ParseStream<'a> {
                 Ok(token)
             }
         } else {
-            self.tokenize_text()
+            match self.tokenize_text() {
+                // the text was removed by whitespace control
+                Ok(token) if token.as_str().is_empty() => return self.next(),
+                token => token,
+            }
         };
 
         Some(token)
@@ -284,6 +435,17 @@ impl<'a> TryInto<Vec<Token<'a>>> for ParseStream<'a> {
     }
 }
 
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Check if the tag content starts with `_` modifier (e.g. `<%_ code %>`)
+fn has_trim_marker(content: &str) -> bool {
+    content
+        .strip_prefix('_')
+        .is_some_and(|rest| !rest.starts_with(is_ident_char))
+}
+
 fn find_block_end(haystack: &str, delimiter: &str) -> Option<usize> {
     let mut remain = haystack;
 
@@ -298,7 +460,7 @@ fn find_block_end(haystack: &str, delimiter: &str) -> Option<usize> {
             },
             b'\"' => {
                 // check if the literal is a raw string
-                for (i, byte) in remain[..pos].as_bytes().iter().enumerate().rev() {
+                for (i, byte) in remain.as_bytes()[..pos].iter().enumerate().rev() {
                     match byte {
                         b'#' => {}
                         b'r' => {
@@ -497,4 +659,152 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn directives() {
+        let src =
+            "<%@ extends \"base.stpl\" %><%@block title%>a<%@ super() %><%@ endblock %>";
+        let parser = Parser::new();
+        let tokens = parser.parse(src).into_vec().unwrap();
+        assert_eq!(
+            &tokens,
+            &[
+                Token {
+                    content: "\"base.stpl\"",
+                    offset: 12,
+                    kind: TokenKind::Extends
+                },
+                Token {
+                    content: "title",
+                    offset: 35,
+                    kind: TokenKind::BlockStart
+                },
+                Token {
+                    content: "a",
+                    offset: 42,
+                    kind: TokenKind::Text
+                },
+                Token {
+                    content: "",
+                    offset: 52,
+                    kind: TokenKind::Super
+                },
+                Token {
+                    content: "",
+                    offset: 69,
+                    kind: TokenKind::BlockEnd
+                },
+            ]
+        );
+
+        let err = parser
+            .parse("<%@ include \"a.stpl\" %>")
+            .into_vec()
+            .unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::ParseError(_)));
+        let err = parser
+            .parse("<%@ endblock title %>")
+            .into_vec()
+            .unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::ParseError(_)));
+
+        let tokens = parser.parse("a<%@ flush %>b").into_vec().unwrap();
+        assert_eq!(tokens[1].kind(), TokenKind::Flush);
+        let err = parser.parse("<%@ flush 1 %>").into_vec().unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::ParseError(_)));
+
+        let src = "<%@ import \"m.stpl\" %><%@ macro card(title: &str) %><%@ endmacro %>\
+                   <%@ call card(\"a\") %>";
+        let tokens = parser.parse(src).into_vec().unwrap();
+        let kinds: Vec<_> = tokens.iter().map(|t| t.kind()).collect();
+        assert_eq!(
+            kinds,
+            [
+                TokenKind::Import,
+                TokenKind::MacroStart,
+                TokenKind::MacroEnd,
+                TokenKind::MacroCall
+            ]
+        );
+        assert_eq!(tokens[1].as_str(), "card(title: &str)");
+        assert_eq!(tokens[3].as_str(), "card(\"a\")");
+        let err = parser.parse("<%@ endmacro card %>").into_vec().unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::ParseError(_)));
+    }
+
+    #[test]
+    fn multiple_errors() {
+        let src = "<%@ include %>a<%@ flush 1 %>b<%= c";
+        let err = Parser::new().parse(src).into_vec().unwrap_err();
+        let offsets: Vec<_> = std::iter::once(&err)
+            .chain(err.related())
+            .map(|e| e.offset.unwrap())
+            .collect();
+        assert_eq!(offsets, [4, 19, 30]);
+    }
+
+    #[test]
+    fn whitespace_control() {
+        let src =
+            "a\n  <%_ for x in xs { _%>\n  <%= x _%>  \n<%_ } _%>\r\nb <%= y_%> <%-_x %>";
+        let parser = Parser::new();
+        let tokens = parser.parse(src).into_vec().unwrap();
+        assert_eq!(
+            &tokens,
+            &[
+                Token {
+                    content: "a\n",
+                    offset: 0,
+                    kind: TokenKind::Text
+                },
+                Token {
+                    content: "for x in xs {",
+                    offset: 8,
+                    kind: TokenKind::Code
+                },
+                Token {
+                    content: "  ",
+                    offset: 26,
+                    kind: TokenKind::Text
+                },
+                Token {
+                    content: "x",
+                    offset: 32,
+                    kind: TokenKind::BufferedCode { escape: true }
+                },
+                Token {
+                    content: "}",
+                    offset: 44,
+                    kind: TokenKind::Code
+                },
+                Token {
+                    content: "b ",
+                    offset: 51,
+                    kind: TokenKind::Text
+                },
+                Token {
+                    content: "y_",
+                    offset: 57,
+                    kind: TokenKind::BufferedCode { escape: true }
+                },
+                Token {
+                    content: " ",
+                    offset: 61,
+                    kind: TokenKind::Text
+                },
+                Token {
+                    content: "_x",
+                    offset: 65,
+                    kind: TokenKind::BufferedCode { escape: false }
+                },
+            ]
+        );
+
+        let tokens = parser
+            .parse("<%# comment _%>\n<%@ flush _%>\n")
+            .into_vec()
+            .unwrap();
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens[1].kind(), TokenKind::Flush);
+    }
 }
@@ -1,4 +1,4 @@
-use proc_macro2::{Span, TokenStream};
+use proc_macro2::{Group, Span, TokenStream, TokenTree};
 use quote::{quote, TokenStreamExt};
 use std::collections::hash_map::DefaultHasher;
 use std::env;
@@ -7,23 +7,27 @@ use std::io::Write;
 use std::iter;
 use std::path::{Path, PathBuf};
 use syn::parse::{ParseStream, Parser, Result as ParseResult};
-use syn::punctuated::Punctuated;
-use syn::{Fields, Ident, ItemStruct, LitBool, LitChar, LitStr, Token};
+use syn::{
+    Attribute, Fields, Generics, Ident, Item, ItemEnum, ItemStruct, Lit, LitBool,
+    LitChar, LitStr, Token, Type,
+};
 
-use crate::compiler::Compiler;
-use crate::config::Config;
+use crate::compiler::{CompiledCode, Compiler};
+use crate::config::{Config, EscapeMode};
 use crate::error::*;
 use crate::util::filetime;
 
 // options for `template` attributes
-#[derive(Default)]
+#[derive(Clone, Default)]
 struct DeriveTemplateOptions {
     found_keys: Vec<Ident>,
     path: Option<LitStr>,
+    source: Option<LitStr>,
     delimiter: Option<LitChar>,
-    escape: Option<LitBool>,
+    escape: Option<EscapeMode>,
     rm_whitespace: Option<LitBool>,
     rm_newline: Option<LitBool>,
+    filters: Option<LitStr>,
 }
 
 impl DeriveTemplateOptions {
@@ -34,7 +38,7 @@ impl DeriveTemplateOptions {
                 s.parse::<Token![=]>()?;
 
                 // check if argument is repeated
-                if self.found_keys.iter().any(|e| *e == key) {
+                if self.found_keys.contains(&key) {
                     return Err(syn::Error::new(
                         key.span(),
                         format!("Argument `{}` was repeated.", key),
@@ -43,14 +47,41 @@ impl DeriveTemplateOptions {
 
                 if key == "path" {
                     self.path = Some(s.parse::<LitStr>()?);
+                } else if key == "source" {
+                    self.source = Some(s.parse::<LitStr>()?);
                 } else if key == "delimiter" {
                     self.delimiter = Some(s.parse::<LitChar>()?);
                 } else if key == "escape" {
-                    self.escape = Some(s.parse::<LitBool>()?);
+                    self.escape = Some(match s.parse::<Lit>()? {
+                        Lit::Bool(b) => EscapeMode::from(b.value),
+                        Lit::Str(mode) => {
+                            EscapeMode::parse(&mode.value()).ok_or_else(|| {
+                                syn::Error::new(
+                                    mode.span(),
+                                    "`escape` option must be one of \"html\", \"xml\", \"json\", \"none\" or a path to the escaper type",
+                                )
+                            })?
+                        }
+                        lit => {
+                            return Err(syn::Error::new(
+                                lit.span(),
+                                "expected boolean or string literal",
+                            ));
+                        }
+                    });
                 } else if key == "rm_whitespace" {
                     self.rm_whitespace = Some(s.parse::<LitBool>()?);
                 } else if key == "rm_newline" {
                     self.rm_newline = Some(s.parse::<LitBool>()?);
+                } else if key == "filters" {
+                    let filters = s.parse::<LitStr>()?;
+                    if filters.parse::<syn::Path>().is_err() {
+                        return Err(syn::Error::new(
+                            filters.span(),
+                            "`filters` option must be a path to the module",
+                        ));
+                    }
+                    self.filters = Some(filters);
                 } else {
                     return Err(syn::Error::new(
                         key.span(),
@@ -73,12 +104,41 @@ impl DeriveTemplateOptions {
     }
 }
 
+impl DeriveTemplateOptions {
+    fn from_attrs(attrs: &[Attribute]) -> Result<Self, syn::Error> {
+        let mut options = Self::default();
+        for attr in attrs {
+            if attr.path().is_ident("template") {
+                attr.parse_args_with(options.parser())?;
+            }
+        }
+        Ok(options)
+    }
+
+    /// Fill the options which are not specified in the enum variant with the
+    /// options of the enum
+    fn with_defaults(mut self, defaults: &DeriveTemplateOptions) -> Self {
+        if self.path.is_none() && self.source.is_none() {
+            self.path = defaults.path.clone();
+            self.source = defaults.source.clone();
+        }
+        self.delimiter = self.delimiter.or_else(|| defaults.delimiter.clone());
+        self.escape = self.escape.or_else(|| defaults.escape.clone());
+        self.rm_whitespace = self
+            .rm_whitespace
+            .or_else(|| defaults.rm_whitespace.clone());
+        self.rm_newline = self.rm_newline.or_else(|| defaults.rm_newline.clone());
+        self.filters = self.filters.or_else(|| defaults.filters.clone());
+        self
+    }
+}
+
 fn merge_config_options(config: &mut Config, options: &DeriveTemplateOptions) {
     if let Some(ref delimiter) = options.delimiter {
         config.delimiter = delimiter.value();
     }
     if let Some(ref escape) = options.escape {
-        config.escape = escape.value;
+        config.escape = escape.clone();
     }
     if let Some(ref rm_whitespace) = options.rm_whitespace {
         config.rm_whitespace = rm_whitespace.value;
@@ -86,6 +146,116 @@ fn merge_config_options(config: &mut Config, options: &DeriveTemplateOptions) {
     if let Some(ref rm_newline) = options.rm_newline {
         config.rm_newline = rm_newline.value;
     }
+    if let Some(ref filters) = options.filters {
+        config.filters.push(filters.value());
+    }
+}
+
+// options for `template` attributes of the fields
+#[derive(Default)]
+struct FieldOptions {
+    rename: Option<Ident>,
+    skip: bool,
+}
+
+impl FieldOptions {
+    fn from_attrs(attrs: &[Attribute]) -> Result<Self, syn::Error> {
+        let mut options = Self::default();
+        for attr in attrs {
+            if attr.path().is_ident("template") {
+                attr.parse_args_with(options.parser())?;
+            }
+        }
+        Ok(options)
+    }
+
+    fn parser(&mut self) -> impl Parser + '_ {
+        move |s: ParseStream| -> ParseResult<()> {
+            while !s.is_empty() {
+                let key = s.parse::<Ident>()?;
+
+                if key == "rename" {
+                    if self.rename.is_some() {
+                        return Err(syn::Error::new(
+                            key.span(),
+                            "Argument `rename` was repeated.",
+                        ));
+                    }
+                    s.parse::<Token![=]>()?;
+                    let name = s.parse::<LitStr>()?;
+                    self.rename = Some(name.parse::<Ident>().map_err(|_| {
+                        syn::Error::new(
+                            name.span(),
+                            "`rename` option must be an identifier",
+                        )
+                    })?);
+                } else if key == "skip" {
+                    self.skip = true;
+                } else {
+                    return Err(syn::Error::new(
+                        key.span(),
+                        format!("Unknown option: `{}`", key),
+                    ));
+                }
+
+                // consume comma token
+                if s.is_empty() {
+                    break;
+                } else {
+                    s.parse::<Token![,]>()?;
+                }
+            }
+
+            if self.skip && self.rename.is_some() {
+                return Err(
+                    s.error("`rename` and `skip` options cannot be used together")
+                );
+            }
+
+            Ok(())
+        }
+    }
+}
+
+/// Pattern which binds the fields to the local variables
+///
+/// Fields are bound to their names, or `_0`, `_1`, ... for tuple fields, unless
+/// `rename` or `skip` option is specified in the `template` attribute. Returns
+/// the pattern and the names of the local variables.
+fn bind_fields(
+    path: TokenStream,
+    fields: &Fields,
+) -> Result<(TokenStream, Vec<Ident>), syn::Error> {
+    let mut names = Vec::new();
+    let mut bindings = Vec::new();
+    for (i, field) in fields.iter().enumerate() {
+        let options = FieldOptions::from_attrs(&field.attrs)?;
+        let name = if options.skip {
+            None
+        } else {
+            let name = match (options.rename, &field.ident) {
+                (Some(name), _) => name,
+                (None, Some(ident)) => ident.clone(),
+                (None, None) => Ident::new(&format!("_{}", i), Span::call_site()),
+            };
+            names.push(name.clone());
+            Some(name)
+        };
+        bindings.push(match (&field.ident, name) {
+            (Some(ident), Some(name)) if *ident == name => quote! { #ident },
+            (Some(ident), Some(name)) => quote! { #ident: #name },
+            (Some(ident), None) => quote! { #ident: _ },
+            (None, Some(name)) => quote! { #name },
+            (None, None) => quote! { _ },
+        });
+    }
+
+    let pattern = match *fields {
+        Fields::Named(_) => quote! { #path { #(#bindings),* } },
+        Fields::Unnamed(_) => quote! { #path(#(#bindings),*) },
+        Fields::Unit => path,
+    };
+    Ok((pattern, names))
 }
 
 fn resolve_template_file(path: &str, template_dirs: &[PathBuf]) -> Option<PathBuf> {
@@ -150,18 +320,219 @@ fn with_compiler<T, F: FnOnce(Compiler) -> Result<T, Error>>(
     apply(compiler)
 }
 
-fn derive_template_common_impl(
+/// Compile the template source embedded in `#[template(source = "...")]` attribute
+fn compile_inline_source(
+    config: Config,
+    source: &LitStr,
+    name: &str,
+) -> Result<TokenStream, syn::Error> {
+    let compiled = with_compiler(config, |compiler| {
+        compiler.compile_named_str(&source.value(), name)
+    })
+    .map_err(|e| syn::Error::new(source.span(), e))?;
+
+    parse_compiled_code(compiled, source.span())
+}
+
+/// Parse the generated code, and set the spans of the tokens which come from the
+/// template code to `span`
+///
+/// Embedded templates have no output file to point at, so the errors in the
+/// template code (e.g. type errors) are reported at `span`. Other tokens keep the
+/// call site spans.
+///
+/// Clippy lints are disabled since the generated code is not written by users.
+fn parse_compiled_code(
+    compiled: CompiledCode,
+    span: Span,
+) -> Result<TokenStream, syn::Error> {
+    let mut tokens = compiled.code.parse::<TokenStream>().map_err(|e| {
+        syn::Error::new(span, format!("Failed to parse compiled template: {}", e))
+    })?;
+
+    // the marks do not match if the code was not parsed back into the same tokens
+    if count_tokens(tokens.clone()) == compiled.template_tokens.len() {
+        tokens = respan(tokens, &mut compiled.template_tokens.into_iter(), span);
+    }
+    Ok(quote! {
+        #[allow(clippy::all, clippy::pedantic, clippy::nursery)]
+        #tokens
+    })
+}
+
+fn count_tokens(tokens: TokenStream) -> usize {
+    tokens
+        .into_iter()
+        .map(|tt| match tt {
+            TokenTree::Group(g) => 1 + count_tokens(g.stream()),
+            _ => 1,
+        })
+        .sum()
+}
+
+fn respan(
     tokens: TokenStream,
-) -> Result<(ItemStruct, TokenStream, TokenStream), syn::Error> {
-    let strct = syn::parse2::<ItemStruct>(tokens)?;
+    marks: &mut impl Iterator<Item = bool>,
+    span: Span,
+) -> TokenStream {
+    tokens
+        .into_iter()
+        .map(|mut tt| {
+            let is_template = marks.next().unwrap_or(false);
+            if let TokenTree::Group(ref g) = tt {
+                let mut group =
+                    Group::new(g.delimiter(), respan(g.stream(), marks, span));
+                group.set_span(if is_template { span } else { g.span() });
+                tt = TokenTree::Group(group);
+            } else if is_template {
+                tt.set_span(span);
+            }
+            tt
+        })
+        .collect()
+}
 
-    let mut all_options = DeriveTemplateOptions::default();
-    for attr in &strct.attrs {
-        if attr.path().is_ident("template") {
-            attr.parse_args_with(all_options.parser())?;
+/// Generate code which renders the template from its source file if possible.
+///
+/// Fields are passed to the interpreter through `Render` trait. If a field does
+/// not implement `Render`, the interpreter falls back to the compiled template.
+#[cfg(feature = "hot-reload")]
+fn hot_reload_prelude(
+    fields: &[Ident],
+    input_file: &Path,
+    config: &Config,
+    local_fields: bool,
+) -> TokenStream {
+    // the interpreter only supports HTML escaping
+    let escape = match config.escape {
+        EscapeMode::Html => true,
+        EscapeMode::None => false,
+        _ => return TokenStream::new(),
+    };
+    let path = input_file.to_string_lossy().into_owned();
+    let delimiter = config.delimiter;
+    let rm_whitespace = config.rm_whitespace;
+    let rm_newline = config.rm_newline;
+
+    let names = fields.iter().map(|f| f.to_string());
+    let accessors = fields.iter().map(|f| {
+        if local_fields {
+            quote! { #f }
+        } else {
+            quote! { self.#f }
+        }
+    });
+
+    quote! {
+        #[cfg(debug_assertions)]
+        {
+            use sailfish::runtime::hot_reload::{RenderFallback as _, RenderField as _};
+
+            let __sf_options = sailfish::runtime::hot_reload::Options {
+                path: #path,
+                delimiter: #delimiter,
+                escape: #escape,
+                rm_whitespace: #rm_whitespace,
+                rm_newline: #rm_newline,
+                local_fields: #local_fields,
+            };
+            let __sf_lookup = |__sf_name: &str, __sf_buf: &mut sailfish::runtime::Buffer, __sf_escape: bool|
+                    -> std::option::Option<std::result::Result<(), sailfish::runtime::RenderError>> {
+                match __sf_name {
+                    #(#names => (&sailfish::runtime::hot_reload::Field(&#accessors)).hot_render(__sf_buf, __sf_escape),)*
+                    _ => None,
+                }
+            };
+            if let Some(__sf_result) = sailfish::runtime::hot_reload::render(&__sf_options, sailfish::runtime::Sink::buffer(__sf_buf), &__sf_lookup) {
+                return __sf_result;
+            }
         }
     }
+}
 
+/// `Content-Type` of the output, which is determined by the escaper
+fn content_type(escape: &EscapeMode) -> TokenStream {
+    let escaper = match *escape {
+        EscapeMode::Html => quote! { sailfish::runtime::escape::Html },
+        EscapeMode::Xml => quote! { sailfish::runtime::escape::Xml },
+        EscapeMode::Json => quote! { sailfish::runtime::escape::Json },
+        EscapeMode::None => return quote! { "text/plain; charset=utf-8" },
+        EscapeMode::Custom(ref path) => path
+            .parse()
+            .expect("Internal error: escaper path was already validated"),
+    };
+    quote! { <#escaper as sailfish::runtime::escape::Escaper>::CONTENT_TYPE }
+}
+
+/// Implement the response traits of web frameworks enabled by the features
+fn derive_integration_impls(
+    input: &TemplateInput,
+    trait_path: &TokenStream,
+) -> TokenStream {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let mut output = TokenStream::new();
+
+    if cfg!(feature = "axum") {
+        output.append_all(quote! {
+            impl #impl_generics sailfish::integration::axum::IntoResponse for #name #ty_generics #where_clause {
+                fn into_response(self) -> sailfish::integration::axum::Response {
+                    sailfish::integration::axum::into_response(
+                        <Self as #trait_path>::CONTENT_TYPE,
+                        #trait_path::render_once(self),
+                    )
+                }
+            }
+        });
+    }
+
+    if cfg!(feature = "actix-web") {
+        output.append_all(quote! {
+            impl #impl_generics sailfish::integration::actix::Responder for #name #ty_generics #where_clause {
+                type Body = sailfish::integration::actix::BoxBody;
+
+                fn respond_to(self, _: &sailfish::integration::actix::HttpRequest) -> sailfish::integration::actix::HttpResponse {
+                    sailfish::integration::actix::into_response(
+                        <Self as #trait_path>::CONTENT_TYPE,
+                        #trait_path::render_once(self),
+                    )
+                }
+            }
+        });
+    }
+
+    if cfg!(feature = "hyper") {
+        output.append_all(quote! {
+            impl #impl_generics std::convert::From<#name #ty_generics> for sailfish::integration::hyper::Response #where_clause {
+                fn from(tpl: #name #ty_generics) -> Self {
+                    sailfish::integration::hyper::into_response(
+                        <#name #ty_generics as #trait_path>::CONTENT_TYPE,
+                        #trait_path::render_once(tpl),
+                    )
+                }
+            }
+        });
+    }
+
+    output
+}
+
+// struct or enum which derives the template traits
+struct TemplateInput {
+    ident: Ident,
+    generics: Generics,
+    // pattern which destructures `self` for `TemplateSimple` (`None` for enums)
+    pattern: Option<TokenStream>,
+}
+
+// compiled template of the struct or the enum variant
+struct CompiledTemplate {
+    include_bytes_seq: TokenStream,
+    compiled_tokens: TokenStream,
+    escape: EscapeMode,
+}
+
+fn read_config() -> Result<Config, syn::Error> {
     #[cfg(feature = "config")]
     let mut config = {
         let manifest_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").expect(
@@ -175,7 +546,7 @@ fn derive_template_common_impl(
     #[cfg(not(feature = "config"))]
     let mut config = Config::default();
 
-    if env::var("SAILFISH_INTEGRATION_TESTS").map_or(false, |s| s == "1") {
+    if env::var("SAILFISH_INTEGRATION_TESTS").is_ok_and(|s| s == "1") {
         let template_dir = env::current_dir()
             .unwrap()
             .ancestors()
@@ -189,9 +560,43 @@ fn derive_template_common_impl(
         config.template_dirs.push(template_dir);
     }
 
+    Ok(config)
+}
+
+/// Compile the template specified in `options`
+///
+/// `fields` are the names of the fields available in the template. `name` is
+/// reported in the rendering errors of inline templates. `span` is used for the
+/// errors which are not related to the options.
+fn compile_template(
+    mut config: Config,
+    options: &DeriveTemplateOptions,
+    fields: &[Ident],
+    local_fields: bool,
+    name: &str,
+    span: Span,
+) -> Result<CompiledTemplate, syn::Error> {
+    if let Some(ref source) = options.source {
+        if let Some(ref path) = options.path {
+            return Err(syn::Error::new(
+                path.span(),
+                "`path` and `source` options cannot be specified at the same time.",
+            ));
+        }
+
+        merge_config_options(&mut config, options);
+        let escape = config.escape.clone();
+        let compiled_tokens = compile_inline_source(config, source, name)?;
+        return Ok(CompiledTemplate {
+            include_bytes_seq: TokenStream::new(),
+            compiled_tokens,
+            escape,
+        });
+    }
+
     let input_file = {
-        let path = all_options.path.as_ref().ok_or_else(|| {
-            syn::Error::new(Span::call_site(), "`path` option must be specified.")
+        let path = options.path.as_ref().ok_or_else(|| {
+            syn::Error::new(span, "`path` or `source` option must be specified.")
         })?;
         resolve_template_file(&path.value(), &config.template_dirs)
             .and_then(|path| path.canonicalize().ok())
@@ -203,16 +608,34 @@ fn derive_template_common_impl(
             })?
     };
 
-    merge_config_options(&mut config, &all_options);
+    // `escape` option in the attribute takes precedence over the file name
+    if let Some(escape) = config.escape_for(&input_file) {
+        config.escape = escape.clone();
+    }
+    merge_config_options(&mut config, options);
+    let escape = config.escape.clone();
+
+    #[cfg(feature = "hot-reload")]
+    let hot_reload_tokens =
+        hot_reload_prelude(fields, &input_file, &config, local_fields);
 
-    // Template compilation through this proc-macro uses a caching mechanism. Output file
-    // names include a hash calculated from input file contents and compiler
-    // configuration. This way, existing files never need updating and can simply be
-    // re-used if they exist.
+    #[cfg(not(feature = "hot-reload"))]
+    let hot_reload_tokens = {
+        let _ = (fields, local_fields);
+        TokenStream::new()
+    };
+
+    // Output file paths include a hash calculated from input file contents and
+    // compiler configuration. This way, existing files never need updating if they
+    // are up to date. The file name is taken from the template so that it shows up
+    // in the compile errors.
     let mut output_file = PathBuf::from(env!("OUT_DIR"));
     output_file.push("templates");
     output_file.push(filename_hash(&input_file, &config));
-    output_file.set_extension("rs");
+    output_file.push(format!(
+        "{}.rs",
+        input_file.file_name().unwrap_or_default().to_string_lossy()
+    ));
 
     std::fs::create_dir_all(output_file.parent().unwrap()).unwrap();
 
@@ -277,7 +700,7 @@ fn derive_template_common_impl(
             Err(e) => panic!("{:?}: {}. Maybe try `cargo clean`?", lock_path, e),
         }
     })
-    .map_err(|e| syn::Error::new(Span::call_site(), e))?;
+    .map_err(|e| syn::Error::new(span, e))?;
 
     let input_file_string = input_file
         .to_str()
@@ -289,35 +712,178 @@ fn derive_template_common_impl(
         }
     }
 
-    let compiled_source = std::fs::read_to_string(&output_file).map_err(|e| {
+    // The compiled template is included from the output file instead of being
+    // parsed here, so that rustc reports the errors in the template code (e.g.
+    // type errors) at the lines of the output file. Each line ends with a comment
+    // pointing to the corresponding line of the template.
+    //
+    // Clippy lints are disabled since the generated code is not written by users.
+    let output_file_string = output_file.to_str().ok_or_else(|| {
         syn::Error::new(
-            Span::call_site(),
-            format!("Failed to read compiled template {:?}: {}", output_file, e),
-        )
-    })?;
-    let compiled_tokens = compiled_source.parse::<TokenStream>().map_err(|e| {
-        syn::Error::new(
-            Span::call_site(),
-            format!("Failed to parse compiled template {:?}: {}", output_file, e),
+            span,
+            format!("Non UTF-8 path to compiled template: {:?}", output_file),
         )
     })?;
+    let compiled_tokens = quote! {
+        #hot_reload_tokens
+        #[allow(clippy::all, clippy::pedantic, clippy::nursery)]
+        {
+            include!(#output_file_string)
+        }
+    };
 
-    Ok((strct, include_bytes_seq, compiled_tokens))
+    Ok(CompiledTemplate {
+        include_bytes_seq,
+        compiled_tokens,
+        escape,
+    })
+}
+
+fn derive_template_common_impl(
+    tokens: TokenStream,
+    local_fields: bool,
+) -> Result<(TemplateInput, TokenStream, TokenStream, TokenStream), syn::Error> {
+    let strct = match syn::parse2::<Item>(tokens)? {
+        Item::Struct(strct) => strct,
+        Item::Enum(enm) => return derive_enum_common_impl(enm),
+        item => {
+            return Err(syn::Error::new_spanned(
+                item,
+                "Templates can only be derived for structs and enums",
+            ));
+        }
+    };
+
+    let options = DeriveTemplateOptions::from_attrs(&strct.attrs)?;
+    let ident = &strct.ident;
+    let (pattern, fields) = if local_fields {
+        let (pattern, names) = bind_fields(quote! { #ident }, &strct.fields)?;
+        (Some(pattern), names)
+    } else {
+        if let Some(attr) = strct
+            .fields
+            .iter()
+            .flat_map(|f| &f.attrs)
+            .find(|attr| attr.path().is_ident("template"))
+        {
+            return Err(syn::Error::new_spanned(
+                attr,
+                "Field options are only available for `TemplateSimple` and enums",
+            ));
+        }
+        let names = strct
+            .fields
+            .iter()
+            .filter_map(|f| f.ident.clone())
+            .collect();
+        (None, names)
+    };
+    let template = compile_template(
+        read_config()?,
+        &options,
+        &fields,
+        local_fields,
+        &strct.ident.to_string(),
+        Span::call_site(),
+    )?;
+
+    let input = TemplateInput {
+        ident: strct.ident,
+        generics: strct.generics,
+        pattern,
+    };
+
+    Ok((
+        input,
+        template.include_bytes_seq,
+        template.compiled_tokens,
+        content_type(&template.escape),
+    ))
+}
+
+/// Compile the template of each variant, and dispatch to them with `match`
+/// expression.
+///
+/// Fields of the variant are bound to local variables with [`bind_fields`]. Options in the `template` attribute of the enum are used
+/// for the variants which do not specify them.
+fn derive_enum_common_impl(
+    enm: ItemEnum,
+) -> Result<(TemplateInput, TokenStream, TokenStream, TokenStream), syn::Error> {
+    if enm.variants.is_empty() {
+        return Err(syn::Error::new(
+            enm.ident.span(),
+            "You cannot derive templates for enum without variants",
+        ));
+    }
+
+    let defaults = DeriveTemplateOptions::from_attrs(&enm.attrs)?;
+    let config = read_config()?;
+
+    let mut include_bytes_seq = TokenStream::new();
+    let mut arms = TokenStream::new();
+    let mut escape: Option<EscapeMode> = None;
+
+    for variant in &enm.variants {
+        let options =
+            DeriveTemplateOptions::from_attrs(&variant.attrs)?.with_defaults(&defaults);
+        let ident = &variant.ident;
+        let (pattern, fields) = bind_fields(quote! { Self::#ident }, &variant.fields)?;
+        let template = compile_template(
+            config.clone(),
+            &options,
+            &fields,
+            true,
+            &format!("{}::{}", enm.ident, ident),
+            variant.ident.span(),
+        )?;
+
+        // `CONTENT_TYPE` is determined by the escaping mode
+        if *escape.get_or_insert_with(|| template.escape.clone()) != template.escape {
+            return Err(syn::Error::new(
+                variant.ident.span(),
+                "All variants must use the same escaping mode",
+            ));
+        }
+
+        let compiled_tokens = template.compiled_tokens;
+        include_bytes_seq.extend(template.include_bytes_seq);
+        arms.extend(quote! { #pattern => { #compiled_tokens; } });
+    }
+
+    let input = TemplateInput {
+        ident: enm.ident,
+        generics: enm.generics,
+        pattern: None,
+    };
+    let compiled_tokens = quote! { match self { #arms } };
+
+    Ok((
+        input,
+        include_bytes_seq,
+        compiled_tokens,
+        content_type(&escape.unwrap()),
+    ))
 }
 
 fn derive_template_once_only_impl(
-    strct: &ItemStruct,
+    input: &TemplateInput,
     include_bytes_seq: &TokenStream,
     compiled_tokens: &TokenStream,
+    content_type: &TokenStream,
 ) -> TokenStream {
-    let name = &strct.ident;
-    let (impl_generics, ty_generics, where_clause) = strct.generics.split_for_impl();
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
 
     // render_once method always results in the same code.
     // This method can be implemented in `sailfish` crate, but I found that performance
     // drops when the implementation is written in `sailfish` crate.
+    let integration_impls =
+        derive_integration_impls(input, &quote! { sailfish::TemplateOnce });
+
     quote! {
         impl #impl_generics sailfish::TemplateOnce for #name #ty_generics #where_clause {
+            const CONTENT_TYPE: &'static str = #content_type;
+
             fn render_once(mut self) -> sailfish::RenderResult {
                 use sailfish::runtime::{Buffer, SizeHint};
                 static SIZE_HINT: SizeHint = SizeHint::new();
@@ -329,7 +895,11 @@ fn derive_template_once_only_impl(
                 Ok(buf.into_string())
             }
 
-            fn render_once_to(mut self, __sf_buf: &mut sailfish::runtime::Buffer) -> std::result::Result<(), sailfish::runtime::RenderError> {
+            fn render_once_to(self, __sf_buf: &mut sailfish::runtime::Buffer) -> std::result::Result<(), sailfish::runtime::RenderError> {
+                sailfish::TemplateOnce::render_once_to_sink(self, __sf_buf)
+            }
+
+            fn render_once_to_sink<__SfSink: sailfish::runtime::Sink>(mut self, __sf_buf: &mut __SfSink) -> std::result::Result<(), sailfish::runtime::RenderError> {
                 // This line is required for cargo to track child templates
                 #include_bytes_seq;
 
@@ -339,16 +909,18 @@ fn derive_template_once_only_impl(
                 Ok(())
             }
         }
+
+        #integration_impls
     }
 }
 
 fn derive_template_mut_only_impl(
-    strct: &ItemStruct,
+    input: &TemplateInput,
     include_bytes_seq: &TokenStream,
     compiled_tokens: &TokenStream,
 ) -> TokenStream {
-    let name = &strct.ident;
-    let (impl_generics, ty_generics, where_clause) = strct.generics.split_for_impl();
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
 
     // This method can be implemented in `sailfish` crate, but I found that performance
     // drops when the implementation is written in `sailfish` crate.
@@ -366,6 +938,10 @@ fn derive_template_mut_only_impl(
             }
 
             fn render_mut_to(&mut self, __sf_buf: &mut sailfish::runtime::Buffer) -> std::result::Result<(), sailfish::runtime::RenderError> {
+                sailfish::TemplateMut::render_mut_to_sink(self, __sf_buf)
+            }
+
+            fn render_mut_to_sink<__SfSink: sailfish::runtime::Sink>(&mut self, __sf_buf: &mut __SfSink) -> std::result::Result<(), sailfish::runtime::RenderError> {
                 // This line is required for cargo to track child templates
                 #include_bytes_seq;
 
@@ -379,12 +955,12 @@ fn derive_template_mut_only_impl(
 }
 
 fn derive_template_only_impl(
-    strct: &ItemStruct,
+    input: &TemplateInput,
     include_bytes_seq: &TokenStream,
     compiled_tokens: &TokenStream,
 ) -> TokenStream {
-    let name = &strct.ident;
-    let (impl_generics, ty_generics, where_clause) = strct.generics.split_for_impl();
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
 
     // This method can be implemented in `sailfish` crate, but I found that performance
     // drops when the implementation is written in `sailfish` crate.
@@ -402,6 +978,10 @@ fn derive_template_only_impl(
             }
 
             fn render_to(&self, __sf_buf: &mut sailfish::runtime::Buffer) -> std::result::Result<(), sailfish::runtime::RenderError> {
+                sailfish::Template::render_to_sink(self, __sf_buf)
+            }
+
+            fn render_to_sink<__SfSink: sailfish::runtime::Sink>(&self, __sf_buf: &mut __SfSink) -> std::result::Result<(), sailfish::runtime::RenderError> {
                 // This line is required for cargo to track child templates
                 #include_bytes_seq;
 
@@ -415,34 +995,36 @@ fn derive_template_only_impl(
 }
 
 fn derive_template_once_impl(tokens: TokenStream) -> Result<TokenStream, syn::Error> {
-    let (strct, include_bytes_seq, compiled_tokens) =
-        derive_template_common_impl(tokens)?;
+    let (input, include_bytes_seq, compiled_tokens, content_type) =
+        derive_template_common_impl(tokens, false)?;
 
     let mut output = TokenStream::new();
 
     output.append_all(derive_template_once_only_impl(
-        &strct,
+        &input,
         &include_bytes_seq,
         &compiled_tokens,
+        &content_type,
     ));
 
     Ok(output)
 }
 
 fn derive_template_mut_impl(tokens: TokenStream) -> Result<TokenStream, syn::Error> {
-    let (strct, include_bytes_seq, compiled_tokens) =
-        derive_template_common_impl(tokens)?;
+    let (input, include_bytes_seq, compiled_tokens, content_type) =
+        derive_template_common_impl(tokens, false)?;
 
     let mut output = TokenStream::new();
 
     output.append_all(derive_template_once_only_impl(
-        &strct,
+        &input,
         &include_bytes_seq,
         &compiled_tokens,
+        &content_type,
     ));
 
     output.append_all(derive_template_mut_only_impl(
-        &strct,
+        &input,
         &include_bytes_seq,
         &compiled_tokens,
     ));
@@ -451,25 +1033,26 @@ fn derive_template_mut_impl(tokens: TokenStream) -> Result<TokenStream, syn::Err
 }
 
 fn derive_template_impl(tokens: TokenStream) -> Result<TokenStream, syn::Error> {
-    let (strct, include_bytes_seq, compiled_tokens) =
-        derive_template_common_impl(tokens)?;
+    let (input, include_bytes_seq, compiled_tokens, content_type) =
+        derive_template_common_impl(tokens, false)?;
 
     let mut output = TokenStream::new();
 
     output.append_all(derive_template_once_only_impl(
-        &strct,
+        &input,
         &include_bytes_seq,
         &compiled_tokens,
+        &content_type,
     ));
 
     output.append_all(derive_template_mut_only_impl(
-        &strct,
+        &input,
         &include_bytes_seq,
         &compiled_tokens,
     ));
 
     output.append_all(derive_template_only_impl(
-        &strct,
+        &input,
         &include_bytes_seq,
         &compiled_tokens,
     ));
@@ -478,37 +1061,28 @@ fn derive_template_impl(tokens: TokenStream) -> Result<TokenStream, syn::Error>
 }
 
 fn derive_template_simple_impl(tokens: TokenStream) -> Result<TokenStream, syn::Error> {
-    let (strct, include_bytes_seq, compiled_tokens) =
-        derive_template_common_impl(tokens)?;
+    let (input, include_bytes_seq, compiled_tokens, content_type) =
+        derive_template_common_impl(tokens, true)?;
 
-    let name = &strct.ident;
+    let name = &input.ident;
+    let integration_impls =
+        derive_integration_impls(&input, &quote! { sailfish::TemplateSimple });
 
-    let field_names: Punctuated<Ident, Token![,]> = match strct.fields {
-        Fields::Named(fields) => fields
-            .named
-            .into_iter()
-            .map(|f| {
-                f.ident.expect(
-                    "Internal error: Failed to get field name (error code: 73621)",
-                )
-            })
-            .collect(),
-        Fields::Unit => Punctuated::new(),
-        _ => {
-            return Err(syn::Error::new(
-                Span::call_site(),
-                "You cannot derive `TemplateSimple` for tuple struct",
-            ));
-        }
+    // variants of enums are destructured in the compiled template
+    let destructure = match input.pattern {
+        Some(ref pattern) => quote! { let #pattern = self; },
+        None => TokenStream::new(),
     };
 
-    let (impl_generics, ty_generics, where_clause) = strct.generics.split_for_impl();
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
 
     // render_once method always results in the same code.
     // This method can be implemented in `sailfish` crate, but I found that performance
     // drops when the implementation is written in `sailfish` crate.
     Ok(quote! {
         impl #impl_generics sailfish::TemplateSimple for #name #ty_generics #where_clause {
+            const CONTENT_TYPE: &'static str = #content_type;
+
             fn render_once(self) -> sailfish::RenderResult {
                 use sailfish::runtime::{Buffer, SizeHint};
                 static SIZE_HINT: SizeHint = SizeHint::new();
@@ -521,16 +1095,107 @@ fn derive_template_simple_impl(tokens: TokenStream) -> Result<TokenStream, syn::
             }
 
             fn render_once_to(self, __sf_buf: &mut sailfish::runtime::Buffer) -> std::result::Result<(), sailfish::runtime::RenderError> {
+                sailfish::TemplateSimple::render_once_to_sink(self, __sf_buf)
+            }
+
+            fn render_once_to_sink<__SfSink: sailfish::runtime::Sink>(self, __sf_buf: &mut __SfSink) -> std::result::Result<(), sailfish::runtime::RenderError> {
                 // This line is required for cargo to track child templates
                 #include_bytes_seq;
 
                 use sailfish::runtime as __sf_rt;
-                let #name { #field_names } = self;
+                #destructure
                 #compiled_tokens;
 
                 Ok(())
             }
         }
+
+        #integration_impls
+    })
+}
+
+fn derive_template_data_impl(tokens: TokenStream) -> Result<TokenStream, syn::Error> {
+    let strct = syn::parse2::<ItemStruct>(tokens)?;
+
+    if !strct.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &strct.generics,
+            "You cannot derive `TemplateData` for generic struct",
+        ));
+    }
+
+    let name = &strct.ident;
+    let type_name = name.to_string();
+
+    let (field_names, field_types): (Vec<Ident>, Vec<Type>) = match strct.fields {
+        Fields::Named(fields) => fields
+            .named
+            .into_iter()
+            .map(|f| {
+                let ident = f.ident.expect(
+                    "Internal error: Failed to get field name (error code: 73622)",
+                );
+                (ident, f.ty)
+            })
+            .unzip(),
+        Fields::Unit => (Vec::new(), Vec::new()),
+        _ => {
+            return Err(syn::Error::new(
+                Span::call_site(),
+                "You cannot derive `TemplateData` for tuple struct",
+            ));
+        }
+    };
+
+    // Rust code which will be pasted into the generated template library.
+    let field_strs: Vec<String> = field_names.iter().map(|f| f.to_string()).collect();
+    let def_head = format!("pub struct {} {{\n", type_name);
+    let def_fields = field_strs.iter().map(|f| format!("    pub {}: ", f));
+    let decode_head = format!(
+        "}}\n\nimpl sailfish::dynamic::runtime::Decode for {} {{\n    \
+         fn decode(data: &mut &[u8]) -> Self {{\n        {} {{\n",
+        type_name, type_name
+    );
+    let decode_fields = field_strs.iter().map(|f| {
+        format!(
+            "            {}: sailfish::dynamic::runtime::Decode::decode(data),\n",
+            f
+        )
+    });
+
+    Ok(quote! {
+        unsafe impl sailfish::dynamic::TemplateData for #name {
+            fn type_name() -> std::string::String {
+                std::string::String::from(#type_name)
+            }
+
+            fn definition(defs: &mut std::vec::Vec<std::string::String>) {
+                #(<#field_types as sailfish::dynamic::TemplateData>::definition(defs);)*
+
+                let mut def = std::string::String::new();
+                def.push_str(#def_head);
+                #(
+                    def.push_str(#def_fields);
+                    def.push_str(&<#field_types as sailfish::dynamic::TemplateData>::type_name());
+                    def.push_str(",\n");
+                )*
+                def.push_str(#decode_head);
+                #(def.push_str(#decode_fields);)*
+                def.push_str("        }\n    }\n}\n");
+
+                if !defs.contains(&def) {
+                    defs.push(def);
+                }
+            }
+
+            fn fields() -> &'static [&'static str] {
+                &[#(#field_strs),*]
+            }
+
+            fn serialize(&self, v: &mut std::vec::Vec<u8>) {
+                #(sailfish::dynamic::TemplateData::serialize(&self.#field_names, v);)*
+            }
+        }
     })
 }
 
@@ -549,3 +1214,7 @@ pub fn derive_template(tokens: TokenStream) -> TokenStream {
 pub fn derive_template_simple(tokens: TokenStream) -> TokenStream {
     derive_template_simple_impl(tokens).unwrap_or_else(|e| e.to_compile_error())
 }
+
+pub fn derive_template_data(tokens: TokenStream) -> TokenStream {
+    derive_template_data_impl(tokens).unwrap_or_else(|e| e.to_compile_error())
+}
@@ -1,10 +1,14 @@
 use quote::quote;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::mem;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use syn::visit_mut::VisitMut;
-use syn::{Block, Expr, ExprBlock, LitStr, Macro, Stmt};
+use syn::{Block, Expr, ExprBlock, LitStr, Macro, Pat, Stmt};
 
 use crate::error::*;
+use crate::translator::{BLOCK_LABEL_PREFIX, MACRO_NAME_PREFIX};
 
 macro_rules! matches_or_else {
     ($val:expr, $p:pat, $ok:expr, $else:expr) => {
@@ -58,13 +62,9 @@ impl<'h> Resolver<'h> {
             error: None,
             include_handler: Arc::clone(&self.include_handler),
         };
-        child.visit_block_mut(ast);
+        child.resolve_inheritance(ast)?;
 
-        if let Some(e) = child.error {
-            Err(e)
-        } else {
-            Ok(ResolveReport { deps: child.deps })
-        }
+        Ok(ResolveReport { deps: child.deps })
     }
 }
 
@@ -80,6 +80,96 @@ struct ResolverImpl<'h> {
 }
 
 impl<'h> ResolverImpl<'h> {
+    /// Resolve `include!` macros and splice the template into its parent layouts.
+    ///
+    /// Blocks overridden by the child templates are collected while walking up the
+    /// `extends` chain, and substituted into the root layout at the end.
+    fn resolve_inheritance(&mut self, ast: &mut Block) -> Result<(), Error> {
+        let mut overrides: HashMap<String, Block> = HashMap::new();
+        let mut current = mem::replace(ast, empty_block());
+        let mut chain: Vec<PathBuf> = Vec::new();
+
+        let result = loop {
+            self.visit_block_mut(&mut current);
+            if let Some(e) = self.error.take() {
+                break Err(e);
+            }
+
+            let parent = match take_extends(&mut current) {
+                Ok(Some(parent)) => parent,
+                Ok(None) => break Ok(()),
+                Err(e) => break Err(e),
+            };
+
+            let mut blocks = HashMap::new();
+            if let Err(e) = collect_blocks(&mut current, &mut blocks) {
+                break Err(e);
+            }
+
+            // child blocks take precedence, and `super()` refers to the parent block
+            for (name, block) in blocks {
+                let merged = match overrides.remove(&name) {
+                    Some(mut child) => {
+                        replace_super(&mut child, &block);
+                        child
+                    }
+                    None => block,
+                };
+                overrides.insert(name, merged);
+            }
+
+            let parent_file = self.resolve_path(&parent);
+            if chain.contains(&parent_file) || self.path_stack[0] == parent_file {
+                break Err(make_error!(ErrorKind::AnalyzeError(format!(
+                    "Circular template inheritance detected: {:?}",
+                    parent_file
+                ))));
+            }
+
+            current = match (*self.include_handler)(&parent_file)
+                .chain_err(|| format!("Failed to extend {:?}", parent_file))
+            {
+                Ok(blk) => blk,
+                Err(e) => break Err(e),
+            };
+
+            if self.deps.iter().all(|p| p != &parent_file) {
+                self.deps.push(parent_file.clone());
+            }
+            self.path_stack.push(parent_file.clone());
+            chain.push(parent_file);
+        };
+
+        for _ in 0..chain.len() {
+            self.path_stack.pop();
+        }
+        result?;
+
+        let mut applier = BlockApplier {
+            overrides: &overrides,
+            name_stack: Vec::new(),
+            error: None,
+        };
+        applier.visit_block_mut(&mut current);
+        if let Some(e) = applier.error {
+            return Err(e);
+        }
+
+        *ast = current;
+        Ok(())
+    }
+
+    fn resolve_path(&self, arg: &str) -> PathBuf {
+        // TODO: How should arguments be interpreted on Windows?
+        if Path::new(arg).is_absolute() {
+            // absolute imclude
+            PathBuf::from(&arg[1..])
+        } else {
+            // relative include
+            self.path_stack.last().unwrap().parent().unwrap().join(arg)
+        }
+    }
+
     fn resolve_include(&mut self, mac: &Macro) -> Result<Expr, Error> {
         let arg = match syn::parse2::<LitStr>(mac.tokens.clone()) {
             Ok(l) => l.value(),
@@ -94,11 +184,7 @@ impl<'h> ResolverImpl<'h> {
 
         // resolve include! for rust file
         if arg.ends_with(".rs") {
-            let absolute_path = if Path::new(&*arg).is_absolute() {
-                PathBuf::from(&arg[1..])
-            } else {
-                self.path_stack.last().unwrap().parent().unwrap().join(arg)
-            };
+            let absolute_path = self.resolve_path(&arg);
 
             return if let Some(absolute_path_str) = absolute_path.to_str() {
                 Ok(syn::parse2(quote! { include!(#absolute_path_str) }).unwrap())
@@ -112,14 +198,7 @@ impl<'h> ResolverImpl<'h> {
         }
 
         // resolve the template file path
-        // TODO: How should arguments be interpreted on Windows?
-        let child_template_file = if Path::new(&*arg).is_absolute() {
-            // absolute imclude
-            PathBuf::from(&arg[1..])
-        } else {
-            // relative include
-            self.path_stack.last().unwrap().parent().unwrap().join(arg)
-        };
+        let child_template_file = self.resolve_path(&arg);
 
         // parse and translate the child template
         let mut blk = (*self.include_handler)(&child_template_file).chain_err(|| {
@@ -127,7 +206,7 @@ impl<'h> ResolverImpl<'h> {
         })?;
 
         self.path_stack.push(child_template_file);
-        syn::visit_mut::visit_block_mut(self, &mut blk);
+        self.visit_block_mut(&mut blk);
 
         let child_template_file = self.path_stack.pop().unwrap();
         if self.deps.iter().all(|p| p != &child_template_file) {
@@ -140,9 +219,62 @@ impl<'h> ResolverImpl<'h> {
             block: blk,
         }))
     }
+
+    /// Translate the imported template and return the macro definitions in it
+    fn resolve_import(&mut self, mac: &Macro) -> Result<Vec<Stmt>, Error> {
+        let arg = syn::parse2::<LitStr>(mac.tokens.clone())?.value();
+        let imported_file = self.resolve_path(&arg);
+        if self.path_stack.contains(&imported_file) {
+            return Err(make_error!(ErrorKind::AnalyzeError(format!(
+                "Circular import detected: {:?}",
+                imported_file
+            ))));
+        }
+
+        let mut blk = (*self.include_handler)(&imported_file)
+            .chain_err(|| format!("Failed to import {:?}", imported_file))?;
+
+        self.path_stack.push(imported_file);
+        self.visit_block_mut(&mut blk);
+
+        let imported_file = self.path_stack.pop().unwrap();
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        if self.deps.iter().all(|p| p != &imported_file) {
+            self.deps.push(imported_file);
+        }
+
+        // other contents of the imported template are not rendered
+        blk.stmts.retain(is_macro_definition);
+        Ok(blk.stmts)
+    }
 }
 
 impl<'h> VisitMut for ResolverImpl<'h> {
+    fn visit_block_mut(&mut self, i: &mut Block) {
+        return_if_some!(self.error);
+        let mut stmts = Vec::with_capacity(i.stmts.len());
+
+        // macros are imported into the scope where `import` directive is placed
+        for mut stmt in mem::take(&mut i.stmts) {
+            match stmt_macro(&stmt) {
+                Some(mac) if mac.path.is_ident("__sf_import") => {
+                    match self.resolve_import(mac) {
+                        Ok(definitions) => stmts.extend(definitions),
+                        Err(e) => self.error = Some(e),
+                    }
+                }
+                _ => {
+                    self.visit_stmt_mut(&mut stmt);
+                    stmts.push(stmt);
+                }
+            }
+        }
+
+        i.stmts = stmts;
+    }
+
     fn visit_stmt_mut(&mut self, i: &mut Stmt) {
         return_if_some!(self.error);
         let sm = matches_or_else!(*i, Stmt::Macro(ref mut sm), sm, {
@@ -178,3 +310,186 @@ impl<'h> VisitMut for ResolverImpl<'h> {
         }
     }
 }
+
+/// Visitor which replaces the labeled blocks with the overridden contents
+struct BlockApplier<'a> {
+    overrides: &'a HashMap<String, Block>,
+    name_stack: Vec<String>,
+    error: Option<Error>,
+}
+
+impl<'a> VisitMut for BlockApplier<'a> {
+    fn visit_expr_mut(&mut self, i: &mut Expr) {
+        return_if_some!(self.error);
+        let name = match *i {
+            Expr::Block(ref mut eb) => block_name(eb),
+            _ => None,
+        };
+
+        let name = match name {
+            Some(name) => name,
+            None => {
+                syn::visit_mut::visit_expr_mut(self, i);
+                return;
+            }
+        };
+
+        if self.name_stack.contains(&name) {
+            let msg = format!("Block `{}` is defined inside itself", name);
+            self.error = Some(make_error!(ErrorKind::AnalyzeError(msg)));
+            return;
+        }
+
+        if let Expr::Block(ref mut eb) = *i {
+            if let Some(child) = self.overrides.get(&name) {
+                let mut child = child.clone();
+                replace_super(&mut child, &eb.block);
+                eb.block = child;
+            }
+            eb.label = None;
+        }
+
+        self.name_stack.push(name);
+        syn::visit_mut::visit_expr_mut(self, i);
+        self.name_stack.pop();
+    }
+
+    fn visit_macro_mut(&mut self, i: &mut Macro) {
+        return_if_some!(self.error);
+        if i.path.is_ident("__sf_super") {
+            self.error = Some(make_error!(ErrorKind::AnalyzeError(
+                "`super` directive must be placed inside an overriding block".to_owned()
+            )));
+        } else if i.path.is_ident("__sf_extends") {
+            self.error = Some(make_error!(ErrorKind::AnalyzeError(
+                "`extends` directive must be placed at the top level of the template"
+                    .to_owned()
+            )));
+        }
+    }
+}
+
+fn empty_block() -> Block {
+    Block {
+        brace_token: Default::default(),
+        stmts: Vec::new(),
+    }
+}
+
+fn block_name(eb: &ExprBlock) -> Option<String> {
+    let label = eb.label.as_ref()?.name.to_string();
+    label
+        .strip_prefix(BLOCK_LABEL_PREFIX)
+        .map(|name| name.to_owned())
+}
+
+fn stmt_macro(stmt: &Stmt) -> Option<&Macro> {
+    match *stmt {
+        Stmt::Macro(ref sm) => Some(&sm.mac),
+        Stmt::Expr(Expr::Macro(ref em), _) => Some(&em.mac),
+        _ => None,
+    }
+}
+
+fn is_macro_definition(stmt: &Stmt) -> bool {
+    match *stmt {
+        Stmt::Local(ref local) => matches!(
+            local.pat,
+            Pat::Ident(ref pi) if pi.ident.to_string().starts_with(MACRO_NAME_PREFIX)
+        ),
+        _ => false,
+    }
+}
+
+/// Remove the top-level `extends` directive and return the path to the parent template
+fn take_extends(ast: &mut Block) -> Result<Option<String>, Error> {
+    let mut parent = None;
+    let mut error = None;
+
+    ast.stmts.retain(|stmt| {
+        let mac = match stmt_macro(stmt) {
+            Some(mac) if mac.path.is_ident("__sf_extends") => mac,
+            _ => return true,
+        };
+
+        match syn::parse2::<LitStr>(mac.tokens.clone()) {
+            Ok(_) if parent.is_some() => {
+                error = Some(make_error!(ErrorKind::AnalyzeError(
+                    "`extends` directive was repeated".to_owned()
+                )));
+            }
+            Ok(l) => parent = Some(l.value()),
+            Err(e) => error = Some(Error::from(e)),
+        }
+        false
+    });
+
+    match error {
+        Some(e) => Err(e),
+        None => Ok(parent),
+    }
+}
+
+/// Collect all blocks defined inside the child template
+fn collect_blocks(
+    ast: &mut Block,
+    blocks: &mut HashMap<String, Block>,
+) -> Result<(), Error> {
+    struct BlockCollector<'a> {
+        blocks: &'a mut HashMap<String, Block>,
+        error: Option<Error>,
+    }
+
+    impl<'a> VisitMut for BlockCollector<'a> {
+        fn visit_expr_block_mut(&mut self, i: &mut ExprBlock) {
+            return_if_some!(self.error);
+            syn::visit_mut::visit_expr_block_mut(self, i);
+
+            if let Some(name) = block_name(i) {
+                match self.blocks.entry(name) {
+                    Entry::Occupied(e) => {
+                        let msg =
+                            format!("Block `{}` was defined multiple times", e.key());
+                        self.error = Some(make_error!(ErrorKind::AnalyzeError(msg)));
+                    }
+                    Entry::Vacant(e) => {
+                        e.insert(i.block.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    let mut collector = BlockCollector {
+        blocks,
+        error: None,
+    };
+    collector.visit_block_mut(ast);
+    collector.error.map_or(Ok(()), Err)
+}
+
+/// Replace `super()` directives with the contents of parent block
+fn replace_super(ast: &mut Block, parent: &Block) {
+    struct SuperReplacer<'a> {
+        parent: &'a Block,
+    }
+
+    impl<'a> VisitMut for SuperReplacer<'a> {
+        fn visit_stmt_mut(&mut self, i: &mut Stmt) {
+            if stmt_macro(i).is_some_and(|mac| mac.path.is_ident("__sf_super")) {
+                *i = Stmt::Expr(
+                    Expr::Block(ExprBlock {
+                        attrs: Vec::new(),
+                        label: None,
+                        block: self.parent.clone(),
+                    }),
+                    None,
+                );
+            } else {
+                syn::visit_mut::visit_stmt_mut(self, i);
+            }
+        }
+    }
+
+    SuperReplacer { parent }.visit_block_mut(ast);
+}
@@ -1,40 +1,80 @@
-use proc_macro2::{Span, TokenStream};
-use quote::ToTokens;
-use syn::parse::{Parse, ParseStream as SynParseStream, Result as ParseResult};
-use syn::{BinOp, Block, Expr};
+use proc_macro2::{Group, LineColumn, Literal, Span, TokenStream, TokenTree};
+use quote::{ToTokens, quote};
+use std::ops::Range;
+use syn::parse::{
+    Parse, ParseStream as SynParseStream, Parser as _, Result as ParseResult,
+};
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::visit_mut::VisitMut;
+use syn::{
+    Attribute, BinOp, Block, Expr, ExprCall, ExprClosure, ExprMacro, Ident, Item, LitStr,
+    Macro, Pat, Stmt, Token,
+};
 
+use crate::config::EscapeMode;
+use crate::context::{ContextTracker, EscapeContext};
 use crate::error::*;
 use crate::parser::{ParseStream, Token, TokenKind};
 
+/// Label prefix of the block expressions generated from `block` directives
+pub(crate) const BLOCK_LABEL_PREFIX: &str = "'__sf_block_";
+
+/// Name prefix of the closures generated from `macro` directives
+pub(crate) const MACRO_NAME_PREFIX: &str = "__sf_macro_";
+
 // translate tokens into Rust code
 #[derive(Clone, Debug, Default)]
 pub struct Translator {
-    escape: bool,
+    escape: EscapeMode,
+    filters: Vec<String>,
+    path: Option<String>,
 }
 
 impl Translator {
     #[inline]
     pub fn new() -> Self {
-        Self { escape: true }
+        Self {
+            escape: EscapeMode::Html,
+            filters: Vec::new(),
+            path: None,
+        }
     }
 
     #[inline]
-    pub fn escape(mut self, new: bool) -> Self {
+    pub fn escape(mut self, new: EscapeMode) -> Self {
         self.escape = new;
         self
     }
 
+    /// Paths to the modules which contain user-defined filters
+    #[inline]
+    pub fn filters(mut self, new: Vec<String>) -> Self {
+        self.filters = new;
+        self
+    }
+
+    /// Path to the template file which is reported in the rendering errors
+    ///
+    /// If the path is specified, fallible expressions are annotated with their
+    /// locations in the template file.
+    #[inline]
+    pub fn path(mut self, new: String) -> Self {
+        self.path = Some(new);
+        self
+    }
+
     pub fn translate(
         &self,
         token_iter: ParseStream<'_>,
     ) -> Result<TranslatedSource, Error> {
         let original_source = token_iter.original_source;
 
-        let mut ps = SourceBuilder::new(self.escape);
+        let mut ps = SourceBuilder::new(self.escape.clone(), self.filters.clone());
         ps.reserve(original_source.len());
-        ps.feed_tokens(token_iter)?;
+        ps.feed_tokens(token_iter);
 
-        ps.finalize()
+        ps.finalize(self.path.as_deref(), original_source)
     }
 }
 
@@ -77,17 +117,43 @@ impl SourceMap {
 }
 
 struct SourceBuilder {
-    escape: bool,
+    escape: EscapeMode,
+    filters: Vec<String>,
     source: String,
     source_map: SourceMap,
+    // names and offsets of currently opened `block` directives
+    block_stack: Vec<(String, usize)>,
+    // currently opened `macro` directives
+    macro_stack: Vec<MacroFrame>,
+    // HTML context at the end of the source
+    context: ContextTracker,
+    // errors found so far, which are reported together
+    errors: Vec<Error>,
+    // ranges of the code blocks in `source`
+    code_ranges: Vec<Range<usize>>,
+}
+
+struct MacroFrame {
+    name: String,
+    offset: usize,
+    // number of blocks opened outside the macro
+    block_depth: usize,
+    // HTML context outside the macro
+    context: ContextTracker,
 }
 
 impl SourceBuilder {
-    fn new(escape: bool) -> SourceBuilder {
+    fn new(escape: EscapeMode, filters: Vec<String>) -> SourceBuilder {
         SourceBuilder {
             escape,
+            filters,
             source: String::from("{\n"),
             source_map: SourceMap::default(),
+            block_stack: Vec::new(),
+            macro_stack: Vec::new(),
+            context: ContextTracker::new(),
+            errors: Vec::new(),
+            code_ranges: Vec::new(),
         }
     }
 
@@ -105,9 +171,21 @@ impl SourceBuilder {
         self.source.push_str(token.as_str());
     }
 
+    // write `?` operator which is reported at `offset` in the template
+    fn write_try(&mut self, offset: usize) {
+        self.source_map.entries.push(SourceMapEntry {
+            original: offset,
+            new: self.source.len(),
+            length: 1,
+        });
+        self.source.push('?');
+    }
+
     fn write_code(&mut self, token: &Token<'_>) -> Result<(), Error> {
         // TODO: automatically add missing tokens (e.g. ';', '{')
+        let start = self.source.len();
         self.write_token(token);
+        self.code_ranges.push(start..self.source.len());
         self.source.push('\n');
         Ok(())
     }
@@ -115,6 +193,8 @@ impl SourceBuilder {
     fn write_text(&mut self, token: &Token<'_>) -> Result<(), Error> {
         use std::fmt::Write;
 
+        self.context.feed(token.as_str());
+
         // if error has occured at the first byte of `render_text!` macro, it
         // will be mapped to the first byte of text
         self.source_map.entries.push(SourceMapEntry {
@@ -133,58 +213,86 @@ impl SourceBuilder {
     fn parse_filter(
         &mut self,
         token: &Token<'_>,
-    ) -> Result<Option<(Filter, TokenStream)>, Error> {
-        // parse and split off filter
-        let code_block = syn::parse_str::<CodeBlock>(token.as_str()).map_err(|e| {
-            let span = e.span();
-            let mut err = make_error!(ErrorKind::RustSyntaxError(e));
-            err.offset = into_offset(token.as_str(), span).map(|p| token.offset() + p);
-            err
-        })?;
+    ) -> Result<Option<(Vec<Filter>, TokenStream)>, Error> {
+        // parse and split off filters
+        let code_block = syn::parse_str::<CodeBlock>(token.as_str())
+            .map_err(|e| syntax_error(e, token))?;
 
-        Ok(code_block
-            .filter
-            .map(|filter| (filter, code_block.expr.into_token_stream())))
+        if code_block.filters.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some((
+                code_block.filters,
+                code_block.expr.into_token_stream(),
+            )))
+        }
+    }
+
+    fn write_filter_name(&mut self, name: &str) {
+        if self.filters.is_empty() {
+            self.source.push_str("sailfish::runtime::filter::");
+            self.source.push_str(name);
+            return;
+        }
+
+        // Resolve the filter name inside nested scopes so that the user-defined
+        // filters shadow the built-in filters. Arguments are evaluated outside
+        // of these scopes.
+        self.source
+            .push_str("({ #[allow(unused_imports)] use sailfish::runtime::filter::*; ");
+        for module in &self.filters {
+            self.source.push_str("{ #[allow(unused_imports)] use ");
+            self.source.push_str(module);
+            self.source.push_str("::*; ");
+        }
+        self.source.push_str(name);
+        for _ in 0..=self.filters.len() {
+            self.source.push_str(" }");
+        }
+        self.source.push(')');
     }
 
     fn write_with_filter(
         &mut self,
         token: &Token<'_>,
-        filter: Filter,
+        filters: Vec<Filter>,
         expr_str: &str,
     ) -> Result<(), Error> {
-        let (name, extra_args) = match filter {
-            Filter::Ident(i) => (i.to_string(), None),
-            Filter::Call(c) => (
-                c.func.into_token_stream().to_string(),
-                Some(c.args.into_token_stream().to_string()),
-            ),
-        };
+        let mut extra_args_list = Vec::with_capacity(filters.len());
+
+        // the last filter is the outermost function call
+        for filter in filters.into_iter().rev() {
+            let (name, extra_args) = match filter {
+                Filter::Ident(i) => (i.to_string(), None),
+                Filter::Call(c) => (
+                    c.func.into_token_stream().to_string(),
+                    Some(c.args.into_token_stream().to_string()),
+                ),
+            };
 
-        self.source.push_str("sailfish::runtime::filter::");
-        self.source.push_str(&name);
-        self.source.push('(');
+            self.write_filter_name(&name);
+            self.source.push_str("(&(");
+            extra_args_list.push(extra_args);
+        }
 
         // arguments to filter function
-        {
-            self.source.push_str("&(");
-            let entry = SourceMapEntry {
-                original: token.offset(),
-                new: self.source.len(),
-                length: expr_str.len(),
-            };
-            self.source_map.entries.push(entry);
-            self.source.push_str(expr_str);
-            self.source.push(')');
+        let entry = SourceMapEntry {
+            original: token.offset(),
+            new: self.source.len(),
+            length: expr_str.len(),
+        };
+        self.source_map.entries.push(entry);
+        self.source.push_str(expr_str);
 
+        for extra_args in extra_args_list.into_iter().rev() {
+            self.source.push(')');
             if let Some(extra_args) = extra_args {
                 self.source.push_str(", ");
                 self.source.push_str(&extra_args);
             }
+            self.source.push(')');
         }
 
-        self.source.push(')');
-
         Ok(())
     }
 
@@ -193,56 +301,367 @@ impl SourceBuilder {
         token: &Token<'_>,
         escape: bool,
     ) -> Result<(), Error> {
-        let method = if self.escape && escape {
-            "render_escaped"
-        } else {
-            "render"
+        let escaper = self.escape.escaper().filter(|_| escape).map(str::to_owned);
+
+        // HTML context is tracked only for HTML escaping
+        let context = match self.escape {
+            EscapeMode::Html if escape => Some(self.context.context()),
+            _ => None,
+        };
+        // values without quotes must not end the attribute
+        let unquoted = context.is_some() && self.context.is_unquoted();
+        let method = match (context, &escaper) {
+            (Some(_), _) if unquoted => "render_escaped_unquoted",
+            (Some(EscapeContext::Html), _) => "render_escaped",
+            (Some(_), _) => "render_escaped_in",
+            (None, Some(_)) => "render_escaped_with",
+            (None, None) => "render",
         };
+        let filters = self.parse_filter(token)?;
 
+        // errors raised by the macro are reported at the tag
+        self.source_map.entries.push(SourceMapEntry {
+            original: token.offset(),
+            new: self.source.len(),
+            length: 1,
+        });
         self.source.push_str("__sf_rt::");
         self.source.push_str(method);
         self.source.push_str("!(__sf_buf, ");
 
-        if let Some((filter, code_token_stream)) = self.parse_filter(token)? {
+        if let Some((filters, code_token_stream)) = filters {
             let expr_str = format!("{}", code_token_stream);
-            self.write_with_filter(token, filter, &expr_str)?;
+            self.write_with_filter(token, filters, &expr_str)?;
         } else {
             self.write_token(token);
         }
 
+        match (context, escaper) {
+            (Some(EscapeContext::Html), _) if !unquoted => {}
+            (Some(context), _) => {
+                self.source.push_str(", ");
+                self.source.push_str(context.name());
+            }
+            (None, Some(escaper)) => {
+                self.source.push_str(", ");
+                self.source.push_str(&escaper);
+            }
+            (None, None) => {}
+        }
+
         self.source.push_str(");\n");
+        self.context.value_rendered();
 
         Ok(())
     }
 
     fn write_nested_template_once(&mut self, token: &Token<'_>) -> Result<(), Error> {
-        if let Some((filter, code_token_stream)) = self.parse_filter(token)? {
+        let filters = self.parse_filter(token)?;
+
+        // nested templates are the boundaries of streamed chunks
+        self.source.push_str("__sf_rt::flush!(__sf_buf);\n");
+
+        if let Some((filters, code_token_stream)) = filters {
+            self.source_map.entries.push(SourceMapEntry {
+                original: token.offset(),
+                new: self.source.len(),
+                length: 1,
+            });
             self.source.push_str("__sf_rt::render!(__sf_buf, ");
             let expr_str = format!("{}{}", code_token_stream, ".render_once()?");
-            self.write_with_filter(token, filter, &expr_str)?;
+            self.write_with_filter(token, filters, &expr_str)?;
             self.source.push_str(");\n");
         } else {
             self.source.push('(');
             self.write_token(token);
-            self.source.push_str(").render_once_to(__sf_buf)?;\n");
+            self.source.push_str(").render_once_to_sink(__sf_buf)");
+            self.write_try(token.offset());
+            self.source.push_str(";\n");
+        }
+
+        self.source.push_str("__sf_rt::flush!(__sf_buf);\n");
+        self.context.value_rendered();
+        Ok(())
+    }
+
+    fn write_extends(&mut self, token: &Token<'_>) -> Result<(), Error> {
+        syn::parse_str::<LitStr>(token.as_str()).map_err(|e| {
+            let mut err = make_error!(ErrorKind::RustSyntaxError(e));
+            err.chains.push(ErrorKind::ParseError(
+                "`extends` directive requires a string literal".to_owned(),
+            ));
+            err.offset = Some(token.offset());
+            err.length = Some(token.as_str().len());
+            err
+        })?;
+
+        self.source.push_str("__sf_extends!(");
+        self.write_token(token);
+        self.source.push_str(");\n");
+        Ok(())
+    }
+
+    fn write_block_start(&mut self, token: &Token<'_>) -> Result<(), Error> {
+        let name = syn::parse_str::<Ident>(token.as_str())
+            .ok()
+            .filter(|i| !i.to_string().starts_with("r#"))
+            .ok_or_else(|| {
+                make_error!(
+                    ErrorKind::ParseError(format!(
+                        "Invalid block name: `{}`",
+                        token.as_str()
+                    )),
+                    offset = token.offset(),
+                    length = token.as_str().len()
+                )
+            })?;
+
+        self.source_map.entries.push(SourceMapEntry {
+            original: token.offset(),
+            new: self.source.len(),
+            length: 1,
+        });
+        self.source.push_str(BLOCK_LABEL_PREFIX);
+        self.source.push_str(&name.to_string());
+        self.source.push_str(": {\n");
+        self.block_stack.push((name.to_string(), token.offset()));
+        Ok(())
+    }
+
+    fn write_block_end(&mut self, token: &Token<'_>) -> Result<(), Error> {
+        let depth = self.macro_stack.last().map_or(0, |m| m.block_depth);
+        if self.block_stack.len() <= depth {
+            return Err(make_error!(
+                ErrorKind::ParseError("Unexpected `endblock` directive".to_owned()),
+                offset = token.offset()
+            ));
         }
 
+        self.block_stack.pop();
+        self.source.push_str("}\n");
         Ok(())
     }
 
-    pub fn feed_tokens(&mut self, token_iter: ParseStream<'_>) -> Result<(), Error> {
+    fn write_macro_start(&mut self, token: &Token<'_>) -> Result<(), Error> {
+        let invalid = || {
+            make_error!(
+                ErrorKind::ParseError(format!(
+                    "Invalid macro signature: `{}`",
+                    token.as_str()
+                )),
+                offset = token.offset(),
+                length = token.as_str().len()
+            )
+        };
+
+        let (name, params) = token
+            .as_str()
+            .split_once('(')
+            .and_then(|(name, rest)| Some((name.trim_end(), rest.strip_suffix(')')?)))
+            .ok_or_else(invalid)?;
+        let name = syn::parse_str::<Ident>(name)
+            .ok()
+            .filter(|i| !i.to_string().starts_with("r#"))
+            .ok_or_else(invalid)?;
+        let params_offset = token.offset() + token.as_str().find('(').unwrap() + 1;
+
+        // macro parameters are passed to the closure as is
+        let closure_str = format!("|{}| ()", params);
+        let closure = syn::parse_str::<ExprClosure>(&closure_str).map_err(|e| {
+            let mut err = make_error!(ErrorKind::RustSyntaxError(e));
+            err.chains.push(ErrorKind::ParseError(format!(
+                "Invalid parameters for macro `{}`",
+                name
+            )));
+            err.offset = Some(params_offset);
+            err.length = Some(params.len());
+            err
+        })?;
+
+        // parameters must be annotated so that the macro is type-checked at its
+        // definition instead of the first call
+        for input in &closure.inputs {
+            if matches!(input, Pat::Type(_)) {
+                continue;
+            }
+            let range = into_range(&closure_str, input.span());
+            let param = match range {
+                Some((offset, length)) => closure_str[offset..offset + length].to_owned(),
+                None => input.to_token_stream().to_string(),
+            };
+            let mut err = make_error!(ErrorKind::ParseError(format!(
+                "Parameter `{}` of macro `{}` requires a type annotation",
+                param, name
+            )));
+            if let Some((offset, length)) = range {
+                // skip the leading `|`
+                err.offset = Some(params_offset + offset - 1);
+                err.length = Some(length);
+            }
+            err.details.help = Some(format!(
+                "annotate the type of the parameter (e.g. `{}: &str`)",
+                param
+            ));
+            self.errors.push(err);
+        }
+
+        self.source_map.entries.push(SourceMapEntry {
+            original: token.offset(),
+            new: self.source.len(),
+            length: 1,
+        });
+        self.source.push_str("let ");
+        self.source.push_str(MACRO_NAME_PREFIX);
+        self.source.push_str(&name.to_string());
+        self.source.push_str(" = |__sf_buf: &mut _");
+        if !params.trim().is_empty() {
+            self.source.push_str(", ");
+            self.write_token(&Token::new(params, params_offset, TokenKind::Code));
+        }
+        self.source
+            .push_str("| -> ::std::result::Result<(), __sf_rt::RenderError> {\n");
+
+        // macro body is rendered at the call site, not at the definition
+        let context = std::mem::take(&mut self.context);
+        self.macro_stack.push(MacroFrame {
+            name: name.to_string(),
+            offset: token.offset(),
+            block_depth: self.block_stack.len(),
+            context,
+        });
+        Ok(())
+    }
+
+    fn write_macro_end(&mut self, token: &Token<'_>) -> Result<(), Error> {
+        let frame = self.macro_stack.pop().ok_or_else(|| {
+            make_error!(
+                ErrorKind::ParseError("Unexpected `endmacro` directive".to_owned()),
+                offset = token.offset()
+            )
+        })?;
+
+        // the blocks left open inside the macro are closed here to continue
+        // translating the rest of the template
+        let unclosed = self.block_stack.get(frame.block_depth).cloned();
+        while self.block_stack.len() > frame.block_depth {
+            self.block_stack.pop();
+            self.source.push_str("}\n");
+        }
+
+        self.context = frame.context;
+        self.source.push_str("Ok(())\n};\n");
+
+        match unclosed {
+            Some((name, offset)) => Err(unclosed_block(&name, offset)),
+            None => Ok(()),
+        }
+    }
+
+    fn write_macro_call(&mut self, token: &Token<'_>) -> Result<(), Error> {
+        let call = syn::parse_str::<ExprCall>(token.as_str()).map_err(|e| {
+            let mut err = syntax_error(e, token);
+            err.chains.push(ErrorKind::ParseError(
+                "`call` directive requires a macro invocation (e.g. `card(title)`)"
+                    .to_owned(),
+            ));
+            err
+        })?;
+
+        let name = match *call.func {
+            Expr::Path(ref p) if p.qself.is_none() => p.path.get_ident().cloned(),
+            _ => None,
+        }
+        .ok_or_else(|| {
+            make_error!(
+                ErrorKind::ParseError(format!(
+                    "Invalid macro name: `{}`",
+                    call.func.to_token_stream()
+                )),
+                offset = token.offset(),
+                length = token.as_str().find('(').unwrap_or(0)
+            )
+        })?;
+
+        let args_start = token.as_str().find('(').unwrap() + 1;
+        let args = &token.as_str()[args_start..token.as_str().len() - 1];
+
+        self.source_map.entries.push(SourceMapEntry {
+            original: token.offset(),
+            new: self.source.len(),
+            length: 1,
+        });
+        self.source.push_str(MACRO_NAME_PREFIX);
+        self.source.push_str(&name.to_string());
+        self.source.push_str("(&mut *__sf_buf");
+        if !call.args.is_empty() {
+            self.source.push_str(", ");
+            self.write_token(&Token::new(
+                args,
+                token.offset() + args_start,
+                TokenKind::Code,
+            ));
+        }
+        self.source.push(')');
+        self.write_try(token.offset());
+        self.source.push_str(";\n");
+        self.context.value_rendered();
+        Ok(())
+    }
+
+    fn write_import(&mut self, token: &Token<'_>) -> Result<(), Error> {
+        syn::parse_str::<LitStr>(token.as_str()).map_err(|e| {
+            let mut err = make_error!(ErrorKind::RustSyntaxError(e));
+            err.chains.push(ErrorKind::ParseError(
+                "`import` directive requires a string literal".to_owned(),
+            ));
+            err.offset = Some(token.offset());
+            err.length = Some(token.as_str().len());
+            err
+        })?;
+
+        self.source.push_str("__sf_import!(");
+        self.write_token(token);
+        self.source.push_str(");\n");
+        Ok(())
+    }
+
+    /// Translate the tokens into Rust code
+    ///
+    /// Errors are collected instead of stopping at the first one, and the invalid
+    /// tokens are skipped.
+    pub fn feed_tokens(&mut self, token_iter: ParseStream<'_>) {
         let mut it = token_iter.peekable();
         while let Some(token) = it.next() {
-            let token = token?;
-            match token.kind() {
-                TokenKind::Code => self.write_code(&token)?,
-                TokenKind::Comment => {}
-                TokenKind::BufferedCode { escape } => {
-                    self.write_buffered_code(&token, escape)?
+            let token = match token {
+                Ok(token) => token,
+                Err(e) => {
+                    self.errors.push(e);
+                    continue;
                 }
-                TokenKind::NestedTemplateOnce => {
-                    self.write_nested_template_once(&token)?
+            };
+
+            let result = match token.kind() {
+                TokenKind::Code => self.write_code(&token),
+                TokenKind::Comment => Ok(()),
+                TokenKind::Extends => self.write_extends(&token),
+                TokenKind::BlockStart => self.write_block_start(&token),
+                TokenKind::BlockEnd => self.write_block_end(&token),
+                TokenKind::Super => {
+                    self.source.push_str("__sf_super!();\n");
+                    Ok(())
                 }
+                TokenKind::Flush => {
+                    self.source.push_str("__sf_rt::flush!(__sf_buf);\n");
+                    Ok(())
+                }
+                TokenKind::MacroStart => self.write_macro_start(&token),
+                TokenKind::MacroEnd => self.write_macro_end(&token),
+                TokenKind::MacroCall => self.write_macro_call(&token),
+                TokenKind::Import => self.write_import(&token),
+                TokenKind::BufferedCode { escape } => {
+                    self.write_buffered_code(&token, escape)
+                }
+                TokenKind::NestedTemplateOnce => self.write_nested_template_once(&token),
                 TokenKind::Text => {
                     // concatenate repeated text token
                     let offset = token.offset();
@@ -263,37 +682,170 @@ impl SourceBuilder {
                     }
 
                     let new_token = Token::new(&concatenated, offset, TokenKind::Text);
-                    self.write_text(&new_token)?;
+                    self.write_text(&new_token)
                 }
+            };
+
+            if let Err(e) = result {
+                self.errors.push(e);
             }
         }
-
-        Ok(())
     }
 
-    pub fn finalize(mut self) -> Result<TranslatedSource, Error> {
+    pub fn finalize(
+        mut self,
+        path: Option<&str>,
+        original_source: &str,
+    ) -> Result<TranslatedSource, Error> {
+        // close the unclosed macros and blocks so that the Rust code can be parsed
+        while let Some(frame) = self.macro_stack.pop() {
+            while self.block_stack.len() > frame.block_depth {
+                let (name, offset) = self.block_stack.pop().unwrap();
+                self.errors.push(unclosed_block(&name, offset));
+                self.source.push_str("}\n");
+            }
+            let mut err = make_error!(
+                ErrorKind::ParseError(format!("Unclosed macro `{}`", frame.name)),
+                offset = frame.offset,
+                length = frame.name.len()
+            );
+            err.details.help =
+                Some("close the macro with `endmacro` directive".to_owned());
+            self.errors.push(err);
+            self.source.push_str("Ok(())\n};\n");
+        }
+        while let Some((name, offset)) = self.block_stack.pop() {
+            self.errors.push(unclosed_block(&name, offset));
+            self.source.push_str("}\n");
+        }
+
         self.source.push_str("\n}");
-        match syn::parse_str::<Block>(&self.source) {
-            Ok(ast) => Ok(TranslatedSource {
-                ast,
-                source_map: self.source_map,
-            }),
-            Err(synerr) => {
-                let span = synerr.span();
-                let original_offset = into_offset(&self.source, span)
-                    .and_then(|o| self.source_map.reverse_mapping(o));
-
-                let mut err =
-                    make_error!(ErrorKind::RustSyntaxError(synerr), source = self.source);
-
-                err.offset = original_offset;
-
-                Err(err)
+
+        // Code blocks are often incomplete statements (e.g. `for x in xs {`), so
+        // they are parsed together. When a code block has a syntax error, it is
+        // blanked out and the source is parsed again, so that the errors in the
+        // other code blocks are also reported.
+        let mut last_offset = None;
+        let result = loop {
+            let synerr = match syn::parse_str::<Block>(&self.source) {
+                Ok(ast) => break Ok(ast),
+                Err(synerr) => synerr,
+            };
+            let range = into_range(&self.source, synerr.span());
+            let err = self.locate_syntax_error(synerr, range);
+            if err.offset.is_some() && err.offset == last_offset {
+                // blanking out the code block did not help
+                break Err(err);
+            }
+
+            // errors such as missing `;` are found at the beginning of the next
+            // statement, so they are caused by the last code block before them
+            let block = range.and_then(|(offset, _)| {
+                self.code_ranges.iter().rposition(|r| {
+                    r.start <= offset && self.source[r.clone()].contains(is_code)
+                })
+            });
+            match block {
+                Some(index) => {
+                    blank_out(&mut self.source, self.code_ranges[index].clone());
+                    last_offset = err.offset;
+                    self.errors.push(err);
+                }
+                None => break Err(err),
+            }
+        };
+
+        let result = result.and_then(|mut ast| {
+            let mut locator = Locator {
+                path: path.unwrap_or_default(),
+                source: &self.source,
+                source_lines: line_starts(&self.source),
+                source_map: &self.source_map,
+                original_lines: line_starts(original_source),
+            };
+            if path.is_some() {
+                locator.visit_block_mut(&mut ast);
+            }
+
+            // only the tokens from the template code keep their spans, so that
+            // the derive macros can tell them from the generated code
+            let tokens = locator.reset_spans(ast.into_token_stream());
+            syn::parse2::<Block>(tokens)
+                .map(|ast| TranslatedSource {
+                    ast,
+                    source_map: self.source_map,
+                })
+                .map_err(|e| make_error!(ErrorKind::RustSyntaxError(e)))
+        });
+
+        match result {
+            Ok(tsource) if self.errors.is_empty() => return Ok(tsource),
+            Ok(_) => {}
+            Err(e) => {
+                // syntax errors outside of the template code (e.g. unclosed braces
+                // at the end) are likely caused by the previous errors
+                let reported = e.offset.is_some() && e.offset == last_offset;
+                if (e.offset.is_some() && !reported) || self.errors.is_empty() {
+                    self.errors.push(e);
+                }
+            }
+        }
+
+        // report the errors in the order of appearance
+        self.errors.sort_by_key(|e| e.offset.unwrap_or(usize::MAX));
+        Err(Error::from_errors(self.errors).unwrap())
+    }
+
+    // map the syntax error found at `range` of the source to the template
+    fn locate_syntax_error(
+        &self,
+        synerr: syn::Error,
+        range: Option<(usize, usize)>,
+    ) -> Error {
+        let original_offset =
+            range.and_then(|(offset, _)| self.source_map.reverse_mapping(offset));
+        let original_end =
+            range
+                .filter(|&(_, length)| length > 0)
+                .and_then(|(offset, length)| {
+                    self.source_map.reverse_mapping(offset + length - 1)
+                });
+
+        let mut err = make_error!(ErrorKind::RustSyntaxError(synerr));
+        match original_offset {
+            Some(offset) => {
+                err.offset = Some(offset);
+                err.length = original_end
+                    .filter(|&end| end >= offset)
+                    .map(|end| end + 1 - offset);
             }
+            // the error cannot be located in the template
+            None => err.source = Some(self.source.clone()),
         }
+        err
     }
 }
 
+fn is_code(c: char) -> bool {
+    !matches!(c, '{' | '}') && !c.is_whitespace()
+}
+
+// replace the code in `range` with whitespaces except the braces, which keeps the
+// blocks balanced and the offsets of the source map valid
+fn blank_out(source: &mut String, range: Range<usize>) {
+    let blank: String = source[range.clone()]
+        .chars()
+        .flat_map(|c| {
+            let (c, n) = match c {
+                '{' | '}' | '\n' => (c, 1),
+                _ => (' ', c.len_utf8()),
+            };
+            std::iter::repeat_n(c, n)
+        })
+        .collect();
+    source.replace_range(range, &blank);
+}
+
 enum Filter {
     Ident(syn::Ident),
     Call(syn::ExprCall),
@@ -311,14 +863,15 @@ impl ToTokens for Filter {
 struct CodeBlock {
     #[allow(dead_code)]
     expr: Box<Expr>,
-    filter: Option<Filter>,
+    // filters in the order of application
+    filters: Vec<Filter>,
 }
 
 impl Parse for CodeBlock {
     fn parse(s: SynParseStream) -> ParseResult<Self> {
         let main = s.parse::<Expr>()?;
 
-        let code_block = match main {
+        let mut code_block = match main {
             Expr::Binary(b) if matches!(b.op, BinOp::BitOr(_)) => {
                 match *b.right {
                     Expr::Call(c) => {
@@ -326,7 +879,7 @@ impl Parse for CodeBlock {
                             if p.path.get_ident().is_some() {
                                 CodeBlock {
                                     expr: b.left,
-                                    filter: Some(Filter::Call(c)),
+                                    filters: vec![Filter::Call(c)],
                                 }
                             } else {
                                 return Err(syn::Error::new_spanned(
@@ -339,7 +892,7 @@ impl Parse for CodeBlock {
                             // normal evaluation block
                             CodeBlock {
                                 expr: b.left,
-                                filter: None,
+                                filters: Vec::new(),
                             }
                         }
                     }
@@ -347,7 +900,7 @@ impl Parse for CodeBlock {
                         if let Some(i) = p.path.get_ident() {
                             CodeBlock {
                                 expr: b.left,
-                                filter: Some(Filter::Ident(i.clone())),
+                                filters: vec![Filter::Ident(i.clone())],
                             }
                         } else {
                             return Err(syn::Error::new_spanned(
@@ -363,27 +916,399 @@ impl Parse for CodeBlock {
             }
             _ => CodeBlock {
                 expr: Box::new(main),
-                filter: None,
+                filters: Vec::new(),
             },
         };
 
+        // split off the chained filters (e.g. `expr | trim | upper`)
+        if !code_block.filters.is_empty() {
+            while let Some((expr, filter)) = split_filter(&code_block.expr) {
+                code_block.expr = expr;
+                code_block.filters.insert(0, filter);
+            }
+        }
+
         Ok(code_block)
     }
 }
 
-fn into_offset(source: &str, span: Span) -> Option<usize> {
-    let lc = span.start();
-    if lc.line > 0 {
-        Some(
-            source
-                .lines()
-                .take(lc.line - 1)
-                .fold(0, |s, e| s + e.len() + 1)
-                + lc.column,
-        )
-    } else {
-        None
+fn split_filter(expr: &Expr) -> Option<(Box<Expr>, Filter)> {
+    let b = match expr {
+        Expr::Binary(b) if matches!(b.op, BinOp::BitOr(_)) => b,
+        _ => return None,
+    };
+
+    let filter = match *b.right {
+        Expr::Call(ref c) => match *c.func {
+            Expr::Path(ref p) if p.path.get_ident().is_some() => Filter::Call(c.clone()),
+            _ => return None,
+        },
+        Expr::Path(ref p) => Filter::Ident(p.path.get_ident()?.clone()),
+        _ => return None,
+    };
+
+    Some((b.left.clone(), filter))
+}
+
+/// Visitor which annotates the fallible expressions with their locations
+///
+/// `?` operators are replaced with `located!` macro, and the locations are
+/// appended to the arguments of `render!` macros.
+struct Locator<'a> {
+    path: &'a str,
+    source: &'a str,
+    // offsets of the lines in `source`
+    source_lines: Vec<usize>,
+    source_map: &'a SourceMap,
+    // offsets of the lines in the template
+    original_lines: Vec<usize>,
+}
+
+impl<'a> Locator<'a> {
+    /// Line number in the template corresponding to `span`
+    fn line(&self, span: Span) -> Option<u32> {
+        let lc = span.start();
+        let start = *self.source_lines.get(lc.line.checked_sub(1)?)?;
+        // column is counted in characters
+        let offset = self.source[start..]
+            .char_indices()
+            .nth(lc.column)
+            .map_or(self.source.len(), |(i, _)| start + i);
+
+        let original = self.source_map.reverse_mapping(offset)?;
+        let line = self.original_lines.partition_point(|&s| s <= original);
+        Some(line as u32)
+    }
+
+    /// Reset the spans of the tokens which do not come from the template
+    fn reset_spans(&self, tokens: TokenStream) -> TokenStream {
+        tokens
+            .into_iter()
+            .map(|tt| match tt {
+                TokenTree::Group(g) => {
+                    let mut group =
+                        Group::new(g.delimiter(), self.reset_spans(g.stream()));
+                    if self.line(g.span_open()).is_some() {
+                        group.set_span(g.span());
+                    }
+                    TokenTree::Group(group)
+                }
+                mut tt => {
+                    if self.line(tt.span()).is_none() {
+                        tt.set_span(Span::call_site());
+                    }
+                    tt
+                }
+            })
+            .collect()
+    }
+
+    /// Line number of the first token which comes from the template
+    fn first_line(&self, tokens: TokenStream) -> Option<u32> {
+        tokens.into_iter().find_map(|tt| match tt {
+            TokenTree::Group(g) => self
+                .line(g.span_open())
+                .or_else(|| self.first_line(g.stream())),
+            tt => self.line(tt.span()),
+        })
+    }
+
+    /// Attach the location in the template to the statement
+    ///
+    /// The attributes are replaced with comments by [`locations_to_comments`]
+    /// after the code is formatted.
+    fn annotate(&self, stmt: &mut Stmt) {
+        let is_internal = match stmt {
+            Stmt::Macro(sm) => is_internal_macro(&sm.mac),
+            Stmt::Expr(Expr::Macro(em), _) => is_internal_macro(&em.mac),
+            _ => false,
+        };
+        if is_internal {
+            return;
+        }
+
+        let tokens = stmt.to_token_stream();
+        // the comment must not be appended inside multi-line string literals
+        if has_multiline_literal(tokens.clone()) {
+            return;
+        }
+        let line = match self.first_line(tokens) {
+            Some(line) => line,
+            None => return,
+        };
+        if let Some(attrs) = stmt_attrs_mut(stmt) {
+            let location = format!("{}:{}", self.path, line);
+            attrs.push(syn::parse_quote! { #[__sf_loc = #location] });
+        }
+    }
+}
+
+impl<'a> VisitMut for Locator<'a> {
+    fn visit_expr_mut(&mut self, i: &mut Expr) {
+        match *i {
+            Expr::Try(ref mut et) => {
+                self.visit_expr_mut(&mut et.expr);
+                if let Some(line) = self.line(et.question_token.span) {
+                    let line = Literal::u32_unsuffixed(line);
+                    let (expr, path) = (&et.expr, self.path);
+                    let mut em: ExprMacro = syn::parse_quote! {
+                        __sf_rt::located!(#expr, #path, #line)
+                    };
+                    em.attrs = std::mem::take(&mut et.attrs);
+                    *i = Expr::Macro(em);
+                }
+            }
+            Expr::Closure(ref mut ec) if is_macro_closure(ec) => {
+                self.visit_expr_mut(&mut ec.body);
+            }
+            // `?` inside closures and async blocks does not return from templates
+            Expr::Closure(_) | Expr::Async(_) => {}
+            _ => syn::visit_mut::visit_expr_mut(self, i),
+        }
+    }
+
+    fn visit_block_mut(&mut self, i: &mut Block) {
+        for stmt in i.stmts.iter_mut() {
+            self.annotate(stmt);
+            self.visit_stmt_mut(stmt);
+        }
+    }
+
+    fn visit_item_mut(&mut self, _: &mut Item) {}
+
+    fn visit_macro_mut(&mut self, i: &mut Macro) {
+        let is_render_macro = i.path.segments.len() == 2
+            && i.path.segments[0].ident == "__sf_rt"
+            && matches!(
+                i.path.segments[1].ident.to_string().as_str(),
+                "render"
+                    | "render_escaped"
+                    | "render_escaped_in"
+                    | "render_escaped_unquoted"
+                    | "render_escaped_with"
+            );
+        if !is_render_macro {
+            return;
+        }
+
+        let parser = Punctuated::<Expr, Token![,]>::parse_terminated;
+        let mut args = match parser.parse2(i.tokens.clone()) {
+            Ok(args) => args,
+            Err(_) => return,
+        };
+        if let Some(value) = args.iter_mut().nth(1) {
+            self.visit_expr_mut(value);
+        }
+
+        let path = self.path;
+        i.tokens = match self.line(i.path.segments[0].ident.span()) {
+            Some(line) => {
+                let line = Literal::u32_unsuffixed(line);
+                quote! { #args, #path, #line }
+            }
+            None => quote! { #args },
+        };
+    }
+}
+
+// closures generated from `macro` directives
+fn is_macro_closure(ec: &ExprClosure) -> bool {
+    match ec.inputs.first() {
+        Some(Pat::Type(pt)) => {
+            matches!(*pt.pat, Pat::Ident(ref pi) if pi.ident == "__sf_buf")
+        }
+        _ => false,
+    }
+}
+
+// macros which are removed or replaced with the rendered text while compiling
+fn is_internal_macro(mac: &Macro) -> bool {
+    let segments = &mac.path.segments;
+    match segments.len() {
+        1 => segments[0].ident.to_string().starts_with("__sf_"),
+        2 => {
+            segments[0].ident == "__sf_rt"
+                && (segments[1].ident == "render_text" || segments[1].ident == "flush")
+        }
+        _ => false,
+    }
+}
+
+fn has_multiline_literal(tokens: TokenStream) -> bool {
+    tokens.into_iter().any(|tt| match tt {
+        TokenTree::Group(g) => has_multiline_literal(g.stream()),
+        TokenTree::Literal(l) => l.to_string().contains('\n'),
+        _ => false,
+    })
+}
+
+fn stmt_attrs_mut(stmt: &mut Stmt) -> Option<&mut Vec<Attribute>> {
+    let attrs = match stmt {
+        Stmt::Local(sl) => &mut sl.attrs,
+        Stmt::Macro(sm) => &mut sm.attrs,
+        Stmt::Expr(Expr::Assign(e), _) => &mut e.attrs,
+        Stmt::Expr(Expr::Binary(e), _) => &mut e.attrs,
+        Stmt::Expr(Expr::Block(e), _) => &mut e.attrs,
+        Stmt::Expr(Expr::Break(e), _) => &mut e.attrs,
+        Stmt::Expr(Expr::Call(e), _) => &mut e.attrs,
+        Stmt::Expr(Expr::Continue(e), _) => &mut e.attrs,
+        Stmt::Expr(Expr::ForLoop(e), _) => &mut e.attrs,
+        Stmt::Expr(Expr::If(e), _) => &mut e.attrs,
+        Stmt::Expr(Expr::Loop(e), _) => &mut e.attrs,
+        Stmt::Expr(Expr::Macro(e), _) => &mut e.attrs,
+        Stmt::Expr(Expr::Match(e), _) => &mut e.attrs,
+        Stmt::Expr(Expr::MethodCall(e), _) => &mut e.attrs,
+        Stmt::Expr(Expr::Return(e), _) => &mut e.attrs,
+        Stmt::Expr(Expr::Try(e), _) => &mut e.attrs,
+        Stmt::Expr(Expr::Unsafe(e), _) => &mut e.attrs,
+        Stmt::Expr(Expr::While(e), _) => &mut e.attrs,
+        _ => return None,
+    };
+    Some(attrs)
+}
+
+#[cfg(feature = "procmacro")]
+/// Remove the location attributes added by the translator
+pub(crate) fn strip_locations(tokens: TokenStream) -> TokenStream {
+    let mut output = TokenStream::new();
+    let mut iter = tokens.into_iter().peekable();
+    while let Some(tt) = iter.next() {
+        match tt {
+            TokenTree::Punct(ref p) if p.as_char() == '#' => {
+                if let Some(TokenTree::Group(g)) = iter.peek()
+                    && g.delimiter() == proc_macro2::Delimiter::Bracket
+                    && is_location_attr(g)
+                {
+                    iter.next();
+                    continue;
+                }
+                output.extend(Some(tt));
+            }
+            TokenTree::Group(g) => {
+                let mut group = Group::new(g.delimiter(), strip_locations(g.stream()));
+                group.set_span(g.span());
+                output.extend(Some(TokenTree::Group(group)));
+            }
+            tt => output.extend(Some(tt)),
+        }
+    }
+    output
+}
+
+#[cfg(feature = "procmacro")]
+fn is_location_attr(g: &Group) -> bool {
+    matches!(
+        g.stream().into_iter().next(),
+        Some(TokenTree::Ident(ref ident)) if ident == "__sf_loc"
+    )
+}
+
+#[cfg(feature = "procmacro")]
+/// Whether each token comes from the template code, in the depth-first order
+///
+/// Groups are followed by the tokens inside them. The spans of other tokens are
+/// reset by the translator.
+pub(crate) fn template_token_marks(tokens: TokenStream, marks: &mut Vec<bool>) {
+    for tt in tokens {
+        marks.push(!tt.span().byte_range().is_empty());
+        if let TokenTree::Group(g) = tt {
+            template_token_marks(g.stream(), marks);
+        }
+    }
+}
+
+/// Replace the location attributes added by the translator with comments
+///
+/// The attributes on their own lines are turned into comments at the end of the
+/// next lines, so that the compiler errors in the generated code show the
+/// location in the template. Other attributes are removed.
+pub fn locations_to_comments(code: &str) -> String {
+    let mut output = String::with_capacity(code.len());
+    let mut location = None;
+    for line in code.lines() {
+        let trimmed = line.trim();
+        if let Some(loc) = trimmed
+            .strip_prefix("#[__sf_loc = \"")
+            .and_then(|s| s.strip_suffix("\"]"))
+        {
+            location = Some(loc);
+            continue;
+        }
+
+        strip_location_attrs(line, &mut output);
+        if let Some(loc) = location.take() {
+            output.push_str(" // ");
+            output.push_str(loc);
+        }
+        output.push('\n');
+    }
+    output
+}
+
+fn strip_location_attrs(mut line: &str, output: &mut String) {
+    while let Some(pos) = line.find("[__sf_loc = \"") {
+        let end = match line[pos..].find("\"]") {
+            Some(end) => pos + end + 2,
+            None => break,
+        };
+        let before = line[..pos].trim_end();
+        output.push_str(before.strip_suffix('#').unwrap_or(before));
+        line = line[end..].trim_start();
+        if !line.is_empty() && !output.is_empty() && !output.ends_with(' ') {
+            output.push(' ');
+        }
     }
+    output.push_str(line);
+}
+
+fn line_starts(s: &str) -> Vec<usize> {
+    std::iter::once(0)
+        .chain(s.match_indices('\n').map(|(i, _)| i + 1))
+        .collect()
+}
+
+// byte range of `span` in `source` as offset and length
+fn into_range(source: &str, span: Span) -> Option<(usize, usize)> {
+    let start = offset_at(source, span.start())?;
+    let end = offset_at(source, span.end()).filter(|&end| end >= start)?;
+    Some((start, end - start))
+}
+
+fn offset_at(source: &str, lc: LineColumn) -> Option<usize> {
+    let line_start = source
+        .split('\n')
+        .take(lc.line.checked_sub(1)?)
+        .map(|line| line.len() + 1)
+        .sum::<usize>()
+        .min(source.len());
+    // column is counted in characters
+    Some(
+        source[line_start..]
+            .char_indices()
+            .nth(lc.column)
+            .map_or(source.len(), |(i, _)| line_start + i),
+    )
+}
+
+// syntax error in the Rust code of `token`
+fn syntax_error(e: syn::Error, token: &Token<'_>) -> Error {
+    let range = into_range(token.as_str(), e.span());
+    let mut err = make_error!(ErrorKind::RustSyntaxError(e));
+    if let Some((offset, length)) = range {
+        err.offset = Some(token.offset() + offset);
+        err.length = Some(length);
+    }
+    err
+}
+
+fn unclosed_block(name: &str, offset: usize) -> Error {
+    let mut err = make_error!(
+        ErrorKind::ParseError(format!("Unclosed block `{}`", name)),
+        offset,
+        length = name.len()
+    );
+    err.details.help = Some("close the block with `endblock` directive".to_owned());
+    err
 }
 
 #[cfg(test)]
@@ -397,11 +1322,18 @@ mod tests {
         let lexer = Parser::new();
         let token_iter = lexer.parse(src);
         let mut ps = SourceBuilder {
-            escape: true,
+            escape: EscapeMode::Html,
+            filters: Vec::new(),
             source: String::with_capacity(token_iter.original_source.len()),
             source_map: SourceMap::default(),
+            block_stack: Vec::new(),
+            macro_stack: Vec::new(),
+            context: ContextTracker::new(),
+            errors: Vec::new(),
+            code_ranges: Vec::new(),
         };
-        ps.feed_tokens(token_iter.clone()).unwrap();
+        ps.feed_tokens(token_iter.clone());
+        assert!(ps.errors.is_empty());
         Translator::new().translate(token_iter).unwrap();
     }
 
@@ -411,11 +1343,18 @@ mod tests {
         let lexer = Parser::new();
         let token_iter = lexer.parse(src);
         let mut ps = SourceBuilder {
-            escape: true,
+            escape: EscapeMode::Html,
+            filters: Vec::new(),
             source: String::with_capacity(token_iter.original_source.len()),
             source_map: SourceMap::default(),
+            block_stack: Vec::new(),
+            macro_stack: Vec::new(),
+            context: ContextTracker::new(),
+            errors: Vec::new(),
+            code_ranges: Vec::new(),
         };
-        ps.feed_tokens(token_iter.clone()).unwrap();
+        ps.feed_tokens(token_iter.clone());
+        assert!(ps.errors.is_empty());
         assert_eq!(
             &Translator::new()
                 .translate(token_iter)
@@ -423,7 +1362,7 @@ mod tests {
                 .ast
                 .into_token_stream()
                 .to_string(),
-            r#"{ __sf_rt :: render_text ! (__sf_buf , "outer ") ; (inner) . render_once_to (__sf_buf) ? ; __sf_rt :: render_text ! (__sf_buf , " outer") ; }"#
+            r#"{ __sf_rt :: render_text ! (__sf_buf , "outer ") ; __sf_rt :: flush ! (__sf_buf) ; (inner) . render_once_to_sink (__sf_buf) ? ; __sf_rt :: flush ! (__sf_buf) ; __sf_rt :: render_text ! (__sf_buf , " outer") ; }"#
         );
     }
 
@@ -433,11 +1372,18 @@ mod tests {
         let lexer = Parser::new();
         let token_iter = lexer.parse(src);
         let mut ps = SourceBuilder {
-            escape: true,
+            escape: EscapeMode::Html,
+            filters: Vec::new(),
             source: String::with_capacity(token_iter.original_source.len()),
             source_map: SourceMap::default(),
+            block_stack: Vec::new(),
+            macro_stack: Vec::new(),
+            context: ContextTracker::new(),
+            errors: Vec::new(),
+            code_ranges: Vec::new(),
         };
-        ps.feed_tokens(token_iter.clone()).unwrap();
+        ps.feed_tokens(token_iter.clone());
+        assert!(ps.errors.is_empty());
         assert_eq!(
             &Translator::new()
                 .translate(token_iter)
@@ -445,7 +1391,155 @@ mod tests {
                 .ast
                 .into_token_stream()
                 .to_string(),
-            r#"{ __sf_rt :: render_text ! (__sf_buf , "outer ") ; __sf_rt :: render ! (__sf_buf , sailfish :: runtime :: filter :: upper (& (inner . render_once () ?))) ; __sf_rt :: render_text ! (__sf_buf , " outer") ; }"#
+            r#"{ __sf_rt :: render_text ! (__sf_buf , "outer ") ; __sf_rt :: flush ! (__sf_buf) ; __sf_rt :: render ! (__sf_buf , sailfish :: runtime :: filter :: upper (& (inner . render_once () ?))) ; __sf_rt :: flush ! (__sf_buf) ; __sf_rt :: render_text ! (__sf_buf , " outer") ; }"#
+        );
+    }
+
+    #[test]
+    fn translate_blocks() {
+        let src =
+            r#"<%@ extends "base.stpl" %><%@ block title %>a<%@ super %><%@ endblock %>"#;
+        assert_eq!(
+            &Translator::new()
+                .translate(Parser::new().parse(src))
+                .unwrap()
+                .ast
+                .into_token_stream()
+                .to_string(),
+            r#"{ __sf_extends ! ("base.stpl") ; '__sf_block_title : { __sf_rt :: render_text ! (__sf_buf , "a") ; __sf_super ! () ; } }"#
+        );
+
+        let src = "<%@ block title %>a";
+        let err = Translator::new().translate(Parser::new().parse(src));
+        assert!(err.is_err());
+
+        let src = "a<%@ endblock %>";
+        let err = Translator::new().translate(Parser::new().parse(src));
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn translate_macros() {
+        let src = r#"<%@ import "m.stpl" %><%@ macro item(name: &str) %><li><%= name %></li><%@ endmacro %><%@ call item("a") %>"#;
+        assert_eq!(
+            &Translator::new()
+                .translate(Parser::new().parse(src))
+                .unwrap()
+                .ast
+                .into_token_stream()
+                .to_string(),
+            r#"{ __sf_import ! ("m.stpl") ; let __sf_macro_item = | __sf_buf : & mut _ , name : & str | -> :: std :: result :: Result < () , __sf_rt :: RenderError > { __sf_rt :: render_text ! (__sf_buf , "<li>") ; __sf_rt :: render_escaped ! (__sf_buf , name) ; __sf_rt :: render_text ! (__sf_buf , "</li>") ; Ok (()) } ; __sf_macro_item (& mut * __sf_buf , "a") ? ; }"#
+        );
+
+        for src in [
+            "<%@ macro item %><%@ endmacro %>",
+            "<%@ macro item(a b) %><%@ endmacro %>",
+            "<%@ macro item(a, b: &str) %><%@ endmacro %>",
+            "<%@ macro item() %>",
+            "<%@ endmacro %>",
+            "<%@ macro item() %><%@ block a %><%@ endmacro %><%@ endblock %>",
+            "<%@ call self.item() %>",
+        ] {
+            let err = Translator::new().translate(Parser::new().parse(src));
+            assert!(err.is_err(), "{}", src);
+        }
+    }
+
+    #[test]
+    fn translate_multiple_errors() {
+        let src = "<%= a | 1 %>\n<%@ endblock %>\n<% let = 1; %>\n<%@ block b %>";
+        let err = Translator::new()
+            .translate(Parser::new().parse(src))
+            .err()
+            .unwrap();
+        let errors: Vec<_> = std::iter::once(&err).chain(err.related()).collect();
+        assert_eq!(errors.len(), 4);
+        assert!(matches!(errors[0].kind(), ErrorKind::RustSyntaxError(_)));
+        assert!(matches!(errors[1].kind(), ErrorKind::ParseError(_)));
+        assert!(matches!(errors[2].kind(), ErrorKind::RustSyntaxError(_)));
+        assert!(matches!(errors[3].kind(), ErrorKind::ParseError(_)));
+        let offsets: Vec<_> = errors.iter().map(|e| e.offset.unwrap()).collect();
+        assert_eq!(offsets, [4, 25, 36, 54]);
+    }
+
+    #[test]
+    fn translate_multiple_syntax_errors() {
+        let src = "<% let = 1; %>\n<% for x in xs { %>\n<% if { %><% } %>\n<% } %>\n<% f(,); %>";
+        let err = Translator::new()
+            .translate(Parser::new().parse(src))
+            .err()
+            .unwrap();
+        let errors: Vec<_> = std::iter::once(&err).chain(err.related()).collect();
+        assert_eq!(errors.len(), 3);
+        assert!(
+            errors
+                .iter()
+                .all(|e| matches!(e.kind(), ErrorKind::RustSyntaxError(_)))
+        );
+        let offsets: Vec<_> = errors.iter().map(|e| e.offset.unwrap()).collect();
+        assert_eq!(offsets, [7, 52, 66]);
+    }
+
+    #[test]
+    fn translate_escape_mode() {
+        let src = r#"<script><%= a %></script><%- b %>"#;
+        assert_eq!(
+            &Translator::new()
+                .escape(EscapeMode::Xml)
+                .translate(Parser::new().parse(src))
+                .unwrap()
+                .ast
+                .into_token_stream()
+                .to_string(),
+            r#"{ __sf_rt :: render_text ! (__sf_buf , "<script>") ; __sf_rt :: render_escaped_with ! (__sf_buf , a , __sf_rt :: escape :: Xml) ; __sf_rt :: render_text ! (__sf_buf , "</script>") ; __sf_rt :: render ! (__sf_buf , b) ; }"#
+        );
+        assert_eq!(
+            &Translator::new()
+                .escape(EscapeMode::None)
+                .translate(Parser::new().parse("<%= a %>"))
+                .unwrap()
+                .ast
+                .into_token_stream()
+                .to_string(),
+            r#"{ __sf_rt :: render ! (__sf_buf , a) ; }"#
+        );
+    }
+
+    #[test]
+    fn translate_escape_context() {
+        let src = r#"<a href="<%= url %>?q=<%= q %>" onclick="f('<%= s %>')"><%= t %></a><script>g(<%= v %>)</script>"#;
+        assert_eq!(
+            &Translator::new()
+                .translate(Parser::new().parse(src))
+                .unwrap()
+                .ast
+                .into_token_stream()
+                .to_string(),
+            r#"{ __sf_rt :: render_text ! (__sf_buf , "<a href=\"") ; __sf_rt :: render_escaped_in ! (__sf_buf , url , Url) ; __sf_rt :: render_text ! (__sf_buf , "?q=") ; __sf_rt :: render_escaped_in ! (__sf_buf , q , UrlPart) ; __sf_rt :: render_text ! (__sf_buf , "\" onclick=\"f('") ; __sf_rt :: render_escaped_in ! (__sf_buf , s , ScriptString) ; __sf_rt :: render_text ! (__sf_buf , "')\">") ; __sf_rt :: render_escaped ! (__sf_buf , t) ; __sf_rt :: render_text ! (__sf_buf , "</a><script>g(") ; __sf_rt :: render_escaped_in ! (__sf_buf , v , Script) ; __sf_rt :: render_text ! (__sf_buf , ")</script>") ; }"#
+        );
+    }
+
+    #[test]
+    fn translate_locations() {
+        let src = "<% let a = f()?; %>\n<%= a %><% let g = || h()?; %>\n<%+ b %>";
+        assert_eq!(
+            &Translator::new()
+                .path("a.stpl".to_owned())
+                .translate(Parser::new().parse(src))
+                .unwrap()
+                .ast
+                .into_token_stream()
+                .to_string(),
+            r#"{ # [__sf_loc = "a.stpl:1"] let a = __sf_rt :: located ! (f () , "a.stpl" , 1) ; __sf_rt :: render_text ! (__sf_buf , "\n") ; # [__sf_loc = "a.stpl:2"] __sf_rt :: render_escaped ! (__sf_buf , a , "a.stpl" , 2) ; # [__sf_loc = "a.stpl:2"] let g = | | h () ? ; __sf_rt :: render_text ! (__sf_buf , "\n") ; __sf_rt :: flush ! (__sf_buf) ; # [__sf_loc = "a.stpl:3"] __sf_rt :: located ! ((b) . render_once_to_sink (__sf_buf) , "a.stpl" , 3) ; __sf_rt :: flush ! (__sf_buf) ; }"#
+        );
+    }
+
+    #[test]
+    fn location_comments() {
+        let code = "{\n    #[__sf_loc = \"a.stpl:2\"]\n    for x in xs {\n        # [__sf_loc = \"a.stpl:3\"] f(x);\n    }\n}\n";
+        assert_eq!(
+            locations_to_comments(code),
+            "{\n    for x in xs { // a.stpl:2\n        f(x);\n    }\n}\n"
         );
     }
 }
@@ -34,20 +34,28 @@ fn find_rustfmt() -> io::Result<Option<PathBuf>> {
 
 /// Format block expression using `rustfmt` command
 pub fn rustfmt_block(source: &str) -> io::Result<String> {
+    let mut new_source = String::with_capacity(source.len() + 11);
+    new_source.push_str("fn render()");
+    new_source.push_str(source);
+
+    let mut s = rustfmt(&new_source)?;
+    let brace_offset = s.find('{').unwrap();
+    s.replace_range(..brace_offset, "");
+    Ok(s)
+}
+
+/// Format Rust source file using `rustfmt` command
+pub fn rustfmt(source: &str) -> io::Result<String> {
     let rustfmt = match find_rustfmt()? {
         Some(p) => p,
         None => {
             return Err(io::Error::new(
                 io::ErrorKind::NotFound,
                 "rustfmt command not found",
-            ))
+            ));
         }
     };
 
-    let mut new_source = String::with_capacity(source.len() + 11);
-    new_source.push_str("fn render()");
-    new_source.push_str(source);
-
     let mut child = Command::new(rustfmt)
         .args(["--emit", "stdout", "--color", "never", "--quiet"])
         .stdin(Stdio::piped())
@@ -59,21 +67,14 @@ pub fn rustfmt_block(source: &str) -> io::Result<String> {
         .stdin
         .as_mut()
         .ok_or_else(|| io::Error::from(io::ErrorKind::BrokenPipe))?;
-    stdin.write_all(new_source.as_bytes())?;
+    stdin.write_all(source.as_bytes())?;
 
     let output = child.wait_with_output()?;
 
     if output.status.success() {
-        let mut s =
-            String::from_utf8(output.stdout).expect("rustfmt output is non-UTF-8!");
-        let brace_offset = s.find('{').unwrap();
-        s.replace_range(..brace_offset, "");
-        Ok(s)
+        Ok(String::from_utf8(output.stdout).expect("rustfmt output is non-UTF-8!"))
     } else {
-        Err(io::Error::new(
-            io::ErrorKind::Other,
-            "rustfmt command failed",
-        ))
+        Err(io::Error::other("rustfmt command failed"))
     }
 }
 
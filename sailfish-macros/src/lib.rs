@@ -31,3 +31,10 @@ pub fn derive_template_simple(tokens: TokenStream) -> TokenStream {
     let output = sailfish_compiler::procmacro::derive_template_simple(input);
     TokenStream::from(output)
 }
+
+#[proc_macro_derive(TemplateData)]
+pub fn derive_template_data(tokens: TokenStream) -> TokenStream {
+    let input = proc_macro2::TokenStream::from(tokens);
+    let output = sailfish_compiler::procmacro::derive_template_data(input);
+    TokenStream::from(output)
+}
@@ -20,7 +20,7 @@
 
 #[test]
 fn compile_error() {
-    if std::env::var("SAILFISH_INTEGRATION_TESTS").map_or(false, |v| v == "1") {
+    if std::env::var("SAILFISH_INTEGRATION_TESTS").is_ok_and(|v| v == "1") {
         let t = trybuild::TestCases::new();
         t.compile_fail("tests/fails/*.rs");
     }
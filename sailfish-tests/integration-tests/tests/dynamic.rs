@@ -0,0 +1,59 @@
+use integration_tests::assert_string_eq;
+use sailfish::dynamic::{CompileError, TemplateData, compile};
+use std::path::PathBuf;
+
+#[derive(TemplateData)]
+struct Member {
+    name: String,
+    score: u8,
+}
+
+#[derive(TemplateData)]
+struct Scoreboard {
+    title: String,
+    members: Vec<Member>,
+    note: Option<String>,
+}
+
+fn template_path(name: &str) -> PathBuf {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("templates");
+    path.push(name);
+    path
+}
+
+// Dynamic templates are built by invoking `cargo build`, which takes a while
+#[test]
+fn dynamic() {
+    if std::env::var("SAILFISH_INTEGRATION_TESTS").is_ok_and(|v| v == "1") {
+        let template = compile::<Scoreboard, _>(template_path("dynamic.stpl")).unwrap();
+        assert_eq!(template.deps(), &[template_path("dynamic.stpl")]);
+
+        let data = Scoreboard {
+            title: "Scores & Ranks".to_owned(),
+            members: vec![
+                Member {
+                    name: "Jiangsu".to_owned(),
+                    score: 43,
+                },
+                Member {
+                    name: "Osaka".to_owned(),
+                    score: 38,
+                },
+            ],
+            note: Some("Updated daily".to_owned()),
+        };
+
+        let expected = std::fs::read_to_string(template_path("dynamic.out")).unwrap();
+        assert_string_eq!(
+            &*template.render(&data).unwrap(),
+            expected.strip_suffix('\n').unwrap_or(&expected)
+        );
+
+        match compile::<Scoreboard, _>(template_path("dynamic_error.stpl")) {
+            Err(CompileError::Build(output)) => assert!(output.contains("subtitle")),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("template with unknown variable must not compile"),
+        }
+    }
+}
@@ -0,0 +1,11 @@
+use sailfish::TemplateOnce;
+
+#[derive(TemplateOnce)]
+#[template(source = "<div>\n  <%= name\n</div>")]
+struct InlineSourceError {
+    name: String
+}
+
+fn main() {
+    println!("{}", InlineSourceError { name: "Hanako".to_owned() }.render_once().unwrap());
+}
@@ -0,0 +1,11 @@
+use sailfish::TemplateOnce;
+
+#[derive(TemplateOnce)]
+#[template(source = "<style>p { color: red; }</style>\n<p><%= self.missing %></p>")]
+struct InlineTypeError {
+    name: String,
+}
+
+fn main() {
+    println!("{}", InlineTypeError { name: "Hanako".to_owned() }.render_once().unwrap());
+}
@@ -1,5 +1,4 @@
 use sailfish::TemplateOnce;
-use sailfish_macros::TemplateOnce;
 
 #[derive(TemplateOnce)]
 #[template(path = "missing_semicolon.stpl")]
@@ -0,0 +1,11 @@
+use sailfish::TemplateOnce;
+
+#[derive(TemplateOnce)]
+#[template(path = "empty.stpl", source = "<%= name %>")]
+struct PathAndSource {
+    name: String
+}
+
+fn main() {
+    println!("{}", PathAndSource { name: "Hanako".to_owned() }.render_once().unwrap());
+}
@@ -0,0 +1,11 @@
+use sailfish::TemplateOnce;
+
+#[derive(TemplateOnce)]
+#[template(path = "type_error.stpl")]
+struct TypeError {
+    count: u32,
+}
+
+fn main() {
+    println!("{}", TypeError { count: 1 }.render_once().unwrap());
+}
@@ -0,0 +1,11 @@
+use sailfish::TemplateOnce;
+
+#[derive(TemplateOnce)]
+#[template(source = "<%@ macro card(title) %><h2><%= title %></h2><%@ endmacro %>\n<%@ call card(&self.title) %>")]
+struct UntypedMacroParam {
+    title: String
+}
+
+fn main() {
+    println!("{}", UntypedMacroParam { title: "Hanako".to_owned() }.render_once().unwrap());
+}
@@ -0,0 +1,54 @@
+#![cfg(feature = "hot-reload")]
+
+use sailfish::TemplateOnce;
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+#[derive(TemplateOnce)]
+#[template(path = "hot_reload.stpl")]
+struct HotReload<'a> {
+    title: &'a str,
+}
+
+// Restore the template file (and its modification time so that cargo does not
+// rebuild the tests) even if the test fails
+struct RestoreGuard {
+    path: PathBuf,
+    content: String,
+    modified: SystemTime,
+}
+
+impl Drop for RestoreGuard {
+    fn drop(&mut self) {
+        fs::write(&self.path, &self.content).unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&self.path)
+            .and_then(|f| f.set_modified(self.modified))
+            .unwrap();
+    }
+}
+
+#[test]
+fn hot_reload() {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("templates")
+        .join("hot_reload.stpl");
+    let _guard = RestoreGuard {
+        content: fs::read_to_string(&path).unwrap(),
+        modified: fs::metadata(&path).unwrap().modified().unwrap(),
+        path: path.clone(),
+    };
+
+    let render = || HotReload { title: "<Title>" }.render_once().unwrap();
+    assert_eq!(render(), "<h1>&lt;Title&gt;</h1>");
+
+    // modifications are reflected without recompilation
+    fs::write(&path, "<h2><%- self.title %> <%= \"&\" %></h2>").unwrap();
+    assert_eq!(render(), "<h2><Title> &amp;</h2>");
+
+    // fall back to the compiled template if the interpreter cannot evaluate it
+    fs::write(&path, "<% for _ in 0..2 { %>x<% } %>").unwrap();
+    assert_eq!(render(), "<h1>&lt;Title&gt;</h1>");
+}
@@ -0,0 +1,60 @@
+use sailfish::integration::axum::IntoResponse;
+use sailfish::integration::{actix, hyper};
+use sailfish::{RenderError, TemplateOnce, TemplateSimple};
+
+#[derive(TemplateOnce)]
+#[template(source = "<p><%= self.message %></p>")]
+struct Page {
+    message: &'static str,
+}
+
+#[derive(TemplateSimple)]
+#[template(source = r#"{"message": "<%= message %>"}"#, escape = "json")]
+struct Payload {
+    message: &'static str,
+}
+
+#[derive(TemplateOnce)]
+#[template(source = "<% if self.fail { return Err(RenderError::new(\"broken\")); } %>")]
+struct Broken {
+    fail: bool,
+}
+
+#[test]
+fn axum() {
+    let res = Page { message: "hello" }.into_response();
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.headers()["content-type"], "text/html; charset=utf-8");
+
+    let res = Payload { message: "hello" }.into_response();
+    assert_eq!(res.headers()["content-type"], "application/json");
+
+    let res = Broken { fail: true }.into_response();
+    assert_eq!(res.status(), 500);
+}
+
+#[test]
+fn actix_web() {
+    use actix::Responder;
+
+    let req = actix_web::test::TestRequest::default().to_http_request();
+    let res = Page { message: "hello" }.respond_to(&req);
+    assert_eq!(res.status(), 200);
+    assert_eq!(
+        res.headers().get("content-type").unwrap(),
+        "text/html; charset=utf-8"
+    );
+
+    let res = Broken { fail: true }.respond_to(&req);
+    assert_eq!(res.status(), 500);
+}
+
+#[test]
+fn hyper() {
+    let res = hyper::Response::from(Payload { message: "hello" });
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.headers()["content-type"], "application/json");
+
+    let res = hyper::Response::from(Broken { fail: true });
+    assert_eq!(res.status(), 500);
+}
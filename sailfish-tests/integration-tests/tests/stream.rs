@@ -0,0 +1,61 @@
+use futures_core::Stream;
+use sailfish::TemplateOnce;
+use sailfish::stream::RenderStream;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+struct Unpark(Thread);
+
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+fn collect_chunks(mut stream: RenderStream) -> Vec<String> {
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut chunks = Vec::new();
+    loop {
+        match Pin::new(&mut stream).poll_next(&mut cx) {
+            Poll::Ready(Some(chunk)) => {
+                chunks.push(String::from_utf8(chunk.unwrap().to_vec()).unwrap())
+            }
+            Poll::Ready(None) => return chunks,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+#[derive(TemplateOnce)]
+#[template(source = "<li><%= self.name %></li>")]
+struct Item {
+    name: String,
+}
+
+#[derive(TemplateOnce)]
+#[template(
+    source = "<head></head><%@ flush %><ul><% for name in self.names { %><%+ Item { name } %><% } %></ul>"
+)]
+struct Page {
+    names: Vec<String>,
+}
+
+#[test]
+fn stream_chunks() {
+    let page = Page {
+        names: vec!["foo".to_owned(), "<bar>".to_owned()],
+    };
+    assert_eq!(
+        collect_chunks(page.render_once_to_stream()),
+        [
+            "<head></head>",
+            "<ul>",
+            "<li>foo</li>",
+            "<li>&lt;bar&gt;</li>",
+            "</ul>"
+        ]
+    );
+}
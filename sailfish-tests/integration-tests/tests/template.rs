@@ -1,8 +1,11 @@
+// `rust_macro.stpl` deliberately exercises `matches!` inside a template
+#![allow(clippy::redundant_pattern_matching)]
+
 extern crate sailfish_macros;
 
 use integration_tests::assert_string_eq;
-use sailfish::runtime::RenderResult;
-use sailfish::{Template, TemplateMut, TemplateOnce};
+use sailfish::runtime::{Location, RenderResult};
+use sailfish::{Displayed, Limits, RenderError, Template, TemplateMut, TemplateOnce};
 //use sailfish::TemplateSimple;
 use std::path::PathBuf;
 
@@ -396,3 +399,412 @@ fn test_method() {
         },
     );
 }
+
+#[derive(Template)]
+#[template(path = "extends.stpl")]
+struct Extends<'a> {
+    title: &'a str,
+    items: &'a [&'a str],
+    year: u32,
+}
+
+#[test]
+fn test_extends() {
+    assert_render(
+        "extends",
+        Extends {
+            title: "<Items>",
+            items: &["foo", "bar"],
+            year: 2024,
+        },
+    );
+}
+
+#[derive(TemplateOnce)]
+#[template(source = "<% for msg in self.messages { %><p><%= msg %></p><% } %>")]
+struct InlineSource<'a> {
+    messages: &'a [&'a str],
+}
+
+#[test]
+fn test_inline_source() {
+    let messages = &["Hello", "<World>"];
+    assert_string_eq!(
+        &*InlineSource { messages }.render_once().unwrap(),
+        "<p>Hello</p><p>&lt;World&gt;</p>"
+    );
+}
+
+#[derive(TemplateOnce)]
+#[template(
+    source = "<item><title><%= self.title %></title><link><%= self.link %></link></item>",
+    escape = "xml"
+)]
+struct XmlFeed<'a> {
+    title: &'a str,
+    link: &'a str,
+}
+
+#[derive(TemplateOnce)]
+#[template(
+    source = r#"{"name": "<%= self.name %>", "age": <%= self.age %>}"#,
+    escape = "json"
+)]
+struct JsonPayload<'a> {
+    name: &'a str,
+    age: u32,
+}
+
+struct Latex;
+
+impl sailfish::runtime::escape::Escaper for Latex {
+    fn escape(feed: &str, buf: &mut sailfish::runtime::Buffer) {
+        for c in feed.chars() {
+            if "#$%&_{}".contains(c) {
+                buf.push('\\');
+            }
+            buf.push(c);
+        }
+    }
+}
+
+#[derive(TemplateOnce)]
+#[template(source = r"\textbf{<%= self.text %>}", escape = "crate::Latex")]
+struct LatexDocument<'a> {
+    text: &'a str,
+}
+
+#[test]
+fn test_escape_mode() {
+    let feed = XmlFeed {
+        title: "Tom & Jerry's <show>",
+        link: "https://example.com/?a=1&b=2",
+    };
+    assert_eq!(
+        feed.render_once().unwrap(),
+        "<item><title>Tom &amp; Jerry&apos;s &lt;show&gt;</title>\
+         <link>https://example.com/?a=1&amp;b=2</link></item>"
+    );
+
+    let payload = JsonPayload {
+        name: "\"Sail\"\nfish</script>",
+        age: 5,
+    };
+    assert_eq!(
+        payload.render_once().unwrap(),
+        r#"{"name": "\"Sail\"\nfish</script>", "age": 5}"#
+    );
+
+    let doc = LatexDocument {
+        text: "100% & more",
+    };
+    assert_eq!(doc.render_once().unwrap(), r"\textbf{100\% \& more}");
+}
+
+#[test]
+fn test_content_type() {
+    assert_eq!(XmlFeed::CONTENT_TYPE, "application/xml; charset=utf-8");
+    assert_eq!(JsonPayload::CONTENT_TYPE, "application/json");
+    assert_eq!(LatexDocument::CONTENT_TYPE, "text/plain; charset=utf-8");
+    assert_eq!(Mail::CONTENT_TYPE, "text/plain; charset=utf-8");
+    assert_eq!(Empty::CONTENT_TYPE, "text/html; charset=utf-8");
+}
+
+#[derive(TemplateOnce)]
+#[template(path = "mail.txt.stpl")]
+struct Mail<'a> {
+    name: &'a str,
+    item: &'a str,
+}
+
+#[test]
+fn test_escape_pattern() {
+    assert_render_once(
+        "mail",
+        Mail {
+            name: "Tom & Jerry",
+            item: "<Sailfish>",
+        },
+    );
+}
+
+#[derive(TemplateOnce)]
+#[template(path = "whitespace_control.stpl", escape = false)]
+struct WhitespaceControl<'a> {
+    items: &'a [&'a str],
+}
+
+#[test]
+fn test_whitespace_control() {
+    assert_render_once(
+        "whitespace_control",
+        WhitespaceControl {
+            items: &["foo", "sailfish"],
+        },
+    );
+}
+
+#[derive(Template)]
+#[template(path = "macro.stpl")]
+struct Macro<'a> {
+    title: &'a str,
+    items: &'a [&'a str],
+}
+
+#[test]
+fn test_macro() {
+    assert_render(
+        "macro",
+        Macro {
+            title: "<Items>",
+            items: &["foo", "bar"],
+        },
+    );
+}
+
+#[derive(TemplateOnce)]
+#[template(path = "escape_context.stpl")]
+struct EscapeContext<'a> {
+    url: &'a str,
+    query: &'a str,
+    message: &'a str,
+    handler: &'a str,
+    color: &'a str,
+    background: &'a str,
+    size: &'a str,
+    margin: &'a str,
+    count: u32,
+}
+
+#[test]
+fn test_escape_context() {
+    assert_render_once(
+        "escape_context",
+        EscapeContext {
+            url: "javascript:alert(1)",
+            query: "a&b c",
+            message: "\" onmouseover=alert(1) x\"');<b>",
+            handler: "a onmouseover=alert(1)//",
+            color: "red;}",
+            background: "#fff",
+            size: "1.5em",
+            margin: "0 -2px 10%",
+            count: 42,
+        },
+    );
+}
+
+mod custom_filters {
+    use std::fmt::Display;
+
+    pub fn exclaim<T: Display + ?Sized>(expr: &T) -> String {
+        format!("{}!", expr)
+    }
+
+    // shadows the built-in filter
+    pub fn trim<T: Display + ?Sized>(expr: &T) -> String {
+        format!("[{}]", expr)
+    }
+}
+
+#[derive(TemplateOnce)]
+#[template(
+    source = "<%= self.name | exclaim | upper %> <%= self.tag | trim %> <%= self.name | truncate(4) %>",
+    filters = "crate::custom_filters"
+)]
+struct CustomFilters<'a> {
+    name: &'a str,
+    tag: &'a str,
+}
+
+#[test]
+fn test_custom_filters() {
+    assert_string_eq!(
+        &*CustomFilters {
+            name: "sailfish",
+            tag: "<b>"
+        }
+        .render_once()
+        .unwrap(),
+        "SAILFISH! [&lt;b&gt;] sail..."
+    );
+}
+
+#[test]
+fn test_render_to_writer() {
+    use sailfish::runtime::WriterSink;
+
+    let strs = &["foo", "<bar>", "baz"];
+    let expected = Include { strs }.render().unwrap();
+
+    let mut output = Vec::new();
+    Include { strs }.render_to_writer(&mut output).unwrap();
+    assert_string_eq!(std::str::from_utf8(&output).unwrap(), &*expected);
+
+    // flush at every checkpoint
+    let mut sink = WriterSink::with_threshold(Vec::new(), 0);
+    Include { strs }.render_once_to_sink(&mut sink).unwrap();
+    let output = sink.finish().unwrap();
+    assert_string_eq!(std::str::from_utf8(&output).unwrap(), &*expected);
+}
+
+#[derive(Template)]
+#[template(source = "<b><%= self.name %></b>")]
+struct Bold {
+    name: String,
+}
+
+#[test]
+fn test_displayed() {
+    let bold = Bold {
+        name: "<Sailfish>".to_owned(),
+    };
+    assert_eq!(
+        format!("<p>{}</p>", Displayed(bold)),
+        "<p><b>&lt;Sailfish&gt;</b></p>"
+    );
+}
+
+#[derive(Template)]
+enum Page {
+    #[template(source = "<p>Loading...</p>")]
+    Loading,
+    #[template(source = "<p class=\"error\"><%= msg %> (<%= code %>)</p>")]
+    Error { msg: String, code: u16 },
+    #[template(path = "enum_ready.stpl")]
+    Ready { items: Vec<String> },
+    #[template(source = "<p><%= _0 %>/<%= _1 %></p>")]
+    Progress(u32, u32),
+}
+
+#[test]
+fn test_enum() {
+    assert_eq!(Page::Loading.render().unwrap(), "<p>Loading...</p>");
+
+    let mut error = Page::Error {
+        msg: "<Not Found>".to_owned(),
+        code: 404,
+    };
+    assert_eq!(
+        error.render_mut().unwrap(),
+        "<p class=\"error\">&lt;Not Found&gt; (404)</p>"
+    );
+
+    assert_render(
+        "enum_ready",
+        Page::Ready {
+            items: vec!["foo".to_owned(), "<bar>".to_owned()],
+        },
+    );
+    assert_eq!(Page::Progress(3, 10).render_once().unwrap(), "<p>3/10</p>");
+}
+
+#[derive(Template)]
+#[template(
+    source = "<ul><% for item in &self.items { %><li><%= item %></li><% } %></ul>"
+)]
+struct List {
+    items: Vec<String>,
+}
+
+#[derive(TemplateOnce)]
+#[template(source = "<% for _ in 0..self.0 { %><br><% } %>")]
+struct LineBreaks(usize);
+
+#[test]
+fn test_render_with_limits() {
+    let limits = Limits {
+        max_bytes: Some(100),
+    };
+    let list = List {
+        items: vec!["foo".to_owned(); 5],
+    };
+    assert_eq!(list.render_with_limits(&limits).unwrap().len(), 69);
+
+    let list = List {
+        items: vec!["<foo>".to_owned(); 1_000_000],
+    };
+    assert!(matches!(
+        list.render_with_limits(&limits),
+        Err(RenderError::LimitExceeded)
+    ));
+    assert!(matches!(
+        list.render_once_with_limits(&limits),
+        Err(RenderError::LimitExceeded)
+    ));
+    assert!(matches!(
+        LineBreaks(1_000_000).render_once_with_limits(&limits),
+        Err(RenderError::LimitExceeded)
+    ));
+    assert_eq!(
+        Page::Progress(3, 10)
+            .render_once_with_limits(&Limits::default())
+            .unwrap(),
+        "<p>3/10</p>"
+    );
+}
+
+#[derive(TemplateOnce)]
+#[template(
+    source = "<% let id: u32 = self.id.parse().map_err(|_| RenderError::new(\"invalid id\"))?; %><li><%= id %></li>"
+)]
+struct ItemId<'a> {
+    id: &'a str,
+}
+
+struct Footer(bool);
+
+impl sailfish::runtime::Render for Footer {
+    fn render(&self, b: &mut sailfish::runtime::Buffer) -> Result<(), RenderError> {
+        if self.0 {
+            b.push_str("footer");
+            Ok(())
+        } else {
+            Err(RenderError::new("no footer"))
+        }
+    }
+}
+
+#[derive(TemplateOnce)]
+#[template(path = "error_location.stpl")]
+struct ItemList<'a> {
+    ids: Vec<&'a str>,
+    footer: Footer,
+}
+
+#[test]
+fn test_error_location() {
+    let list = ItemList {
+        ids: vec!["1", "x"],
+        footer: Footer(true),
+    };
+    let err = list.render_once().unwrap_err();
+    assert!(matches!(err.inner(), RenderError::Msg(_)));
+    assert_eq!(
+        err.trace(),
+        [
+            Location {
+                path: "ItemId",
+                line: 1
+            },
+            Location {
+                path: "templates/error_location.stpl",
+                line: 3
+            }
+        ]
+    );
+    assert_eq!(
+        err.to_string(),
+        "invalid id at ItemId:1, called from templates/error_location.stpl:3"
+    );
+
+    let list = ItemList {
+        ids: vec!["1"],
+        footer: Footer(false),
+    };
+    let err = list.render_once().unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "no footer at templates/error_location.stpl:6"
+    );
+}
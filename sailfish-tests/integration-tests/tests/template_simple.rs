@@ -1,8 +1,11 @@
+// `rust_macro.stpl` deliberately exercises `matches!` inside a template
+#![allow(clippy::redundant_pattern_matching)]
+
 extern crate sailfish_macros;
 
 use integration_tests::assert_string_eq;
-use sailfish::runtime::RenderResult;
 use sailfish::TemplateSimple;
+use sailfish::runtime::RenderResult;
 use std::path::PathBuf;
 
 fn assert_render_result(name: &str, result: RenderResult) {
@@ -274,6 +277,61 @@ fn test_json_filter() {
     assert_render_simple("json_filter", JsonFilter { data });
 }
 
+#[derive(TemplateSimple)]
+#[template(escape = false)]
+enum Greeting<'a> {
+    #[template(source = "Hello, <%= name %>!")]
+    Hello {
+        #[template(rename = "name")]
+        user: &'a str,
+    },
+    #[template(source = "Bye.")]
+    Bye,
+}
+
+#[test]
+fn test_enum() {
+    assert_eq!(
+        Greeting::Hello { user: "<World>" }.render_once().unwrap(),
+        "Hello, <World>!"
+    );
+    assert_eq!(Greeting::Bye.render_once().unwrap(), "Bye.");
+}
+
+#[derive(TemplateSimple)]
+#[template(source = "<%= _0 %>: <%= _1 %>")]
+struct Pair(&'static str, u32);
+
+#[derive(TemplateSimple)]
+#[template(source = "<%= user %> (<%= _1 %>)")]
+struct Account(
+    #[template(rename = "user")] String,
+    u32,
+    #[template(skip)] Vec<u8>,
+);
+
+#[derive(TemplateSimple)]
+#[template(source = "<%= user %>")]
+struct Session {
+    #[template(rename = "user")]
+    name: &'static str,
+    #[template(skip)]
+    _token: std::rc::Rc<str>,
+}
+
+#[test]
+fn test_field_bindings() {
+    assert_eq!(Pair("age", 42).render_once().unwrap(), "age: 42");
+    let account = Account("<admin>".to_owned(), 7, vec![1, 2]);
+    assert_eq!(account.2.len(), 2);
+    assert_eq!(account.render_once().unwrap(), "&lt;admin&gt; (7)");
+    let session = Session {
+        name: "guest",
+        _token: "secret".into(),
+    };
+    assert_eq!(session.render_once().unwrap(), "guest");
+}
+
 #[cfg(unix)]
 mod unix {
     use super::*;
@@ -0,0 +1,251 @@
+/// Data which can be passed to dynamically compiled templates
+///
+/// All data is serialized to byte array, and then decoded inside the template
+/// library by the type definition generated from this trait.
+///
+/// You should not implement this trait manually. Use `#[derive(TemplateData)]`
+/// instead.
+///
+/// # Safety
+///
+/// The byte array written by `serialize` method must be decodable by the
+/// `Decode` implementation contained in the code generated by `definition`
+/// method.
+pub unsafe trait TemplateData {
+    /// Rust type name used inside generated code
+    fn type_name() -> String;
+
+    /// Push Rust code which defines this type (and the types it depends on)
+    /// into `defs`.
+    ///
+    /// Definition must implement `sailfish::dynamic::runtime::Decode` trait.
+    /// Nothing is pushed for builtin types.
+    fn definition(defs: &mut Vec<String>);
+
+    /// Names of the fields which are exposed to the template as local variables
+    fn fields() -> &'static [&'static str];
+
+    /// Serialize the data into the byte array
+    fn serialize(&self, v: &mut Vec<u8>);
+}
+
+macro_rules! impl_for_num {
+    ($($ty:ty)*) => {
+        $(
+            unsafe impl TemplateData for $ty {
+                #[inline]
+                fn type_name() -> String {
+                    stringify!($ty).to_owned()
+                }
+
+                #[inline]
+                fn definition(_: &mut Vec<String>) {}
+
+                #[inline]
+                fn fields() -> &'static [&'static str] {
+                    &[]
+                }
+
+                #[inline]
+                fn serialize(&self, v: &mut Vec<u8>) {
+                    v.extend_from_slice(&self.to_le_bytes());
+                }
+            }
+        )*
+    }
+}
+
+impl_for_num!(u8 u16 u32 u64 u128 i8 i16 i32 i64 i128 f32 f64);
+
+macro_rules! impl_for_builtin {
+    ($ty:ty, |$self:ident, $v:ident| $serialize:expr) => {
+        unsafe impl TemplateData for $ty {
+            #[inline]
+            fn type_name() -> String {
+                stringify!($ty).to_owned()
+            }
+
+            #[inline]
+            fn definition(_: &mut Vec<String>) {}
+
+            #[inline]
+            fn fields() -> &'static [&'static str] {
+                &[]
+            }
+
+            #[inline]
+            fn serialize(&$self, $v: &mut Vec<u8>) {
+                $serialize
+            }
+        }
+    };
+}
+
+// usize and isize are always serialized as 64-bit integers
+impl_for_builtin!(usize, |self, v| (*self as u64).serialize(v));
+impl_for_builtin!(isize, |self, v| (*self as i64).serialize(v));
+impl_for_builtin!(bool, |self, v| v.push(*self as u8));
+impl_for_builtin!(char, |self, v| (*self as u32).serialize(v));
+impl_for_builtin!(String, |self, v| {
+    self.len().serialize(v);
+    v.extend_from_slice(self.as_bytes());
+});
+
+unsafe impl<T: TemplateData> TemplateData for Option<T> {
+    #[inline]
+    fn type_name() -> String {
+        format!("Option<{}>", T::type_name())
+    }
+
+    #[inline]
+    fn definition(defs: &mut Vec<String>) {
+        T::definition(defs);
+    }
+
+    #[inline]
+    fn fields() -> &'static [&'static str] {
+        &[]
+    }
+
+    fn serialize(&self, v: &mut Vec<u8>) {
+        match self {
+            Some(value) => {
+                true.serialize(v);
+                value.serialize(v);
+            }
+            None => false.serialize(v),
+        }
+    }
+}
+
+unsafe impl<T: TemplateData> TemplateData for Vec<T> {
+    #[inline]
+    fn type_name() -> String {
+        format!("Vec<{}>", T::type_name())
+    }
+
+    #[inline]
+    fn definition(defs: &mut Vec<String>) {
+        T::definition(defs);
+    }
+
+    #[inline]
+    fn fields() -> &'static [&'static str] {
+        &[]
+    }
+
+    fn serialize(&self, v: &mut Vec<u8>) {
+        self.len().serialize(v);
+        for value in self {
+            value.serialize(v);
+        }
+    }
+}
+
+unsafe impl<T: TemplateData, const N: usize> TemplateData for [T; N] {
+    #[inline]
+    fn type_name() -> String {
+        format!("[{}; {}]", T::type_name(), N)
+    }
+
+    #[inline]
+    fn definition(defs: &mut Vec<String>) {
+        T::definition(defs);
+    }
+
+    #[inline]
+    fn fields() -> &'static [&'static str] {
+        &[]
+    }
+
+    fn serialize(&self, v: &mut Vec<u8>) {
+        for value in self {
+            value.serialize(v);
+        }
+    }
+}
+
+macro_rules! impl_for_tuple {
+    ($($name:ident $idx:tt)+) => {
+        unsafe impl<$($name: TemplateData),+> TemplateData for ($($name,)+) {
+            fn type_name() -> String {
+                let names: &[String] = &[$($name::type_name()),+];
+                if names.len() == 1 {
+                    format!("({},)", names[0])
+                } else {
+                    format!("({})", names.join(", "))
+                }
+            }
+
+            #[inline]
+            fn definition(defs: &mut Vec<String>) {
+                $($name::definition(defs);)+
+            }
+
+            #[inline]
+            fn fields() -> &'static [&'static str] {
+                &[]
+            }
+
+            #[inline]
+            fn serialize(&self, v: &mut Vec<u8>) {
+                $(self.$idx.serialize(v);)+
+            }
+        }
+    }
+}
+
+impl_for_tuple!(A 0);
+impl_for_tuple!(A 0 B 1);
+impl_for_tuple!(A 0 B 1 C 2);
+impl_for_tuple!(A 0 B 1 C 2 D 3);
+impl_for_tuple!(A 0 B 1 C 2 D 3 E 4);
+impl_for_tuple!(A 0 B 1 C 2 D 3 E 4 F 5);
+impl_for_tuple!(A 0 B 1 C 2 D 3 E 4 F 5 G 6);
+impl_for_tuple!(A 0 B 1 C 2 D 3 E 4 F 5 G 6 H 7);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dynamic::runtime::Decode;
+
+    fn roundtrip<T: TemplateData + Decode>(value: &T) -> T {
+        let mut v = Vec::new();
+        value.serialize(&mut v);
+
+        let mut data = &*v;
+        let decoded = T::decode(&mut data);
+        assert!(data.is_empty());
+        decoded
+    }
+
+    #[test]
+    fn type_name() {
+        assert_eq!(u8::type_name(), "u8");
+        assert_eq!(Vec::<Option<String>>::type_name(), "Vec<Option<String>>");
+        assert_eq!(<[char; 3]>::type_name(), "[char; 3]");
+        assert_eq!(<(usize,)>::type_name(), "(usize,)");
+        assert_eq!(<(bool, i64)>::type_name(), "(bool, i64)");
+    }
+
+    #[test]
+    fn serialize_roundtrip() {
+        assert_eq!(roundtrip(&0xdead_beef_u32), 0xdead_beef);
+        assert_eq!(roundtrip(&-42isize), -42);
+        assert_eq!(roundtrip(&1.5f64), 1.5);
+        assert!(roundtrip(&true));
+        assert_eq!(roundtrip(&'🐟'), '🐟');
+        assert_eq!(roundtrip(&String::from("sailfish")), "sailfish");
+        assert_eq!(roundtrip(&Some(3u16)), Some(3));
+        assert_eq!(roundtrip(&None::<u16>), None);
+        assert_eq!(
+            roundtrip(&vec![String::from("a"), String::new()]),
+            vec![String::from("a"), String::new()]
+        );
+        assert_eq!(roundtrip(&[1u8, 2, 3]), [1, 2, 3]);
+        assert_eq!(
+            roundtrip(&(1u8, String::from("b"), vec![Some('c')])),
+            (1, String::from("b"), vec![Some('c')])
+        );
+    }
+}
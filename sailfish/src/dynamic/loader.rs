@@ -0,0 +1,340 @@
+use sailfish_compiler::{Compiler, Config};
+use std::alloc::{self, Layout};
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use super::TemplateData;
+use super::runtime::{ABI_VERSION, AllocVtable, OutputData};
+use crate::runtime::{RenderError, RenderResult};
+
+type RenderFn = unsafe extern "C" fn(u64, *const u8, usize, AllocVtable) -> OutputData;
+
+const RENDER_SYMBOL: &[u8] = b"sailfish_render\0";
+
+/// Error raised while compiling templates at runtime
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum CompileError {
+    /// Failed to translate the template file
+    Template(sailfish_compiler::Error),
+    /// I/O error occurred while generating the template library
+    Io(io::Error),
+    /// `cargo build` command failed. Contains the compiler output.
+    Build(String),
+    /// Failed to load the generated shared library
+    Load(libloading::Error),
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompileError::Template(e) => fmt::Display::fmt(e, f),
+            CompileError::Io(e) => fmt::Display::fmt(e, f),
+            CompileError::Build(output) => {
+                write!(f, "Failed to build template library:\n{}", output)
+            }
+            CompileError::Load(e) => {
+                write!(f, "Failed to load template library: {}", e)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CompileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CompileError::Template(e) => Some(e),
+            CompileError::Io(e) => Some(e),
+            CompileError::Build(_) => None,
+            CompileError::Load(e) => Some(e),
+        }
+    }
+}
+
+impl From<sailfish_compiler::Error> for CompileError {
+    fn from(other: sailfish_compiler::Error) -> Self {
+        CompileError::Template(other)
+    }
+}
+
+impl From<io::Error> for CompileError {
+    fn from(other: io::Error) -> Self {
+        CompileError::Io(other)
+    }
+}
+
+impl From<libloading::Error> for CompileError {
+    fn from(other: libloading::Error) -> Self {
+        CompileError::Load(other)
+    }
+}
+
+/// Template loaded from the shared library generated by [`compile`]
+pub struct DynamicTemplate<Data> {
+    render_fn: RenderFn,
+    deps: Vec<PathBuf>,
+    // must outlive `render_fn`
+    _library: libloading::Library,
+    _marker: PhantomData<fn(&Data)>,
+}
+
+impl<Data: TemplateData> DynamicTemplate<Data> {
+    /// Render the template with given data
+    pub fn render(&self, data: &Data) -> RenderResult {
+        let mut bytes = Vec::new();
+        data.serialize(&mut bytes);
+
+        let vtable = AllocVtable {
+            alloc: vtable_alloc,
+            realloc: vtable_realloc,
+        };
+
+        // SAFETY: `render_fn` was generated by `compile` function for `Data` type,
+        // and `OutputData` is allocated through `vtable` with alignment 1.
+        unsafe {
+            let output =
+                (self.render_fn)(ABI_VERSION, bytes.as_ptr(), bytes.len(), vtable);
+            let s = String::from_raw_parts(output.ptr, output.len, output.capacity);
+            if output.is_err {
+                Err(RenderError::Msg(s))
+            } else {
+                Ok(s)
+            }
+        }
+    }
+
+    /// Template files which this template depends on (including the template
+    /// itself)
+    pub fn deps(&self) -> &[PathBuf] {
+        &self.deps
+    }
+}
+
+unsafe extern "C" fn vtable_alloc(size: usize, align: usize) -> *mut u8 {
+    unsafe { alloc::alloc(Layout::from_size_align_unchecked(size, align)) }
+}
+
+unsafe extern "C" fn vtable_realloc(
+    ptr: *mut u8,
+    size: usize,
+    align: usize,
+    new_size: usize,
+) -> *mut u8 {
+    unsafe {
+        alloc::realloc(
+            ptr,
+            Layout::from_size_align_unchecked(size, align),
+            new_size,
+        )
+    }
+}
+
+/// Compile the template file at runtime.
+///
+/// This function generates Rust code to render the template, builds it as a
+/// shared library by calling `cargo build` command, and then loads the
+/// generated library.
+///
+/// Compiling the same template again after modification loads the new version
+/// of the template.
+pub fn compile<Data: TemplateData, P: AsRef<Path>>(
+    path: P,
+) -> Result<DynamicTemplate<Data>, CompileError> {
+    let input = fs::canonicalize(path.as_ref())?;
+
+    #[cfg(feature = "config")]
+    let mut config = Config::search_file_and_read(input.parent().unwrap_or(&input))?;
+
+    #[cfg(not(feature = "config"))]
+    let mut config = Config::default();
+
+    if let Some(escape) = config.escape_for(&input) {
+        config.escape = escape.clone();
+    }
+
+    let compiler = Compiler::with_config(config);
+    let (tsource, report) = compiler.resolve_file(&input)?;
+    let mut deps = vec![input.clone()];
+    deps.extend(report.deps);
+
+    let type_name = Data::type_name();
+    let crate_name = {
+        let mut hasher = DefaultHasher::new();
+        input.hash(&mut hasher);
+        type_name.hash(&mut hasher);
+        format!("sf_{:016x}", hasher.finish())
+    };
+
+    let root_dir = env::temp_dir().join("sailfish-dynamic");
+    let crate_dir = root_dir.join(&crate_name);
+    let target_dir = root_dir.join("target");
+
+    let template_file = crate_dir.join("template.rs");
+    let tmp_file = crate_dir.join("template.rs.tmp");
+    compiler.compile_file(&input, tsource, &tmp_file)?;
+    let template_code = fs::read_to_string(&tmp_file)?;
+    fs::remove_file(&tmp_file)?;
+
+    let lib_code = generate_lib::<Data>();
+    write_if_changed(
+        &crate_dir.join("Cargo.toml"),
+        &generate_manifest(&crate_name),
+    )?;
+    write_if_changed(&crate_dir.join("lib.rs"), &lib_code)?;
+    write_if_changed(&template_file, &template_code)?;
+
+    build(&crate_dir, &target_dir)?;
+
+    // Copy the library into a unique path, since loading the same path twice
+    // returns the previously loaded library.
+    let content_hash = {
+        let mut hasher = DefaultHasher::new();
+        lib_code.hash(&mut hasher);
+        template_code.hash(&mut hasher);
+        hasher.finish()
+    };
+    let built = target_dir.join("release").join(format!(
+        "{}{}{}",
+        env::consts::DLL_PREFIX,
+        crate_name,
+        env::consts::DLL_SUFFIX
+    ));
+    let loaded = crate_dir.join(format!(
+        "{}{}_{:016x}{}",
+        env::consts::DLL_PREFIX,
+        crate_name,
+        content_hash,
+        env::consts::DLL_SUFFIX
+    ));
+    if !loaded.exists() {
+        fs::copy(&built, &loaded)?;
+    }
+
+    // SAFETY: the library was generated from the code above, and its entry point
+    // has the signature of `RenderFn`.
+    unsafe {
+        let library = libloading::Library::new(&loaded)?;
+        let render_fn = *library.get::<RenderFn>(RENDER_SYMBOL)?;
+
+        Ok(DynamicTemplate {
+            render_fn,
+            deps,
+            _library: library,
+            _marker: PhantomData,
+        })
+    }
+}
+
+fn generate_manifest(crate_name: &str) -> String {
+    let sailfish_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+
+    format!(
+        r#"[package]
+name = "{}"
+version = "0.0.0"
+edition = "2024"
+publish = false
+
+[lib]
+path = "lib.rs"
+crate-type = ["cdylib"]
+
+[dependencies.sailfish]
+path = {:?}
+default-features = false
+features = ["dynamic-runtime"]
+
+[workspace]
+"#,
+        crate_name,
+        sailfish_dir.display().to_string()
+    )
+}
+
+fn generate_lib<Data: TemplateData>() -> String {
+    let type_name = Data::type_name();
+    let mut defs = Vec::new();
+    Data::definition(&mut defs);
+
+    let bind_fields = if Data::fields().is_empty() {
+        format!("let _: {} = __sf_drt::Decode::decode(data);", type_name)
+    } else {
+        format!(
+            "let {} {{ {} }} = __sf_drt::Decode::decode(data);",
+            type_name,
+            Data::fields().join(", ")
+        )
+    };
+
+    format!(
+        r#"// Generated by sailfish. Do not edit.
+#![allow(warnings)]
+
+use sailfish::dynamic::runtime as __sf_drt;
+
+{}
+
+static SIZE_HINT: sailfish::runtime::SizeHint = sailfish::runtime::SizeHint::new();
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sailfish_render(
+    version: u64,
+    data: *const u8,
+    data_len: usize,
+    vtable: __sf_drt::AllocVtable,
+) -> __sf_drt::OutputData {{
+    unsafe {{
+        __sf_drt::render_entry(version, data, data_len, vtable, &SIZE_HINT, |data, __sf_buf| {{
+            {}
+
+            use sailfish::runtime as __sf_rt;
+            include!("template.rs");
+
+            Ok(())
+        }})
+    }}
+}}
+"#,
+        defs.join("\n"),
+        bind_fields
+    )
+}
+
+fn write_if_changed(path: &Path, content: &str) -> io::Result<()> {
+    // avoid updating mtime, which triggers rebuild
+    if fs::read_to_string(path).is_ok_and(|old| old == content) {
+        return Ok(());
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, content)
+}
+
+fn build(crate_dir: &Path, target_dir: &Path) -> Result<(), CompileError> {
+    let cargo = env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    let output = Command::new(cargo)
+        .arg("build")
+        .arg("--release")
+        .arg("--quiet")
+        .arg("--manifest-path")
+        .arg(crate_dir.join("Cargo.toml"))
+        .env("CARGO_TARGET_DIR", target_dir)
+        .output()?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(CompileError::Build(
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        ))
+    }
+}
@@ -0,0 +1,48 @@
+//! Load templates at runtime
+//!
+//! [`compile`] function translates the template file into Rust code, builds it
+//! as a shared library by calling `cargo build`, and then loads the generated
+//! library. Data passed to the template is checked against the template by the
+//! Rust compiler, so rendering never fails because of type mismatch.
+//!
+//! ```no_run
+//! use sailfish::dynamic::{compile, DynamicTemplate, TemplateData};
+//!
+//! #[derive(TemplateData)]
+//! pub struct Team {
+//!     name: String,
+//!     score: u8,
+//! }
+//!
+//! let template: DynamicTemplate<Team> = compile("templates/team.stpl").unwrap();
+//! let data = Team {
+//!     name: "Jiangsu".into(),
+//!     score: 43,
+//! };
+//! println!("{}", template.render(&data).unwrap());
+//! ```
+//!
+//! Fields of the data are accessible as local variables inside templates (same
+//! as `TemplateSimple`).
+//!
+//! Generated crates are stored in `sailfish-dynamic` directory inside the
+//! system temporary directory, and the `sailfish` crate is referenced by the
+//! path of its source code, so `cargo` command and the source code must be
+//! available at runtime.
+
+pub mod runtime;
+
+#[cfg(feature = "dynamic")]
+mod data;
+#[cfg(feature = "dynamic")]
+mod loader;
+
+#[cfg(feature = "dynamic")]
+#[cfg_attr(docsrs, doc(cfg(feature = "dynamic")))]
+pub use data::TemplateData;
+#[cfg(feature = "dynamic")]
+#[cfg_attr(docsrs, doc(cfg(feature = "dynamic")))]
+pub use loader::{CompileError, DynamicTemplate, compile};
+#[cfg(all(feature = "dynamic", feature = "derive"))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "dynamic", feature = "derive"))))]
+pub use sailfish_macros::TemplateData;
@@ -0,0 +1,303 @@
+//! Runtime support for dynamically compiled templates
+//!
+//! Items in this module are used by the shared libraries generated by
+//! `sailfish::dynamic::compile`. You don't need to use them directly.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr::{self, NonNull};
+use std::slice;
+
+use crate::runtime::{Buffer, RenderError, SizeHint};
+
+/// ABI version shared between the renderer and the template libraries.
+///
+/// Template libraries refuse to render when the version passed by the caller
+/// does not match this value.
+pub const ABI_VERSION: u64 = 1;
+
+/// Allocator functions exported by the renderer.
+///
+/// Template libraries may be linked against a different allocator from the
+/// renderer, so every allocation which is returned to the renderer must be made
+/// through this vtable.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct AllocVtable {
+    /// Allocate `size` bytes with alignment `align`
+    pub alloc: unsafe extern "C" fn(size: usize, align: usize) -> *mut u8,
+    /// Resize the allocation at `ptr` from `size` bytes to `new_size` bytes
+    pub realloc: unsafe extern "C" fn(
+        ptr: *mut u8,
+        size: usize,
+        align: usize,
+        new_size: usize,
+    ) -> *mut u8,
+}
+
+/// Buffer whose memory is allocated through [`AllocVtable`]
+pub struct VBuffer {
+    data: NonNull<u8>,
+    len: usize,
+    capacity: usize,
+    vtable: AllocVtable,
+}
+
+impl VBuffer {
+    /// Create an empty buffer which allocates memory through `vtable`
+    #[inline]
+    pub fn from_vtable(vtable: AllocVtable) -> VBuffer {
+        VBuffer {
+            data: NonNull::dangling(),
+            len: 0,
+            capacity: 0,
+            vtable,
+        }
+    }
+
+    /// Returns the length of this buffer in bytes
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if this buffer has a length of zero
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Reserves capacity for at least `size` additional bytes
+    pub fn reserve(&mut self, size: usize) {
+        let required = self.len.checked_add(size).expect("capacity overflow");
+        if required <= self.capacity {
+            return;
+        }
+
+        let new_capacity = std::cmp::max(required, self.capacity * 2);
+        assert!(new_capacity <= isize::MAX as usize, "capacity overflow");
+
+        // SAFETY: `new_capacity` is non-zero, and the old allocation (if any) was
+        // made through the same vtable with alignment 1.
+        let new_ptr = unsafe {
+            if self.capacity == 0 {
+                (self.vtable.alloc)(new_capacity, 1)
+            } else {
+                (self.vtable.realloc)(self.data.as_ptr(), self.capacity, 1, new_capacity)
+            }
+        };
+
+        self.data = NonNull::new(new_ptr).expect("memory allocation failed");
+        self.capacity = new_capacity;
+    }
+
+    /// Appends a given string slice onto the end of this buffer
+    #[inline]
+    pub fn push_str(&mut self, data: &str) {
+        self.reserve(data.len());
+
+        // SAFETY: capacity is reserved above
+        unsafe {
+            ptr::copy_nonoverlapping(
+                data.as_ptr(),
+                self.data.as_ptr().add(self.len),
+                data.len(),
+            );
+        }
+        self.len += data.len();
+    }
+
+    fn into_output(self, is_err: bool) -> OutputData {
+        OutputData {
+            ptr: self.data.as_ptr(),
+            len: self.len,
+            capacity: self.capacity,
+            is_err,
+        }
+    }
+}
+
+/// Rendering result returned from template libraries.
+///
+/// The memory pointed by `ptr` is allocated through [`AllocVtable`], so the
+/// renderer can take its ownership as `String`.
+#[repr(C)]
+pub struct OutputData {
+    /// Pointer to the rendered string (or the error message)
+    pub ptr: *mut u8,
+    /// Length of the output in bytes
+    pub len: usize,
+    /// Capacity of the allocation
+    pub capacity: usize,
+    /// `true` if the output contains an error message
+    pub is_err: bool,
+}
+
+impl OutputData {
+    /// Copy the rendering result into the memory allocated through `vtable`
+    pub fn from_result(result: Result<&str, RenderError>, vtable: AllocVtable) -> Self {
+        let mut buf = VBuffer::from_vtable(vtable);
+        match result {
+            Ok(s) => {
+                buf.push_str(s);
+                buf.into_output(false)
+            }
+            Err(e) => {
+                buf.push_str(&e.to_string());
+                buf.into_output(true)
+            }
+        }
+    }
+}
+
+/// Entry point of the template functions generated by
+/// `sailfish::dynamic::compile`
+///
+/// # Safety
+///
+/// `data` must be valid for reads of `data_len` bytes.
+#[doc(hidden)]
+pub unsafe fn render_entry<F>(
+    version: u64,
+    data: *const u8,
+    data_len: usize,
+    vtable: AllocVtable,
+    size_hint: &SizeHint,
+    f: F,
+) -> OutputData
+where
+    F: FnOnce(&mut &[u8], &mut Buffer) -> Result<(), RenderError>,
+{
+    if version != ABI_VERSION {
+        let msg = format!(
+            "ABI version mismatch (expected {}, found {})",
+            ABI_VERSION, version
+        );
+        return OutputData::from_result(Err(RenderError::Msg(msg)), vtable);
+    }
+
+    // SAFETY: guaranteed by the caller
+    let mut data = unsafe { slice::from_raw_parts(data, data_len) };
+    let mut buf = Buffer::with_capacity(size_hint.get());
+
+    // unwinding across FFI boundary is not allowed
+    let result = panic::catch_unwind(AssertUnwindSafe(|| f(&mut data, &mut buf)))
+        .unwrap_or_else(|_| Err(RenderError::new("template panicked while rendering")));
+
+    match result {
+        Ok(()) => {
+            size_hint.update(buf.len());
+            OutputData::from_result(Ok(buf.as_str()), vtable)
+        }
+        Err(e) => OutputData::from_result(Err(e), vtable),
+    }
+}
+
+/// Types which can be decoded from the bytes serialized by
+/// `TemplateData::serialize`
+///
+/// Decoding panics if the input is malformed.
+pub trait Decode: Sized {
+    /// Decode a value from the front of `data`, and advance `data`
+    fn decode(data: &mut &[u8]) -> Self;
+}
+
+#[inline]
+fn take<'a>(data: &mut &'a [u8], len: usize) -> &'a [u8] {
+    let (head, tail) = data.split_at(len);
+    *data = tail;
+    head
+}
+
+macro_rules! impl_decode_for_num {
+    ($($ty:ty)*) => {
+        $(
+            impl Decode for $ty {
+                #[inline]
+                fn decode(data: &mut &[u8]) -> Self {
+                    let bytes = take(data, std::mem::size_of::<$ty>());
+                    <$ty>::from_le_bytes(bytes.try_into().unwrap())
+                }
+            }
+        )*
+    }
+}
+
+impl_decode_for_num!(u8 u16 u32 u64 u128 i8 i16 i32 i64 i128 f32 f64);
+
+impl Decode for usize {
+    #[inline]
+    fn decode(data: &mut &[u8]) -> Self {
+        u64::decode(data).try_into().expect("integer overflow")
+    }
+}
+
+impl Decode for isize {
+    #[inline]
+    fn decode(data: &mut &[u8]) -> Self {
+        i64::decode(data).try_into().expect("integer overflow")
+    }
+}
+
+impl Decode for bool {
+    #[inline]
+    fn decode(data: &mut &[u8]) -> Self {
+        u8::decode(data) != 0
+    }
+}
+
+impl Decode for char {
+    #[inline]
+    fn decode(data: &mut &[u8]) -> Self {
+        char::from_u32(u32::decode(data)).expect("invalid char")
+    }
+}
+
+impl Decode for String {
+    fn decode(data: &mut &[u8]) -> Self {
+        let len = usize::decode(data);
+        String::from_utf8(take(data, len).to_vec()).expect("invalid UTF-8")
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode(data: &mut &[u8]) -> Self {
+        if bool::decode(data) {
+            Some(T::decode(data))
+        } else {
+            None
+        }
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode(data: &mut &[u8]) -> Self {
+        let len = usize::decode(data);
+        (0..len).map(|_| T::decode(data)).collect()
+    }
+}
+
+impl<T: Decode, const N: usize> Decode for [T; N] {
+    fn decode(data: &mut &[u8]) -> Self {
+        std::array::from_fn(|_| T::decode(data))
+    }
+}
+
+macro_rules! impl_decode_for_tuple {
+    ($($name:ident)+) => {
+        impl<$($name: Decode),+> Decode for ($($name,)+) {
+            #[inline]
+            fn decode(data: &mut &[u8]) -> Self {
+                ($($name::decode(data),)+)
+            }
+        }
+    }
+}
+
+impl_decode_for_tuple!(A);
+impl_decode_for_tuple!(A B);
+impl_decode_for_tuple!(A B C);
+impl_decode_for_tuple!(A B C D);
+impl_decode_for_tuple!(A B C D E);
+impl_decode_for_tuple!(A B C D E F);
+impl_decode_for_tuple!(A B C D E F G);
+impl_decode_for_tuple!(A B C D E F G H);
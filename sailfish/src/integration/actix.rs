@@ -0,0 +1,15 @@
+//! Integration with [actix-web](https://docs.rs/actix-web)
+
+use crate::runtime::RenderResult;
+
+pub use actix_web::body::BoxBody;
+pub use actix_web::{HttpRequest, HttpResponse, Responder};
+
+/// Convert the rendering result into the response
+#[doc(hidden)]
+pub fn into_response(content_type: &'static str, result: RenderResult) -> HttpResponse {
+    match result {
+        Ok(body) => HttpResponse::Ok().content_type(content_type).body(body),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
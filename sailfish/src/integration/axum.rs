@@ -0,0 +1,23 @@
+//! Integration with [axum](https://docs.rs/axum)
+
+use axum_core::body::Body;
+use http::StatusCode;
+use http::header::{CONTENT_TYPE, HeaderValue};
+
+use crate::runtime::RenderResult;
+
+pub use axum_core::response::{IntoResponse, Response};
+
+/// Convert the rendering result into the response
+#[doc(hidden)]
+pub fn into_response(content_type: &'static str, result: RenderResult) -> Response {
+    match result {
+        Ok(body) => {
+            let mut res = Response::new(Body::from(body));
+            res.headers_mut()
+                .insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+            res
+        }
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
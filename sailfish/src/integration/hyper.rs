@@ -0,0 +1,36 @@
+//! Integration with [hyper](https://docs.rs/hyper)
+//!
+//! Since hyper does not have the trait for responses, derived templates can be
+//! converted into [`Response`] with `From` trait.
+//!
+//! ```ignore
+//! let res = sailfish::integration::hyper::Response::from(Hello { name });
+//! ```
+
+use bytes::Bytes;
+use http::StatusCode;
+use http::header::{CONTENT_TYPE, HeaderValue};
+use http_body_util::Full;
+
+use crate::runtime::RenderResult;
+
+/// Response type which templates are converted into
+pub type Response = http::Response<Full<Bytes>>;
+
+/// Convert the rendering result into the response
+#[doc(hidden)]
+pub fn into_response(content_type: &'static str, result: RenderResult) -> Response {
+    match result {
+        Ok(body) => {
+            let mut res = Response::new(Full::new(Bytes::from(body)));
+            res.headers_mut()
+                .insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+            res
+        }
+        Err(_) => {
+            let mut res = Response::default();
+            *res.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            res
+        }
+    }
+}
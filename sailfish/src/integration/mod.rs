@@ -0,0 +1,35 @@
+//! Integration with web frameworks
+//!
+//! When one of the following features is enabled, derived templates implement
+//! the response trait of the framework, and the `Content-Type` header is set
+//! based on the `escape` option of the template (see
+//! [`TemplateOnce::CONTENT_TYPE`](crate::TemplateOnce::CONTENT_TYPE)).
+//!
+//! - `axum`: [`IntoResponse`](axum::IntoResponse)
+//! - `actix-web`: [`Responder`](actix::Responder)
+//! - `hyper`: `From<T>` for [`hyper::Response`]
+//!
+//! If the template failed to render, the response is `500 Internal Server Error`
+//! with an empty body.
+//!
+//! ```ignore
+//! #[derive(TemplateOnce)]
+//! #[template(path = "hello.stpl")]
+//! struct Hello {
+//!     name: String,
+//! }
+//!
+//! async fn hello() -> Hello {
+//!     Hello { name: "World".to_owned() }
+//! }
+//! ```
+
+#[cfg(feature = "actix-web")]
+#[cfg_attr(docsrs, doc(cfg(feature = "actix-web")))]
+pub mod actix;
+#[cfg(feature = "axum")]
+#[cfg_attr(docsrs, doc(cfg(feature = "axum")))]
+pub mod axum;
+#[cfg(feature = "hyper")]
+#[cfg_attr(docsrs, doc(cfg(feature = "hyper")))]
+pub mod hyper;
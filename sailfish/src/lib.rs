@@ -36,16 +36,36 @@
 #![allow(clippy::redundant_closure)]
 #![deny(missing_docs)]
 
+#[cfg(any(feature = "dynamic", feature = "dynamic-runtime"))]
+#[cfg_attr(docsrs, doc(cfg(feature = "dynamic")))]
+pub mod dynamic;
+#[cfg(any(feature = "axum", feature = "actix-web", feature = "hyper"))]
+#[cfg_attr(
+    docsrs,
+    doc(cfg(any(feature = "axum", feature = "actix-web", feature = "hyper")))
+)]
+pub mod integration;
 pub mod runtime;
+#[cfg(feature = "stream")]
+#[cfg_attr(docsrs, doc(cfg(feature = "stream")))]
+pub mod stream;
 
-use runtime::Buffer;
-pub use runtime::{RenderError, RenderResult};
+use runtime::{Buffer, LimitedSink, Sink, WriterSink};
+pub use runtime::{Limits, RenderError, RenderResult};
 #[cfg(feature = "derive")]
 #[cfg_attr(docsrs, doc(cfg(feature = "derive")))]
 pub use sailfish_macros::{Template, TemplateMut, TemplateOnce, TemplateSimple};
+use std::fmt;
+use std::io;
 
 /// Template which can be accessed without using `self`.
 pub trait TemplateSimple: Sized {
+    /// `Content-Type` of the rendered document
+    ///
+    /// Derived implementations determine this value from the `escape` option
+    /// (e.g. `"application/json"` for `escape = "json"`).
+    const CONTENT_TYPE: &'static str = "text/html; charset=utf-8";
+
     /// Render the template and return the rendering result as `RenderResult`
     ///
     /// This method never returns `Err`, unless you explicitly return RenderError
@@ -90,10 +110,67 @@ pub trait TemplateSimple: Sized {
     /// tpl.render_once_to(&mut buffer).unwrap();
     /// ```
     fn render_once_to(self, buf: &mut Buffer) -> Result<(), RenderError>;
+
+    /// Render the template and write the result into `sink`.
+    ///
+    /// Sinks may consume the rendered contents while rendering. See
+    /// [`WriterSink`](runtime::WriterSink) for example.
+    ///
+    /// The default implementation renders the whole template into the buffer of
+    /// `sink`. Derived implementations render each part of the template directly.
+    fn render_once_to_sink<S: Sink>(self, sink: &mut S) -> Result<(), RenderError> {
+        self.render_once_to(sink.buffer())?;
+        sink.checkpoint()
+    }
+
+    /// Render the template and write the result into `writer`.
+    ///
+    /// The output is written in chunks of about
+    /// [`DEFAULT_THRESHOLD`](runtime::DEFAULT_THRESHOLD) bytes, so the whole
+    /// output is never held in memory. Use `render_once_to_sink` method with
+    /// [`WriterSink::with_threshold`](runtime::WriterSink::with_threshold) to
+    /// change the chunk size.
+    fn render_once_to_writer<W: io::Write>(self, writer: W) -> Result<(), RenderError> {
+        let mut sink = WriterSink::new(writer);
+        self.render_once_to_sink(&mut sink)?;
+        sink.finish().map(|_| ())
+    }
+
+    /// Render the template and return the rendering result, or
+    /// `RenderError::LimitExceeded` if the output exceeds `limits`.
+    ///
+    /// The output size is checked after each value or text is rendered, and the
+    /// rendering is aborted once it exceeds the limit. A value is rendered into
+    /// memory before the check, so the memory usage may exceed the limit by the
+    /// size of one rendered value (e.g. a large string field).
+    fn render_once_with_limits(self, limits: &Limits) -> runtime::RenderResult {
+        let mut sink = LimitedSink::new(limits);
+        let result = self.render_once_to_sink(&mut sink);
+        sink.finish(result)
+    }
+
+    /// Render the template into a stream of `Bytes` chunks.
+    ///
+    /// The template is rendered on another thread. See [`stream`] module for
+    /// details.
+    #[cfg(feature = "stream")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "stream")))]
+    fn render_once_to_stream(self) -> stream::RenderStream
+    where
+        Self: Send + 'static,
+    {
+        stream::RenderStream::new(move |sink| self.render_once_to_sink(sink))
+    }
 }
 
 /// Template that can be rendered with consuming itself.
 pub trait TemplateOnce: Sized {
+    /// `Content-Type` of the rendered document
+    ///
+    /// Derived implementations determine this value from the `escape` option
+    /// (e.g. `"application/json"` for `escape = "json"`).
+    const CONTENT_TYPE: &'static str = "text/html; charset=utf-8";
+
     /// Render the template and return the rendering result as `RenderResult`
     ///
     /// This method never returns `Err`, unless you explicitly return RenderError
@@ -138,6 +215,57 @@ pub trait TemplateOnce: Sized {
     /// tpl.render_once_to(&mut buffer).unwrap();
     /// ```
     fn render_once_to(self, buf: &mut Buffer) -> Result<(), RenderError>;
+
+    /// Render the template and write the result into `sink`.
+    ///
+    /// Sinks may consume the rendered contents while rendering. See
+    /// [`WriterSink`](runtime::WriterSink) for example.
+    ///
+    /// The default implementation renders the whole template into the buffer of
+    /// `sink`. Derived implementations render each part of the template directly.
+    fn render_once_to_sink<S: Sink>(self, sink: &mut S) -> Result<(), RenderError> {
+        self.render_once_to(sink.buffer())?;
+        sink.checkpoint()
+    }
+
+    /// Render the template and write the result into `writer`.
+    ///
+    /// The output is written in chunks of about
+    /// [`DEFAULT_THRESHOLD`](runtime::DEFAULT_THRESHOLD) bytes, so the whole
+    /// output is never held in memory. Use `render_once_to_sink` method with
+    /// [`WriterSink::with_threshold`](runtime::WriterSink::with_threshold) to
+    /// change the chunk size.
+    fn render_once_to_writer<W: io::Write>(self, writer: W) -> Result<(), RenderError> {
+        let mut sink = WriterSink::new(writer);
+        self.render_once_to_sink(&mut sink)?;
+        sink.finish().map(|_| ())
+    }
+
+    /// Render the template and return the rendering result, or
+    /// `RenderError::LimitExceeded` if the output exceeds `limits`.
+    ///
+    /// The output size is checked after each value or text is rendered, and the
+    /// rendering is aborted once it exceeds the limit. A value is rendered into
+    /// memory before the check, so the memory usage may exceed the limit by the
+    /// size of one rendered value (e.g. a large string field).
+    fn render_once_with_limits(self, limits: &Limits) -> runtime::RenderResult {
+        let mut sink = LimitedSink::new(limits);
+        let result = self.render_once_to_sink(&mut sink);
+        sink.finish(result)
+    }
+
+    /// Render the template into a stream of `Bytes` chunks.
+    ///
+    /// The template is rendered on another thread. See [`stream`] module for
+    /// details.
+    #[cfg(feature = "stream")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "stream")))]
+    fn render_once_to_stream(self) -> stream::RenderStream
+    where
+        Self: Send + 'static,
+    {
+        stream::RenderStream::new(move |sink| self.render_once_to_sink(sink))
+    }
 }
 
 /// Template that is mutable and can be rendered any number of times.
@@ -197,6 +325,47 @@ pub trait TemplateMut: TemplateOnce {
     /// tpl.render_mut_to(&mut buffer).unwrap();
     /// ```
     fn render_mut_to(&mut self, buf: &mut Buffer) -> Result<(), RenderError>;
+
+    /// Render the template and write the result into `sink`.
+    ///
+    /// Sinks may consume the rendered contents while rendering. See
+    /// [`WriterSink`](runtime::WriterSink) for example.
+    ///
+    /// The default implementation renders the whole template into the buffer of
+    /// `sink`. Derived implementations render each part of the template directly.
+    fn render_mut_to_sink<S: Sink>(&mut self, sink: &mut S) -> Result<(), RenderError> {
+        self.render_mut_to(sink.buffer())?;
+        sink.checkpoint()
+    }
+
+    /// Render the template and write the result into `writer`.
+    ///
+    /// The output is written in chunks of about
+    /// [`DEFAULT_THRESHOLD`](runtime::DEFAULT_THRESHOLD) bytes, so the whole
+    /// output is never held in memory. Use `render_mut_to_sink` method with
+    /// [`WriterSink::with_threshold`](runtime::WriterSink::with_threshold) to
+    /// change the chunk size.
+    fn render_mut_to_writer<W: io::Write>(
+        &mut self,
+        writer: W,
+    ) -> Result<(), RenderError> {
+        let mut sink = WriterSink::new(writer);
+        self.render_mut_to_sink(&mut sink)?;
+        sink.finish().map(|_| ())
+    }
+
+    /// Render the template and return the rendering result, or
+    /// `RenderError::LimitExceeded` if the output exceeds `limits`.
+    ///
+    /// The output size is checked after each value or text is rendered, and the
+    /// rendering is aborted once it exceeds the limit. A value is rendered into
+    /// memory before the check, so the memory usage may exceed the limit by the
+    /// size of one rendered value (e.g. a large string field).
+    fn render_mut_with_limits(&mut self, limits: &Limits) -> runtime::RenderResult {
+        let mut sink = LimitedSink::new(limits);
+        let result = self.render_mut_to_sink(&mut sink);
+        sink.finish(result)
+    }
 }
 
 /// Template that can be rendered any number of times.
@@ -267,4 +436,99 @@ pub trait Template: TemplateMut {
     /// tpl.render_to(&mut buffer).unwrap();
     /// ```
     fn render_to(&self, buf: &mut Buffer) -> Result<(), RenderError>;
+
+    /// Render the template and write the result into `sink`.
+    ///
+    /// Sinks may consume the rendered contents while rendering. See
+    /// [`WriterSink`](runtime::WriterSink) for example.
+    ///
+    /// The default implementation renders the whole template into the buffer of
+    /// `sink`. Derived implementations render each part of the template directly.
+    fn render_to_sink<S: Sink>(&self, sink: &mut S) -> Result<(), RenderError> {
+        self.render_to(sink.buffer())?;
+        sink.checkpoint()
+    }
+
+    /// Render the template and write the result into `writer`.
+    ///
+    /// The output is written in chunks of about
+    /// [`DEFAULT_THRESHOLD`](runtime::DEFAULT_THRESHOLD) bytes, so the whole
+    /// output is never held in memory. Use `render_to_sink` method with
+    /// [`WriterSink::with_threshold`](runtime::WriterSink::with_threshold) to
+    /// change the chunk size.
+    fn render_to_writer<W: io::Write>(&self, writer: W) -> Result<(), RenderError> {
+        let mut sink = WriterSink::new(writer);
+        self.render_to_sink(&mut sink)?;
+        sink.finish().map(|_| ())
+    }
+
+    /// Render the template and return the rendering result, or
+    /// `RenderError::LimitExceeded` if the output exceeds `limits`.
+    ///
+    /// The output size is checked after each value or text is rendered, and the
+    /// rendering is aborted once it exceeds the limit. A value is rendered into
+    /// memory before the check, so the memory usage may exceed the limit by the
+    /// size of one rendered value (e.g. a large string field).
+    fn render_with_limits(&self, limits: &Limits) -> runtime::RenderResult {
+        let mut sink = LimitedSink::new(limits);
+        let result = self.render_to_sink(&mut sink);
+        sink.finish(result)
+    }
+}
+
+/// Wrapper which implements `Display` for templates
+///
+/// Rendering errors are reported as [`fmt::Error`].
+///
+/// ```
+/// use sailfish::{Displayed, Template, TemplateMut, TemplateOnce};
+/// use sailfish::runtime::Buffer;
+///
+/// # pub struct Hello;
+/// #
+/// # impl TemplateOnce for Hello {
+/// #     fn render_once(self) -> Result<String, sailfish::RenderError> {
+/// #         Ok(String::from("Hello"))
+/// #     }
+/// #
+/// #     fn render_once_to(self, buf: &mut Buffer)
+/// #             -> Result<(), sailfish::RenderError> {
+/// #         Ok(())
+/// #     }
+/// # }
+/// #
+/// # impl TemplateMut for Hello {
+/// #     fn render_mut(&mut self) -> Result<String, sailfish::RenderError> {
+/// #         Ok(String::from("Hello"))
+/// #     }
+/// #
+/// #     fn render_mut_to(&mut self, buf: &mut Buffer)
+/// #             -> Result<(), sailfish::RenderError> {
+/// #         Ok(())
+/// #     }
+/// # }
+/// #
+/// # impl Template for Hello {
+/// #     fn render(&self) -> Result<String, sailfish::RenderError> {
+/// #         Ok(String::from("Hello"))
+/// #     }
+/// #
+/// #     fn render_to(&self, buf: &mut Buffer)
+/// #             -> Result<(), sailfish::RenderError> {
+/// #         buf.push_str("Hello");
+/// #         Ok(())
+/// #     }
+/// # }
+/// #
+/// assert_eq!(format!("<{}>", Displayed(Hello)), "<Hello>");
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Displayed<T>(pub T);
+
+impl<T: Template> fmt::Display for Displayed<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut buf = Buffer::new();
+        self.0.render_to(&mut buf).map_err(|_| fmt::Error)?;
+        f.write_str(buf.as_str())
+    }
 }
@@ -1,6 +1,6 @@
 use std::alloc::{alloc, dealloc, handle_alloc_error, realloc, Layout};
 use std::fmt;
-use std::mem::{align_of, ManuallyDrop};
+use std::mem::ManuallyDrop;
 use std::ops::{Add, AddAssign};
 use std::ptr;
 use crate::runtime::utils::{likely, unlikely};
@@ -20,7 +20,7 @@ impl Buffer {
     #[inline]
     pub const fn new() -> Buffer {
         Self {
-            data: align_of::<u8>() as *mut u8, // dangling pointer
+            data: ptr::dangling_mut(),
             len: 0,
             capacity: 0,
         }
@@ -108,7 +108,7 @@ impl Buffer {
     /// overflows `isize::MAX`.
     #[inline]
     pub(crate) unsafe fn reserve_small(&mut self, size: usize) {
-        debug_assert!(size <= std::isize::MAX as usize);
+        debug_assert!(size <= isize::MAX as usize);
         if likely(self.len + size <= self.capacity) {
             return;
         }
@@ -164,7 +164,7 @@ impl Buffer {
 
     #[cfg_attr(feature = "perf-inline", inline)]
     fn reserve_internal(&mut self, size: usize) {
-        debug_assert!(size <= std::isize::MAX as usize);
+        debug_assert!(size <= isize::MAX as usize);
 
         let new_capacity = std::cmp::max(self.capacity * 2, self.capacity + size);
         debug_assert!(new_capacity > self.capacity);
@@ -180,7 +180,7 @@ impl Buffer {
 fn safe_alloc(capacity: usize) -> *mut u8 {
     assert!(capacity > 0);
     assert!(
-        capacity <= std::isize::MAX as usize,
+        capacity <= isize::MAX as usize,
         "capacity is too large"
     );
 
@@ -199,13 +199,13 @@ fn safe_alloc(capacity: usize) -> *mut u8 {
 /// # Safety
 ///
 /// - if `capacity > 0`, `capacity` is the same value that was used to allocate the block
-/// of memory pointed by `ptr`.
+///   of memory pointed by `ptr`.
 #[cold]
 #[inline(never)]
 unsafe fn safe_realloc(ptr: *mut u8, capacity: usize, new_capacity: usize) -> *mut u8 { unsafe {
     assert!(new_capacity > 0);
     assert!(
-        new_capacity <= std::isize::MAX as usize,
+        new_capacity <= isize::MAX as usize,
         "capacity is too large"
     );
 
@@ -411,6 +411,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::assign_op_pattern)]
     fn clone() {
         use std::fmt::Write;
 
@@ -482,7 +483,7 @@ mod tests {
     #[should_panic]
     fn reserve_overflow() {
         let mut buf = Buffer::new();
-        buf.reserve(std::isize::MAX as usize + 1);
+        buf.reserve(isize::MAX as usize + 1);
     }
 
     #[test]
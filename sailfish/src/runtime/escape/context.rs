@@ -0,0 +1,362 @@
+//! Escaping for each context inside HTML documents
+
+use std::fmt::Write;
+
+use super::super::buffer::Buffer;
+use super::super::render::{Render, RenderError};
+use super::escape_to_buf;
+
+/// URL which is rendered instead of the URL with unsafe scheme
+pub const INVALID_URL: &str = "about:invalid#sailfish";
+
+/// Context of HTML document where the value is rendered
+///
+/// Sailfish compiler determines the context of each `<%= %>` tag and escapes the
+/// value so that it cannot break out of the context.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Context {
+    /// HTML text or attribute value
+    Html,
+    /// JavaScript code outside of string literals inside `<script>` element
+    ///
+    /// Numbers and booleans are rendered as is, and other values are rendered as
+    /// string literals.
+    Script,
+    /// JavaScript code outside of string literals inside event handler attribute
+    /// (e.g. `onclick`)
+    ///
+    /// Same as [`Context::Script`], but the quotes of string literals are
+    /// written as `&quot;` so that they cannot close the attribute value.
+    ScriptAttr,
+    /// JavaScript string literal
+    ScriptString,
+    /// CSS inside `<style>` element or `style` attribute
+    Style,
+    /// Beginning of URL attribute (e.g. `href` or `src`). URLs with schemes
+    /// other than `http`, `https`, `mailto` and `tel` are replaced with
+    /// [`INVALID_URL`].
+    Url,
+    /// Part of URL attribute after the beginning (e.g. path segment or query
+    /// parameter)
+    UrlPart,
+}
+
+/// write the contents escaped for `ctx` into `Buffer`
+///
+/// # Examples
+///
+/// ```
+/// use sailfish::runtime::Buffer;
+/// use sailfish::runtime::escape::{escape_in_context, Context};
+///
+/// let mut buf = Buffer::new();
+/// escape_in_context("</script>", &mut buf, Context::ScriptString);
+/// assert_eq!(buf.as_str(), "\\u003C\\/script\\u003E");
+/// ```
+pub fn escape_in_context(feed: &str, buf: &mut Buffer, ctx: Context) {
+    match ctx {
+        Context::Html => escape_to_buf(feed, buf),
+        Context::Script => escape_script(feed, buf, "\""),
+        Context::ScriptAttr => escape_script(feed, buf, "&quot;"),
+        Context::ScriptString => escape_script_string(feed, buf),
+        Context::Style => escape_style(feed, buf),
+        Context::Url => escape_url(feed, buf),
+        Context::UrlPart => escape_url_part(feed, buf),
+    }
+}
+
+/// write the contents escaped for `ctx` into `Buffer`, inside the attribute value
+/// which is not quoted
+///
+/// In addition to the escaping for `ctx`, whitespaces and the characters which
+/// cannot appear in unquoted attribute values are written as character
+/// references, so that the value cannot end the attribute.
+///
+/// # Examples
+///
+/// ```
+/// use sailfish::runtime::Buffer;
+/// use sailfish::runtime::escape::{escape_in_unquoted_attr, Context};
+///
+/// let mut buf = Buffer::new();
+/// escape_in_unquoted_attr("a onmouseover=alert(1)", &mut buf, Context::ScriptString);
+/// assert_eq!(buf.as_str(), "a&#32;onmouseover&#61;alert(1)");
+/// ```
+pub fn escape_in_unquoted_attr(feed: &str, buf: &mut Buffer, ctx: Context) {
+    let mut tmp = Buffer::new();
+    escape_in_context(feed, &mut tmp, ctx);
+    escape_unquoted(tmp.as_str(), buf);
+}
+
+/// render the value with escaping for `ctx`
+#[doc(hidden)]
+pub fn render_in_context<T: Render + ?Sized>(
+    value: &T,
+    buf: &mut Buffer,
+    ctx: Context,
+) -> Result<(), RenderError> {
+    if ctx == Context::Html {
+        return value.render_escaped(buf);
+    }
+
+    let mut tmp = Buffer::new();
+    value.render(&mut tmp)?;
+    escape_in_context(tmp.as_str(), buf, ctx);
+    Ok(())
+}
+
+/// render the value with escaping for `ctx` inside unquoted attribute value
+#[doc(hidden)]
+pub fn render_in_unquoted_attr<T: Render + ?Sized>(
+    value: &T,
+    buf: &mut Buffer,
+    ctx: Context,
+) -> Result<(), RenderError> {
+    let mut tmp = Buffer::new();
+    value.render(&mut tmp)?;
+    escape_in_unquoted_attr(tmp.as_str(), buf, ctx);
+    Ok(())
+}
+
+// characters which end the attribute value or are not allowed without quotes
+fn escape_unquoted(feed: &str, buf: &mut Buffer) {
+    let mut start = 0;
+    for (i, b) in feed.bytes().enumerate() {
+        if !matches!(
+            b,
+            b' ' | b'\t'
+                | b'\n'
+                | b'\x0C'
+                | b'\r'
+                | b'"'
+                | b'\''
+                | b'`'
+                | b'<'
+                | b'='
+                | b'>'
+        ) {
+            continue;
+        }
+
+        buf.push_str(&feed[start..i]);
+        write!(buf, "&#{};", b).unwrap();
+        start = i + 1;
+    }
+    buf.push_str(&feed[start..]);
+}
+
+fn escape_script(feed: &str, buf: &mut Buffer, quote: &str) {
+    if is_js_literal(feed) {
+        buf.push_str(feed);
+    } else {
+        buf.push_str(quote);
+        escape_script_string(feed, buf);
+        buf.push_str(quote);
+    }
+}
+
+// number, boolean or null literal which can be rendered without quotes
+fn is_js_literal(s: &str) -> bool {
+    if matches!(s, "true" | "false" | "null") {
+        return true;
+    }
+
+    let digits = s.strip_prefix('-').unwrap_or(s);
+    let (mantissa, exponent) = match digits.find(['e', 'E']) {
+        Some(pos) => (&digits[..pos], Some(&digits[pos + 1..])),
+        None => (digits, None),
+    };
+    let (integer, fraction) = match mantissa.find('.') {
+        Some(pos) => (&mantissa[..pos], Some(&mantissa[pos + 1..])),
+        None => (mantissa, None),
+    };
+
+    let is_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    is_digits(integer)
+        && (integer == "0" || !integer.starts_with('0'))
+        && fraction.is_none_or(is_digits)
+        && exponent.is_none_or(|e| is_digits(e.strip_prefix(['+', '-']).unwrap_or(e)))
+}
+
+fn escape_script_string(feed: &str, buf: &mut Buffer) {
+    let mut start = 0;
+    for (i, c) in feed.char_indices() {
+        let escaped = match c {
+            '\\' => "\\\\",
+            '\n' => "\\n",
+            '\r' => "\\r",
+            '\t' => "\\t",
+            '/' => "\\/",
+            '"' | '\'' | '`' | '$' | '<' | '>' | '&' | '\u{2028}' | '\u{2029}' => "",
+            c if c.is_ascii_control() => "",
+            _ => continue,
+        };
+
+        buf.push_str(&feed[start..i]);
+        if escaped.is_empty() {
+            write!(buf, "\\u{:04X}", c as u32).unwrap();
+        } else {
+            buf.push_str(escaped);
+        }
+        start = i + c.len_utf8();
+    }
+    buf.push_str(&feed[start..]);
+}
+
+// characters which can terminate the declaration, the rule, the string literal
+// or the enclosing element and attribute
+fn escape_style(feed: &str, buf: &mut Buffer) {
+    let mut start = 0;
+    for (i, c) in feed.char_indices() {
+        if !matches!(c, ';' | '{' | '}' | '<' | '>' | '"' | '\'' | '&' | '\\')
+            && !c.is_ascii_control()
+        {
+            continue;
+        }
+
+        buf.push_str(&feed[start..i]);
+        // trailing space terminates the escape sequence
+        write!(buf, "\\{:X} ", c as u32).unwrap();
+        start = i + c.len_utf8();
+    }
+    buf.push_str(&feed[start..]);
+}
+
+fn escape_url(feed: &str, buf: &mut Buffer) {
+    if let Some(pos) = feed.find(':') {
+        let scheme = &feed[..pos];
+        let has_scheme = !scheme.contains(['/', '?', '#']);
+        if has_scheme
+            && !["http", "https", "mailto", "tel"]
+                .iter()
+                .any(|s| scheme.eq_ignore_ascii_case(s))
+        {
+            buf.push_str(INVALID_URL);
+            return;
+        }
+    }
+
+    for &b in feed.as_bytes() {
+        match b {
+            b'&' => buf.push_str("&amp;"),
+            b'\'' => buf.push_str("%27"),
+            b'!' | b'#' | b'$' | b'%' | b'('..=b';' | b'=' | b'?' | b'@' => {
+                buf.push(b as char)
+            }
+            b'A'..=b'Z' | b'a'..=b'z' | b'[' | b']' | b'_' | b'~' => buf.push(b as char),
+            _ => write!(buf, "%{:02X}", b).unwrap(),
+        }
+    }
+}
+
+fn escape_url_part(feed: &str, buf: &mut Buffer) {
+    for &b in feed.as_bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                buf.push(b as char)
+            }
+            _ => write!(buf, "%{:02X}", b).unwrap(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn escape(feed: &str, ctx: Context) -> String {
+        let mut buf = Buffer::new();
+        escape_in_context(feed, &mut buf, ctx);
+        buf.into_string()
+    }
+
+    #[test]
+    fn script() {
+        assert_eq!(escape("42", Context::Script), "42");
+        assert_eq!(escape("-1.5e+10", Context::Script), "-1.5e+10");
+        assert_eq!(escape("true", Context::Script), "true");
+        assert_eq!(escape("012", Context::Script), "\"012\"");
+        assert_eq!(escape("1.", Context::Script), "\"1.\"");
+        assert_eq!(escape("alert(1)", Context::Script), "\"alert(1)\"");
+        assert_eq!(escape("42", Context::ScriptAttr), "42");
+        assert_eq!(
+            escape("\" onmouseover=alert(1) x\"", Context::ScriptAttr),
+            "&quot;\\u0022 onmouseover=alert(1) x\\u0022&quot;"
+        );
+        assert_eq!(
+            escape("a'b\"c`${d}</script>\\\n\u{2028}", Context::ScriptString),
+            "a\\u0027b\\u0022c\\u0060\\u0024{d}\\u003C\\/script\\u003E\\\\\\n\\u2028"
+        );
+        assert_eq!(escape("\u{0}&", Context::ScriptString), "\\u0000\\u0026");
+    }
+
+    #[test]
+    fn style() {
+        assert_eq!(escape("red", Context::Style), "red");
+        assert_eq!(escape("#fff", Context::Style), "#fff");
+        assert_eq!(escape("1.5em", Context::Style), "1.5em");
+        assert_eq!(escape("0 -2px 10%", Context::Style), "0 -2px 10%");
+        assert_eq!(
+            escape("rgb(0, 128, 255)", Context::Style),
+            "rgb(0, 128, 255)"
+        );
+        assert_eq!(
+            escape("x;}</style>", Context::Style),
+            "x\\3B \\7D \\3C /style\\3E "
+        );
+        assert_eq!(escape("\"a\\b'\n", Context::Style), "\\22 a\\5C b\\27 \\A ");
+        assert_eq!(escape("日本", Context::Style), "日本");
+    }
+
+    #[test]
+    fn url() {
+        assert_eq!(
+            escape("https://example.com/a?b=c&d='e f'#g", Context::Url),
+            "https://example.com/a?b=c&amp;d=%27e%20f%27#g"
+        );
+        assert_eq!(escape("/path:with/colon", Context::Url), "/path:with/colon");
+        assert_eq!(escape("JavaScript:alert(1)", Context::Url), INVALID_URL);
+        assert_eq!(escape("data:text/html,x", Context::Url), INVALID_URL);
+        assert_eq!(escape("\"><x>", Context::Url), "%22%3E%3Cx%3E");
+        assert_eq!(
+            escape("a b&c/d?é", Context::UrlPart),
+            "a%20b%26c%2Fd%3F%C3%A9"
+        );
+    }
+
+    #[test]
+    fn unquoted_attr() {
+        let escape = |feed: &str, ctx: Context| {
+            let mut buf = Buffer::new();
+            escape_in_unquoted_attr(feed, &mut buf, ctx);
+            buf.into_string()
+        };
+
+        assert_eq!(
+            escape("a onmouseover=alert(1)//", Context::ScriptAttr),
+            "&quot;a&#32;onmouseover&#61;alert(1)\\/\\/&quot;"
+        );
+        assert_eq!(
+            escape("a onmouseover=alert(1)//", Context::ScriptString),
+            "a&#32;onmouseover&#61;alert(1)\\/\\/"
+        );
+        assert_eq!(escape("42", Context::ScriptAttr), "42");
+        assert_eq!(
+            escape("red x=1>", Context::Style),
+            "red&#32;x&#61;1\\3E&#32;"
+        );
+        assert_eq!(escape("a b>`", Context::Html), "a&#32;b&gt;&#96;");
+        assert_eq!(escape("/a b", Context::Url), "/a%20b");
+    }
+
+    #[test]
+    fn render() {
+        let mut buf = Buffer::new();
+        render_in_context("<a>", &mut buf, Context::Html).unwrap();
+        render_in_context(&1.5f64, &mut buf, Context::Script).unwrap();
+        render_in_context("x", &mut buf, Context::Script).unwrap();
+        render_in_context("x", &mut buf, Context::ScriptAttr).unwrap();
+        assert_eq!(buf.as_str(), "&lt;a&gt;1.5\"x\"&quot;x&quot;");
+    }
+}
@@ -0,0 +1,186 @@
+//! Escaping strategies selectable with `escape` option
+
+use std::fmt::Write;
+
+use super::super::buffer::Buffer;
+use super::super::render::{Render, RenderError};
+use super::escape_to_buf;
+
+/// Escaping strategy for the values rendered with `<%= %>` tag
+///
+/// Sailfish compiler dispatches to the escaper selected by `escape` option of
+/// the template. You can implement this trait for your own type to support other
+/// document formats (e.g. LaTeX), and specify the path to the type in `escape`
+/// option.
+///
+/// # Examples
+///
+/// ```
+/// use sailfish::runtime::Buffer;
+/// use sailfish::runtime::escape::Escaper;
+///
+/// struct Latex;
+///
+/// impl Escaper for Latex {
+///     fn escape(feed: &str, buf: &mut Buffer) {
+///         for c in feed.chars() {
+///             if "#$%&_{}".contains(c) {
+///                 buf.push('\\');
+///             }
+///             buf.push(c);
+///         }
+///     }
+/// }
+///
+/// let mut buf = Buffer::new();
+/// Latex::render_escaped("100%", &mut buf).unwrap();
+/// assert_eq!(buf.as_str(), "100\\%");
+/// ```
+pub trait Escaper {
+    /// `Content-Type` of the documents rendered with this escaper
+    ///
+    /// This value is used by the web framework integrations (e.g. `axum`
+    /// feature).
+    const CONTENT_TYPE: &'static str = "text/plain; charset=utf-8";
+
+    /// write the escaped contents into `Buffer`
+    fn escape(feed: &str, buf: &mut Buffer);
+
+    /// render the value with escaping
+    #[inline]
+    fn render_escaped<T: Render + ?Sized>(
+        value: &T,
+        buf: &mut Buffer,
+    ) -> Result<(), RenderError> {
+        let mut tmp = Buffer::new();
+        value.render(&mut tmp)?;
+        Self::escape(tmp.as_str(), buf);
+        Ok(())
+    }
+}
+
+/// HTML escaping (`escape = "html"`, default)
+#[derive(Clone, Copy, Debug)]
+pub struct Html;
+
+impl Escaper for Html {
+    const CONTENT_TYPE: &'static str = "text/html; charset=utf-8";
+
+    #[inline]
+    fn escape(feed: &str, buf: &mut Buffer) {
+        escape_to_buf(feed, buf);
+    }
+
+    #[inline]
+    fn render_escaped<T: Render + ?Sized>(
+        value: &T,
+        buf: &mut Buffer,
+    ) -> Result<(), RenderError> {
+        value.render_escaped(buf)
+    }
+}
+
+/// XML escaping (`escape = "xml"`)
+///
+/// Replaces the characters `&"'<>` with the predefined entities, and the
+/// characters which are not allowed in XML 1.0 documents with U+FFFD.
+#[derive(Clone, Copy, Debug)]
+pub struct Xml;
+
+impl Escaper for Xml {
+    const CONTENT_TYPE: &'static str = "application/xml; charset=utf-8";
+
+    fn escape(feed: &str, buf: &mut Buffer) {
+        let mut start = 0;
+        for (i, c) in feed.char_indices() {
+            let escaped = match c {
+                '&' => "&amp;",
+                '<' => "&lt;",
+                '>' => "&gt;",
+                '"' => "&quot;",
+                '\'' => "&apos;",
+                '\t' | '\n' | '\r' => continue,
+                '\u{0}'..='\u{1F}' | '\u{FFFE}' | '\u{FFFF}' => "\u{FFFD}",
+                _ => continue,
+            };
+
+            buf.push_str(&feed[start..i]);
+            buf.push_str(escaped);
+            start = i + c.len_utf8();
+        }
+        buf.push_str(&feed[start..]);
+    }
+}
+
+/// Escaping for the contents of JSON string literals (`escape = "json"`)
+#[derive(Clone, Copy, Debug)]
+pub struct Json;
+
+impl Escaper for Json {
+    const CONTENT_TYPE: &'static str = "application/json";
+
+    fn escape(feed: &str, buf: &mut Buffer) {
+        let mut start = 0;
+        for (i, c) in feed.char_indices() {
+            let escaped = match c {
+                '"' => "\\\"",
+                '\\' => "\\\\",
+                '\n' => "\\n",
+                '\r' => "\\r",
+                '\t' => "\\t",
+                '\u{8}' => "\\b",
+                '\u{C}' => "\\f",
+                c if c.is_ascii_control() => "",
+                _ => continue,
+            };
+
+            buf.push_str(&feed[start..i]);
+            if escaped.is_empty() {
+                write!(buf, "\\u{:04x}", c as u32).unwrap();
+            } else {
+                buf.push_str(escaped);
+            }
+            start = i + c.len_utf8();
+        }
+        buf.push_str(&feed[start..]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn escape<E: Escaper>(feed: &str) -> String {
+        let mut buf = Buffer::new();
+        E::escape(feed, &mut buf);
+        buf.into_string()
+    }
+
+    #[test]
+    fn xml() {
+        assert_eq!(escape::<Xml>("plain text"), "plain text");
+        assert_eq!(
+            escape::<Xml>("<a href=\"x\">'&'</a>"),
+            "&lt;a href=&quot;x&quot;&gt;&apos;&amp;&apos;&lt;/a&gt;"
+        );
+        assert_eq!(escape::<Xml>("a\u{0}\tb\u{FFFF}"), "a\u{FFFD}\tb\u{FFFD}");
+    }
+
+    #[test]
+    fn json() {
+        assert_eq!(escape::<Json>("<p>日本</p>"), "<p>日本</p>");
+        assert_eq!(
+            escape::<Json>("\"a\\b\"\n\r\t\u{8}\u{C}\u{1}\u{7F}"),
+            "\\\"a\\\\b\\\"\\n\\r\\t\\b\\f\\u0001\\u007f"
+        );
+    }
+
+    #[test]
+    fn render() {
+        let mut buf = Buffer::new();
+        Html::render_escaped("<'>", &mut buf).unwrap();
+        Xml::render_escaped("<'>", &mut buf).unwrap();
+        Json::render_escaped(&1.5f64, &mut buf).unwrap();
+        assert_eq!(buf.as_str(), "&lt;&#039;&gt;&lt;&apos;&gt;1.5");
+    }
+}
@@ -1,20 +1,35 @@
 //! HTML escaping utilities
 //!
 //! By default sailfish replaces the characters `&"'<>` with the equivalent html.
+//! Values rendered inside scripts, styles and URL attributes are escaped for
+//! each [`Context`] instead.
+//!
+//! Templates for other formats can select another [`Escaper`] with `escape`
+//! option.
 
 #![cfg_attr(
-    all(
-        any(target_arch = "x86", target_arch = "x86_64"),
-        not(miri),
-        target_feature = "avx2"
+    any(
+        all(
+            any(target_arch = "x86", target_arch = "x86_64"),
+            not(miri),
+            target_feature = "avx2"
+        ),
+        all(target_arch = "aarch64", not(miri), target_feature = "neon"),
+        all(target_arch = "wasm32", not(miri), target_feature = "simd128")
     ),
     allow(dead_code)
 )]
 
 #[cfg(all(any(target_arch = "x86", target_arch = "x86_64"), not(miri)))]
 mod avx2;
+mod context;
+mod escaper;
 mod fallback;
 mod naive;
+#[cfg(all(target_arch = "aarch64", not(miri), target_feature = "neon"))]
+mod neon;
+#[cfg(all(target_arch = "wasm32", not(miri), target_feature = "simd128"))]
+mod simd128;
 #[cfg(all(any(target_arch = "x86", target_arch = "x86_64"), not(miri)))]
 mod sse2;
 
@@ -36,6 +51,11 @@ const ESCAPED_LEN: usize = 5;
 
 use super::buffer::Buffer;
 
+pub use context::{Context, INVALID_URL, escape_in_context, escape_in_unquoted_attr};
+#[doc(hidden)]
+pub use context::{render_in_context, render_in_unquoted_attr};
+pub use escaper::{Escaper, Html, Json, Xml};
+
 /// write the escaped contents into `Buffer`
 #[cfg(all(any(target_arch = "x86", target_arch = "x86_64"), not(miri)))]
 #[cfg_attr(feature = "perf-inline", inline)]
@@ -98,7 +118,15 @@ pub fn escape_to_buf(feed: &str, buf: &mut Buffer) {
             let l = naive::escape_small(feed, buf.as_mut_ptr().add(buf.len()));
             buf.advance(l);
         } else {
-            fallback::escape(feed, buf)
+            #[cfg(all(target_arch = "aarch64", not(miri), target_feature = "neon"))]
+            neon::escape(feed, buf);
+            #[cfg(all(target_arch = "wasm32", not(miri), target_feature = "simd128"))]
+            simd128::escape(feed, buf);
+            #[cfg(not(any(
+                all(target_arch = "aarch64", not(miri), target_feature = "neon"),
+                all(target_arch = "wasm32", not(miri), target_feature = "simd128")
+            )))]
+            fallback::escape(feed, buf);
         }
     }
 }
@@ -176,7 +204,7 @@ mod tests {
     #[test]
     #[cfg(not(miri))]
     fn random() {
-        const ASCII_CHARS: &'static [u8] = br##"abcdefghijklmnopqrstuvwxyz0123456789-^\@[;:],./\!"#$%&'()~=~|`{+*}<>?_"##;
+        const ASCII_CHARS: &[u8] = br##"abcdefghijklmnopqrstuvwxyz0123456789-^\@[;:],./\!"#$%&'()~=~|`{+*}<>?_"##;
         let mut state = 88172645463325252u64;
         let mut data = Vec::with_capacity(100);
 
@@ -196,7 +224,7 @@ mod tests {
                     data.push(ASCII_CHARS[idx]);
                 }
 
-                let s = unsafe { std::str::from_utf8_unchecked(&*data) };
+                let s = unsafe { std::str::from_utf8_unchecked(&data) };
 
                 unsafe {
                     naive::escape(
@@ -224,6 +252,20 @@ mod tests {
                             buf.clear();
                         }
                     }
+
+                    #[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+                    {
+                        neon::escape(s, &mut buf);
+                        assert_eq!(buf.as_str(), buf_naive.as_str());
+                        buf.clear();
+                    }
+
+                    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+                    {
+                        simd128::escape(s, &mut buf);
+                        assert_eq!(buf.as_str(), buf_naive.as_str());
+                        buf.clear();
+                    }
                 }
 
                 buf_naive.clear();
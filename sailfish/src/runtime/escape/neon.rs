@@ -0,0 +1,90 @@
+use std::arch::aarch64::*;
+use std::slice;
+
+use super::super::Buffer;
+use super::naive::push_escaped_str;
+use super::{ESCAPE_LUT, ESCAPED, ESCAPED_LEN};
+
+const VECTOR_BYTES: usize = std::mem::size_of::<uint8x16_t>();
+
+#[target_feature(enable = "neon")]
+pub unsafe fn escape(feed: &str, buffer: &mut Buffer) {
+    unsafe {
+        let len = feed.len();
+        let mut start_ptr = feed.as_ptr();
+        let mut ptr = start_ptr;
+        let end_ptr = feed[len..].as_ptr();
+
+        let v_independent1 = vdupq_n_u8(5);
+        let v_independent2 = vdupq_n_u8(2);
+        let v_key1 = vdupq_n_u8(0x27);
+        let v_key2 = vdupq_n_u8(0x3e);
+
+        // NEON has no movemask instruction, so each byte is narrowed into 4 bits of
+        // the mask instead
+        let maskgen = |x: uint8x16_t| -> u64 {
+            let cmp = vorrq_u8(
+                vceqq_u8(vorrq_u8(x, v_independent1), v_key1),
+                vceqq_u8(vorrq_u8(x, v_independent2), v_key2),
+            );
+            let nibbles = vshrn_n_u16::<4>(vreinterpretq_u16_u8(cmp));
+            vget_lane_u64::<0>(vreinterpret_u64_u8(nibbles))
+        };
+
+        while ptr <= end_ptr.sub(VECTOR_BYTES) {
+            let mut mask = maskgen(vld1q_u8(ptr));
+            while mask != 0 {
+                let trailing_zeros = mask.trailing_zeros() as usize;
+                mask ^= 0xf << trailing_zeros;
+                let ptr2 = ptr.add(trailing_zeros / 4);
+                let c = ESCAPE_LUT[*ptr2 as usize] as usize;
+                if c < ESCAPED_LEN {
+                    if start_ptr < ptr2 {
+                        let slc = slice::from_raw_parts(
+                            start_ptr,
+                            ptr2 as usize - start_ptr as usize,
+                        );
+                        buffer.push_str(std::str::from_utf8_unchecked(slc));
+                    }
+                    push_escaped_str(ESCAPED.get_unchecked(c), buffer);
+                    start_ptr = ptr2.add(1);
+                }
+            }
+
+            ptr = ptr.add(VECTOR_BYTES);
+        }
+
+        debug_assert!(ptr.add(VECTOR_BYTES) > end_ptr);
+
+        if ptr < end_ptr {
+            debug_assert!((end_ptr as usize - ptr as usize) < VECTOR_BYTES);
+            let backs = VECTOR_BYTES - (end_ptr as usize - ptr as usize);
+            let read_ptr = ptr.sub(backs);
+
+            let mut mask = maskgen(vld1q_u8(read_ptr)) >> (backs * 4);
+            while mask != 0 {
+                let trailing_zeros = mask.trailing_zeros() as usize;
+                mask ^= 0xf << trailing_zeros;
+                let ptr2 = ptr.add(trailing_zeros / 4);
+                let c = ESCAPE_LUT[*ptr2 as usize] as usize;
+                if c < ESCAPED_LEN {
+                    if start_ptr < ptr2 {
+                        let slc = slice::from_raw_parts(
+                            start_ptr,
+                            ptr2 as usize - start_ptr as usize,
+                        );
+                        buffer.push_str(std::str::from_utf8_unchecked(slc));
+                    }
+                    push_escaped_str(ESCAPED.get_unchecked(c), buffer);
+                    start_ptr = ptr2.add(1);
+                }
+            }
+        }
+
+        if end_ptr > start_ptr {
+            let slc =
+                slice::from_raw_parts(start_ptr, end_ptr as usize - start_ptr as usize);
+            buffer.push_str(std::str::from_utf8_unchecked(slc));
+        }
+    }
+}
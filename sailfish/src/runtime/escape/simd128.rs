@@ -0,0 +1,86 @@
+use std::arch::wasm32::*;
+use std::slice;
+
+use super::super::Buffer;
+use super::naive::push_escaped_str;
+use super::{ESCAPE_LUT, ESCAPED, ESCAPED_LEN};
+
+const VECTOR_BYTES: usize = std::mem::size_of::<v128>();
+
+#[target_feature(enable = "simd128")]
+pub unsafe fn escape(feed: &str, buffer: &mut Buffer) {
+    unsafe {
+        let len = feed.len();
+        let mut start_ptr = feed.as_ptr();
+        let mut ptr = start_ptr;
+        let end_ptr = feed[len..].as_ptr();
+
+        let v_independent1 = u8x16_splat(5);
+        let v_independent2 = u8x16_splat(2);
+        let v_key1 = u8x16_splat(0x27);
+        let v_key2 = u8x16_splat(0x3e);
+
+        let maskgen = |x: v128| -> u32 {
+            u8x16_bitmask(v128_or(
+                u8x16_eq(v128_or(x, v_independent1), v_key1),
+                u8x16_eq(v128_or(x, v_independent2), v_key2),
+            )) as u32
+        };
+
+        while ptr <= end_ptr.sub(VECTOR_BYTES) {
+            let mut mask = maskgen(v128_load(ptr as *const v128));
+            while mask != 0 {
+                let trailing_zeros = mask.trailing_zeros() as usize;
+                mask ^= 1 << trailing_zeros;
+                let ptr2 = ptr.add(trailing_zeros);
+                let c = ESCAPE_LUT[*ptr2 as usize] as usize;
+                if c < ESCAPED_LEN {
+                    if start_ptr < ptr2 {
+                        let slc = slice::from_raw_parts(
+                            start_ptr,
+                            ptr2 as usize - start_ptr as usize,
+                        );
+                        buffer.push_str(std::str::from_utf8_unchecked(slc));
+                    }
+                    push_escaped_str(ESCAPED.get_unchecked(c), buffer);
+                    start_ptr = ptr2.add(1);
+                }
+            }
+
+            ptr = ptr.add(VECTOR_BYTES);
+        }
+
+        debug_assert!(ptr.add(VECTOR_BYTES) > end_ptr);
+
+        if ptr < end_ptr {
+            debug_assert!((end_ptr as usize - ptr as usize) < VECTOR_BYTES);
+            let backs = VECTOR_BYTES - (end_ptr as usize - ptr as usize);
+            let read_ptr = ptr.sub(backs);
+
+            let mut mask = maskgen(v128_load(read_ptr as *const v128)) >> backs;
+            while mask != 0 {
+                let trailing_zeros = mask.trailing_zeros() as usize;
+                mask ^= 1 << trailing_zeros;
+                let ptr2 = ptr.add(trailing_zeros);
+                let c = ESCAPE_LUT[*ptr2 as usize] as usize;
+                if c < ESCAPED_LEN {
+                    if start_ptr < ptr2 {
+                        let slc = slice::from_raw_parts(
+                            start_ptr,
+                            ptr2 as usize - start_ptr as usize,
+                        );
+                        buffer.push_str(std::str::from_utf8_unchecked(slc));
+                    }
+                    push_escaped_str(ESCAPED.get_unchecked(c), buffer);
+                    start_ptr = ptr2.add(1);
+                }
+            }
+        }
+
+        if end_ptr > start_ptr {
+            let slc =
+                slice::from_raw_parts(start_ptr, end_ptr as usize - start_ptr as usize);
+            buffer.push_str(std::str::from_utf8_unchecked(slc));
+        }
+    }
+}
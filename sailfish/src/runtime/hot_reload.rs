@@ -0,0 +1,123 @@
+//! Render templates from their source files in debug builds
+//!
+//! When `hot-reload` feature is enabled, templates derived with `path` option
+//! are rendered by an interpreter which reads the template file every time,
+//! so the modifications are reflected without recompiling the binary.
+//!
+//! The interpreter only supports simple templates, which consist of texts,
+//! comments, fields, literals and `include!` macro. If the template contains
+//! other Rust code (e.g. `for` loop or filters), or any of the rendered fields
+//! does not implement `Render` trait, the compiled template is used instead.
+//!
+//! Release builds always use the compiled templates.
+
+use sailfish_compiler::{Instruction, Interpreter};
+use std::path::Path;
+
+use super::{Buffer, Render, RenderError};
+
+/// Compiler options of the template
+#[doc(hidden)]
+pub struct Options {
+    pub path: &'static str,
+    pub delimiter: char,
+    pub escape: bool,
+    pub rm_whitespace: bool,
+    pub rm_newline: bool,
+    pub local_fields: bool,
+}
+
+/// Render the field with given name, or returns `None` if the field cannot be
+/// rendered by the interpreter.
+#[doc(hidden)]
+pub type Lookup<'a> =
+    dyn Fn(&str, &mut Buffer, bool) -> Option<Result<(), RenderError>> + 'a;
+
+/// Render the template from its source file.
+///
+/// Returns `None` if the template cannot be interpreted.
+#[doc(hidden)]
+pub fn render(
+    options: &Options,
+    buf: &mut Buffer,
+    lookup: &Lookup<'_>,
+) -> Option<Result<(), RenderError>> {
+    let path = Path::new(options.path);
+    if !path.is_file() {
+        // template source is not available (e.g. running on another machine)
+        return None;
+    }
+
+    let interpreter = Interpreter::new()
+        .delimiter(options.delimiter)
+        .escape(options.escape)
+        .rm_whitespace(options.rm_whitespace)
+        .rm_newline(options.rm_newline)
+        .local_fields(options.local_fields);
+
+    let program = match interpreter.load(path) {
+        Ok(Some(program)) => program,
+        Ok(None) => return None,
+        Err(e) => return Some(Err(RenderError::Msg(e.to_string()))),
+    };
+
+    // Write into temporary buffer since the interpreter may give up rendering
+    let mut tmp = Buffer::new();
+    for instruction in &program {
+        let result = match instruction {
+            Instruction::Text(text) => {
+                tmp.push_str(text);
+                Ok(())
+            }
+            Instruction::Escaped(text) => text.as_str().render_escaped(&mut tmp),
+            Instruction::Field { name, escape } => lookup(name, &mut tmp, *escape)?,
+        };
+
+        if let Err(e) = result {
+            return Some(Err(e));
+        }
+    }
+
+    buf.push_str(tmp.as_str());
+    Some(Ok(()))
+}
+
+/// Wrapper used to select `RenderField` or `RenderFallback` implementation
+#[doc(hidden)]
+pub struct Field<'a, T: ?Sized>(pub &'a T);
+
+/// Render the field which implements `Render` trait
+#[doc(hidden)]
+pub trait RenderField {
+    fn hot_render(
+        &self,
+        buf: &mut Buffer,
+        escape: bool,
+    ) -> Option<Result<(), RenderError>>;
+}
+
+impl<T: Render + ?Sized> RenderField for Field<'_, T> {
+    #[inline]
+    fn hot_render(
+        &self,
+        buf: &mut Buffer,
+        escape: bool,
+    ) -> Option<Result<(), RenderError>> {
+        if escape {
+            Some(self.0.render_escaped(buf))
+        } else {
+            Some(self.0.render(buf))
+        }
+    }
+}
+
+/// Fallback for the field which does not implement `Render` trait
+#[doc(hidden)]
+pub trait RenderFallback {
+    #[inline]
+    fn hot_render(&self, _: &mut Buffer, _: bool) -> Option<Result<(), RenderError>> {
+        None
+    }
+}
+
+impl<T: ?Sized> RenderFallback for &Field<'_, T> {}
@@ -0,0 +1,106 @@
+//! Limits on the rendering output
+
+use super::buffer::Buffer;
+use super::render::{RenderError, RenderResult};
+use super::sink::Sink;
+
+/// Limits applied while rendering templates
+///
+/// ```
+/// use sailfish::Limits;
+///
+/// let limits = Limits {
+///     max_bytes: Some(1024 * 1024),
+///     ..Default::default()
+/// };
+/// # drop(limits);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Limits {
+    /// Maximum size of the output in bytes (default: unlimited)
+    ///
+    /// The size is checked after each value or text is rendered, so the output
+    /// may exceed the limit by the size of one rendered value before the
+    /// rendering is aborted.
+    pub max_bytes: Option<usize>,
+}
+
+/// Sink which aborts rendering once the output exceeds the limits
+pub(crate) struct LimitedSink {
+    buf: Buffer,
+    max_bytes: usize,
+}
+
+impl LimitedSink {
+    pub(crate) fn new(limits: &Limits) -> Self {
+        Self {
+            buf: Buffer::new(),
+            max_bytes: limits.max_bytes.unwrap_or(usize::MAX),
+        }
+    }
+
+    /// Returns the output, or `RenderError::LimitExceeded` if the output
+    /// exceeded the limits
+    pub(crate) fn finish(self, result: Result<(), RenderError>) -> RenderResult {
+        // the error returned from `checkpoint` may be wrapped with the locations
+        // in the templates
+        self.check()?;
+        result.map(|_| self.buf.into_string())
+    }
+
+    #[inline]
+    fn check(&self) -> Result<(), RenderError> {
+        if self.buf.len() > self.max_bytes {
+            Err(RenderError::LimitExceeded)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+unsafe impl Sink for LimitedSink {
+    #[inline]
+    fn buffer(&mut self) -> &mut Buffer {
+        &mut self.buf
+    }
+
+    #[inline]
+    fn checkpoint(&mut self) -> Result<(), RenderError> {
+        self.check()
+    }
+
+    #[inline]
+    fn break_point(&mut self) -> Result<(), RenderError> {
+        self.check()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(limits: &Limits, n: usize) -> RenderResult {
+        let mut sink = LimitedSink::new(limits);
+        let result = (|| {
+            for _ in 0..n {
+                sink.buffer().push_str("<li>item</li>");
+                sink.checkpoint()?;
+            }
+            Ok(())
+        })();
+        sink.finish(result)
+    }
+
+    #[test]
+    fn max_bytes() {
+        let limits = Limits {
+            max_bytes: Some(40),
+        };
+        assert_eq!(render(&limits, 3).unwrap().len(), 39);
+        assert!(matches!(
+            render(&limits, 4),
+            Err(RenderError::LimitExceeded)
+        ));
+        assert_eq!(render(&Limits::default(), 100).unwrap().len(), 1300);
+    }
+}
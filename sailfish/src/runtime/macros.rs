@@ -1,24 +1,91 @@
 #[macro_export]
 #[doc(hidden)]
 macro_rules! render {
-    ($buf:ident, $value:expr) => {
-        $crate::runtime::Render::render(&($value), $buf)?
-    };
+    ($buf:ident, $value:expr $(, $path:literal, $line:literal)?) => {{
+        $crate::located!(
+            $crate::runtime::Render::render(&($value), $crate::runtime::Sink::buffer($buf))
+            $(, $path, $line)?
+        );
+        $crate::located!($crate::runtime::Sink::checkpoint($buf) $(, $path, $line)?)
+    }};
 }
 
 #[macro_export]
 #[doc(hidden)]
 macro_rules! render_escaped {
-    ($buf:ident, $value:expr) => {
-        $crate::runtime::Render::render_escaped(&($value), $buf)?
-    };
+    ($buf:ident, $value:expr $(, $path:literal, $line:literal)?) => {{
+        $crate::located!(
+            $crate::runtime::Render::render_escaped(
+                &($value),
+                $crate::runtime::Sink::buffer($buf),
+            )
+            $(, $path, $line)?
+        );
+        $crate::located!($crate::runtime::Sink::checkpoint($buf) $(, $path, $line)?)
+    }};
+}
+
+#[macro_export]
+#[doc(hidden)]
+macro_rules! render_escaped_in {
+    ($buf:ident, $value:expr, $ctx:ident $(, $path:literal, $line:literal)?) => {{
+        $crate::located!(
+            $crate::runtime::escape::render_in_context(
+                &($value),
+                $crate::runtime::Sink::buffer($buf),
+                $crate::runtime::escape::Context::$ctx,
+            )
+            $(, $path, $line)?
+        );
+        $crate::located!($crate::runtime::Sink::checkpoint($buf) $(, $path, $line)?)
+    }};
+}
+
+#[macro_export]
+#[doc(hidden)]
+macro_rules! render_escaped_unquoted {
+    ($buf:ident, $value:expr, $ctx:ident $(, $path:literal, $line:literal)?) => {{
+        $crate::located!(
+            $crate::runtime::escape::render_in_unquoted_attr(
+                &($value),
+                $crate::runtime::Sink::buffer($buf),
+                $crate::runtime::escape::Context::$ctx,
+            )
+            $(, $path, $line)?
+        );
+        $crate::located!($crate::runtime::Sink::checkpoint($buf) $(, $path, $line)?)
+    }};
+}
+
+#[macro_export]
+#[doc(hidden)]
+macro_rules! render_escaped_with {
+    ($buf:ident, $value:expr, $escaper:path $(, $path:literal, $line:literal)?) => {{
+        $crate::located!(
+            <$escaper as $crate::runtime::escape::Escaper>::render_escaped(
+                &($value),
+                $crate::runtime::Sink::buffer($buf),
+            )
+            $(, $path, $line)?
+        );
+        $crate::located!($crate::runtime::Sink::checkpoint($buf) $(, $path, $line)?)
+    }};
 }
 
 #[macro_export]
 #[doc(hidden)]
 macro_rules! render_text {
-    ($buf:ident, $value:expr) => {
-        $buf.push_str($value)
+    ($buf:ident, $value:expr) => {{
+        $crate::runtime::Sink::buffer($buf).push_str($value);
+        $crate::runtime::text_checkpoint($buf)?
+    }};
+}
+
+#[macro_export]
+#[doc(hidden)]
+macro_rules! flush {
+    ($buf:ident) => {
+        $crate::runtime::Sink::break_point($buf)?
     };
 }
 
@@ -27,3 +94,24 @@ macro_rules! render_text {
 macro_rules! render_noop {
     ($buf:ident, $value:expr) => {};
 }
+
+#[macro_export]
+#[doc(hidden)]
+macro_rules! located {
+    ($result:expr) => {
+        $result?
+    };
+    ($result:expr, $path:literal, $line:literal) => {
+        match $result {
+            Ok(value) => value,
+            Err(e) => {
+                return Err($crate::runtime::RenderError::from(e).with_location(
+                    $crate::runtime::Location {
+                        path: $path,
+                        line: $line,
+                    },
+                ));
+            }
+        }
+    };
+}
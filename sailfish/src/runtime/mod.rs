@@ -6,13 +6,26 @@ mod utils;
 mod buffer;
 pub mod escape;
 pub mod filter;
+#[cfg(feature = "hot-reload")]
+#[cfg_attr(docsrs, doc(cfg(feature = "hot-reload")))]
+pub mod hot_reload;
+mod limits;
 mod macros;
 mod render;
+mod sink;
 mod size_hint;
 
 pub use buffer::Buffer;
-pub use render::{Render, RenderError, RenderResult};
+pub(crate) use limits::LimitedSink;
+pub use limits::Limits;
+pub use render::{LocatedError, Location, Render, RenderError, RenderResult};
+#[doc(hidden)]
+pub use sink::text_checkpoint;
+pub use sink::{DEFAULT_THRESHOLD, Sink, WriterSink};
 pub use size_hint::SizeHint;
 
 #[doc(hidden)]
-pub use crate::{render, render_escaped, render_noop, render_text};
+pub use crate::{
+    flush, located, render, render_escaped, render_escaped_in, render_escaped_unquoted,
+    render_escaped_with, render_noop, render_text,
+};
@@ -1,6 +1,7 @@
 use std::borrow::Cow;
 use std::cell::{Ref, RefMut};
 use std::fmt;
+use std::io;
 use std::num::{
     NonZeroI128, NonZeroI16, NonZeroI32, NonZeroI64, NonZeroI8, NonZeroIsize,
     NonZeroU128, NonZeroU16, NonZeroU32, NonZeroU64, NonZeroU8, NonZeroUsize, Wrapping,
@@ -367,11 +368,14 @@ impl<T: Render> Render for Wrapping<T> {
 
 /// The error type which is returned from template function
 #[derive(Clone, Debug)]
+#[non_exhaustive]
 pub enum RenderError {
     /// Custom error message
     Msg(String),
     /// fmt::Error was raised during rendering
     Fmt(fmt::Error),
+    /// I/O error was raised while writing the output
+    Io(Arc<io::Error>),
     /// Buffer size shrinked during rendering
     ///
     /// This method won't be raised unless you implement `Render` trait for custom type.
@@ -379,6 +383,12 @@ pub enum RenderError {
     /// Also there is no guarentee that this error will be returned whenever the buffer
     /// size shrinked.
     BufSize,
+    /// The output exceeded the limits passed to `render_*_with_limits` methods
+    LimitExceeded,
+    /// Error raised inside a template, with the locations in the template files
+    ///
+    /// Use [`RenderError::inner`] method to get the original error.
+    Located(Box<LocatedError>),
 }
 
 impl RenderError {
@@ -386,6 +396,62 @@ impl RenderError {
     pub fn new(msg: &str) -> Self {
         RenderError::Msg(msg.to_owned())
     }
+
+    /// Returns the error without the template locations
+    pub fn inner(&self) -> &RenderError {
+        match self {
+            RenderError::Located(e) => &e.error,
+            e => e,
+        }
+    }
+
+    /// Returns the location in the template where this error was raised
+    pub fn location(&self) -> Option<Location> {
+        self.trace().first().copied()
+    }
+
+    /// Returns the locations where this error was propagated
+    ///
+    /// The first entry is the location where the error was raised, and the
+    /// following entries are the `<%+ %>` tags and macro calls which rendered the
+    /// failed template, from the innermost to the outermost.
+    ///
+    /// ```
+    /// use sailfish::runtime::{Location, RenderError};
+    ///
+    /// let err = RenderError::new("invalid id")
+    ///     .with_location(Location { path: "item.stpl", line: 3 })
+    ///     .with_location(Location { path: "page.stpl", line: 10 });
+    /// assert_eq!(err.trace().len(), 2);
+    /// assert_eq!(err.location().unwrap().path, "item.stpl");
+    /// assert_eq!(
+    ///     err.to_string(),
+    ///     "invalid id at item.stpl:3, called from page.stpl:10"
+    /// );
+    /// ```
+    pub fn trace(&self) -> &[Location] {
+        match self {
+            RenderError::Located(e) => &e.trace,
+            _ => &[],
+        }
+    }
+
+    /// Append the location where this error was propagated
+    ///
+    /// Templates call this method for the errors raised inside them, so you don't
+    /// usually need to call it yourself.
+    pub fn with_location(self, location: Location) -> Self {
+        match self {
+            RenderError::Located(mut e) => {
+                e.trace.push(location);
+                RenderError::Located(e)
+            }
+            error => RenderError::Located(Box::new(LocatedError {
+                error,
+                trace: vec![location],
+            })),
+        }
+    }
 }
 
 impl fmt::Display for RenderError {
@@ -393,7 +459,17 @@ impl fmt::Display for RenderError {
         match self {
             RenderError::Msg(s) => f.pad(s),
             RenderError::Fmt(e) => fmt::Display::fmt(e, f),
+            RenderError::Io(e) => fmt::Display::fmt(e, f),
             RenderError::BufSize => f.pad("buffer size shrinked while rendering"),
+            RenderError::LimitExceeded => f.pad("output size exceeded the limit"),
+            RenderError::Located(e) => {
+                fmt::Display::fmt(&e.error, f)?;
+                for (i, location) in e.trace.iter().enumerate() {
+                    let sep = if i == 0 { " at" } else { ", called from" };
+                    write!(f, "{} {}", sep, location)?;
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -401,12 +477,53 @@ impl fmt::Display for RenderError {
 impl std::error::Error for RenderError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
-            RenderError::Msg(_) | RenderError::BufSize => None,
+            RenderError::Msg(_) | RenderError::BufSize | RenderError::LimitExceeded => {
+                None
+            }
             RenderError::Fmt(e) => Some(e),
+            RenderError::Io(e) => Some(&**e),
+            // the message of the original error is included in `Display`
+            RenderError::Located(e) => e.error.source(),
         }
     }
 }
 
+/// Location inside the template files
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Location {
+    /// Path to the template file, relative to the template directory
+    pub path: &'static str,
+    /// Line number, starting from 1
+    pub line: u32,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.path, self.line)
+    }
+}
+
+/// Error with the locations in the template files
+///
+/// See [`RenderError::trace`] for details.
+#[derive(Clone, Debug)]
+pub struct LocatedError {
+    error: RenderError,
+    trace: Vec<Location>,
+}
+
+impl LocatedError {
+    /// Returns the original error
+    pub fn error(&self) -> &RenderError {
+        &self.error
+    }
+
+    /// Returns the locations where the error was propagated
+    pub fn trace(&self) -> &[Location] {
+        &self.trace
+    }
+}
+
 impl From<fmt::Error> for RenderError {
     #[inline]
     fn from(other: fmt::Error) -> Self {
@@ -414,6 +531,13 @@ impl From<fmt::Error> for RenderError {
     }
 }
 
+impl From<io::Error> for RenderError {
+    #[inline]
+    fn from(other: io::Error) -> Self {
+        RenderError::Io(Arc::new(other))
+    }
+}
+
 /// Result type returned from `TemplateOnce::render_once` method
 pub type RenderResult = Result<String, RenderError>;
 
@@ -477,16 +601,16 @@ mod tests {
         let mut b = Buffer::new();
 
         Render::render_escaped(&0.0f64, &mut b).unwrap();
-        Render::render_escaped(&std::f64::INFINITY, &mut b).unwrap();
-        Render::render_escaped(&std::f64::NEG_INFINITY, &mut b).unwrap();
-        Render::render_escaped(&std::f64::NAN, &mut b).unwrap();
+        Render::render_escaped(&f64::INFINITY, &mut b).unwrap();
+        Render::render_escaped(&f64::NEG_INFINITY, &mut b).unwrap();
+        Render::render_escaped(&f64::NAN, &mut b).unwrap();
         assert_eq!(b.as_str(), "0.0inf-infNaN");
         b.clear();
 
         Render::render_escaped(&0.0f32, &mut b).unwrap();
-        Render::render_escaped(&std::f32::INFINITY, &mut b).unwrap();
-        Render::render_escaped(&std::f32::NEG_INFINITY, &mut b).unwrap();
-        Render::render_escaped(&std::f32::NAN, &mut b).unwrap();
+        Render::render_escaped(&f32::INFINITY, &mut b).unwrap();
+        Render::render_escaped(&f32::NEG_INFINITY, &mut b).unwrap();
+        Render::render_escaped(&f32::NAN, &mut b).unwrap();
         assert_eq!(b.as_str(), "0.0inf-infNaN");
     }
 
@@ -494,8 +618,8 @@ mod tests {
     fn test_char() {
         let mut b = Buffer::new();
 
-        let funcs: Vec<fn(&char, &mut Buffer) -> Result<(), RenderError>> =
-            vec![Render::render, Render::render_escaped];
+        type RenderFn = fn(&char, &mut Buffer) -> Result<(), RenderError>;
+        let funcs: Vec<RenderFn> = vec![Render::render, Render::render_escaped];
 
         for func in funcs {
             func(&'a', &mut b).unwrap();
@@ -530,16 +654,33 @@ mod tests {
         assert!(err.source().is_none());
         assert_eq!(format!("{}", err), "custom error");
 
-        let err = RenderError::from(std::fmt::Error::default());
+        let err = RenderError::from(std::fmt::Error);
         assert!(err.source().is_some());
         assert_eq!(
             format!("{}", err),
-            format!("{}", std::fmt::Error::default())
+            format!("{}", std::fmt::Error)
         );
 
         let err = RenderError::BufSize;
         assert!(err.source().is_none());
+        assert!(err.location().is_none());
 
         assert!(format!("{}", err).is_empty().eq(&false));
     }
+
+    #[test]
+    fn render_error_location() {
+        let location = Location {
+            path: "page.stpl",
+            line: 12,
+        };
+        let err = RenderError::from(std::fmt::Error).with_location(location);
+        assert!(matches!(err.inner(), RenderError::Fmt(_)));
+        assert_eq!(err.location(), Some(location));
+        assert!(err.source().is_some());
+        assert_eq!(
+            format!("{}", err),
+            format!("{} at page.stpl:12", std::fmt::Error)
+        );
+    }
 }
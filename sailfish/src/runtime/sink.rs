@@ -0,0 +1,193 @@
+use std::io;
+
+use super::buffer::Buffer;
+use super::render::RenderError;
+
+/// Default threshold of `WriterSink` in bytes
+pub const DEFAULT_THRESHOLD: usize = 8192;
+
+/// Destination of the rendering output
+///
+/// Templates always render into the `Buffer` returned from `buffer` method, and
+/// call `checkpoint` method after each value is rendered, and after each text if
+/// `CHECKPOINT_TEXT` is `true`. Sinks may consume the buffer contents inside
+/// `checkpoint` and `break_point` methods.
+///
+/// # Safety
+///
+/// Contents rendered after the last call of `checkpoint` method must not be
+/// consumed, because templates may truncate them.
+pub unsafe trait Sink {
+    /// Whether `checkpoint` is also called after each text of the templates
+    ///
+    /// Sinks which do nothing in `checkpoint` set this to `false`, so that the
+    /// templates skip the calls after the texts.
+    const CHECKPOINT_TEXT: bool = true;
+
+    /// Returns the buffer which templates render into
+    fn buffer(&mut self) -> &mut Buffer;
+
+    /// Called after each value is rendered, and after each text if
+    /// `CHECKPOINT_TEXT` is `true`
+    #[inline]
+    fn checkpoint(&mut self) -> Result<(), RenderError> {
+        Ok(())
+    }
+
+    /// Called at the boundaries of nested templates (`<%+ %>`) and at the
+    /// explicit flush markers (`<%@ flush %>`)
+    #[inline]
+    fn break_point(&mut self) -> Result<(), RenderError> {
+        Ok(())
+    }
+}
+
+unsafe impl Sink for Buffer {
+    const CHECKPOINT_TEXT: bool = false;
+
+    #[inline]
+    fn buffer(&mut self) -> &mut Buffer {
+        self
+    }
+}
+
+/// call `checkpoint` method after the text if `sink` requires
+#[doc(hidden)]
+#[inline]
+pub fn text_checkpoint<S: Sink + ?Sized>(sink: &mut S) -> Result<(), RenderError> {
+    if S::CHECKPOINT_TEXT {
+        sink.checkpoint()
+    } else {
+        Ok(())
+    }
+}
+
+/// Sink which writes the output into `io::Write` in chunks
+///
+/// The buffer is flushed into the writer once its length exceeds the threshold,
+/// so the memory usage does not grow with the output size.
+///
+/// ```
+/// use sailfish::runtime::{Sink, WriterSink};
+///
+/// let mut sink = WriterSink::with_threshold(Vec::new(), 4);
+/// sink.buffer().push_str("Hello, ");
+/// sink.checkpoint().unwrap();
+/// sink.buffer().push_str("World!");
+///
+/// let output = sink.finish().unwrap();
+/// assert_eq!(output, b"Hello, World!");
+/// ```
+pub struct WriterSink<W: io::Write> {
+    buf: Buffer,
+    writer: W,
+    threshold: usize,
+}
+
+impl<W: io::Write> WriterSink<W> {
+    /// Create a new sink with the default threshold
+    #[inline]
+    pub fn new(writer: W) -> Self {
+        Self::with_threshold(writer, DEFAULT_THRESHOLD)
+    }
+
+    /// Create a new sink which flushes the buffer once its length exceeds
+    /// `threshold` bytes
+    #[inline]
+    pub fn with_threshold(writer: W, threshold: usize) -> Self {
+        Self {
+            buf: Buffer::new(),
+            writer,
+            threshold,
+        }
+    }
+
+    /// Returns the reference to the underlying writer
+    #[inline]
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Write the buffer contents into the writer
+    pub fn flush(&mut self) -> Result<(), RenderError> {
+        if !self.buf.is_empty() {
+            self.writer.write_all(self.buf.as_str().as_bytes())?;
+            self.buf.clear();
+        }
+        Ok(())
+    }
+
+    /// Flush the remaining contents and return the underlying writer
+    pub fn finish(mut self) -> Result<W, RenderError> {
+        self.flush()?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+unsafe impl<W: io::Write> Sink for WriterSink<W> {
+    #[inline]
+    fn buffer(&mut self) -> &mut Buffer {
+        &mut self.buf
+    }
+
+    #[inline]
+    fn checkpoint(&mut self) -> Result<(), RenderError> {
+        if self.buf.len() >= self.threshold {
+            self.flush()
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // records the size of each write
+    struct Chunks(Vec<String>);
+
+    impl io::Write for Chunks {
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+            self.0.push(String::from_utf8(data.to_vec()).unwrap());
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn flush_in_chunks() {
+        let mut sink = WriterSink::with_threshold(Chunks(Vec::new()), 4);
+        for s in ["ab", "cd", "e", "fgh", "i"] {
+            sink.buffer().push_str(s);
+            sink.checkpoint().unwrap();
+        }
+        sink.buffer().push_str("jk");
+
+        let chunks = sink.finish().unwrap().0;
+        assert_eq!(chunks, ["abcd", "efgh", "ijk"]);
+    }
+
+    #[test]
+    fn io_error() {
+        struct Broken;
+
+        impl io::Write for Broken {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::BrokenPipe, "broken"))
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut sink = WriterSink::with_threshold(Broken, 0);
+        sink.buffer().push_str("a");
+        assert!(matches!(sink.checkpoint(), Err(RenderError::Io(_))));
+    }
+}
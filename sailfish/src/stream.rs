@@ -0,0 +1,284 @@
+//! Render templates into asynchronous streams
+//!
+//! [`RenderStream`] renders the template in the background and yields the
+//! output as [`Bytes`] chunks, so that HTTP servers can start sending the
+//! response before the whole template is rendered.
+//!
+//! The output is split at the boundaries of nested templates (`<%+ %>`) and at
+//! the explicit flush markers (`<%@ flush %>`).
+//!
+//! ```
+//! use sailfish::runtime::Sink;
+//! use sailfish::stream::RenderStream;
+//!
+//! let stream = RenderStream::new(|sink| {
+//!     sink.buffer().push_str("<head></head>");
+//!     sink.break_point()?;
+//!     sink.buffer().push_str("<body></body>");
+//!     Ok(())
+//! });
+//! # drop(stream);
+//! ```
+//!
+//! Since templates are rendered synchronously, rendering runs on another thread
+//! and does not depend on any particular async runtime. Rendering is paused
+//! while the chunks are not consumed by the stream.
+
+use bytes::Bytes;
+use futures_core::Stream;
+use std::collections::VecDeque;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+use crate::runtime::{Buffer, RenderError, Sink};
+
+/// Maximum number of chunks which are rendered but not consumed yet
+const MAX_PENDING_CHUNKS: usize = 4;
+
+struct State {
+    chunks: VecDeque<Bytes>,
+    error: Option<RenderError>,
+    finished: bool,
+    // stream was dropped
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    // notified when the chunk is consumed or the stream is dropped
+    consumed: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // state is always consistent since no user code runs inside the lock
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Sink which sends the output to [`RenderStream`]
+pub struct StreamSink {
+    buf: Buffer,
+    shared: Arc<Shared>,
+}
+
+impl StreamSink {
+    /// Send the buffer contents to the stream.
+    ///
+    /// This method blocks while the stream has too many pending chunks.
+    fn send(&mut self) -> Result<(), RenderError> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+
+        let mut state = self.shared.lock();
+        while state.chunks.len() >= MAX_PENDING_CHUNKS && !state.closed {
+            state = self
+                .shared
+                .consumed
+                .wait(state)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+
+        if state.closed {
+            return Err(RenderError::from(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "render stream was dropped",
+            )));
+        }
+
+        let chunk = std::mem::take(&mut self.buf).into_string();
+        state.chunks.push_back(Bytes::from(chunk));
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+        Ok(())
+    }
+}
+
+unsafe impl Sink for StreamSink {
+    const CHECKPOINT_TEXT: bool = false;
+
+    #[inline]
+    fn buffer(&mut self) -> &mut Buffer {
+        &mut self.buf
+    }
+
+    #[inline]
+    fn break_point(&mut self) -> Result<(), RenderError> {
+        self.send()
+    }
+}
+
+/// Stream of the rendered chunks
+///
+/// This stream yields `Err` at most once if rendering fails, and then
+/// terminates.
+pub struct RenderStream {
+    shared: Arc<Shared>,
+}
+
+impl RenderStream {
+    /// Render the output on a new thread
+    pub fn new<F>(render: F) -> Self
+    where
+        F: FnOnce(&mut StreamSink) -> Result<(), RenderError> + Send + 'static,
+    {
+        Self::with_spawner(render, |task| {
+            thread::spawn(task);
+        })
+    }
+
+    /// Render the output on the thread given by `spawn` function.
+    ///
+    /// `spawn` receives the rendering task, which may block the current thread.
+    /// For example, you can pass a function which calls
+    /// `tokio::task::spawn_blocking` to reuse the thread pool of your runtime.
+    pub fn with_spawner<F, S>(render: F, spawn: S) -> Self
+    where
+        F: FnOnce(&mut StreamSink) -> Result<(), RenderError> + Send + 'static,
+        S: FnOnce(Box<dyn FnOnce() + Send>),
+    {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                chunks: VecDeque::new(),
+                error: None,
+                finished: false,
+                closed: false,
+                waker: None,
+            }),
+            consumed: Condvar::new(),
+        });
+
+        let mut sink = StreamSink {
+            buf: Buffer::new(),
+            shared: Arc::clone(&shared),
+        };
+        spawn(Box::new(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                render(&mut sink)?;
+                sink.send()
+            }))
+            .unwrap_or_else(|_| {
+                Err(RenderError::Msg("Template rendering panicked".to_owned()))
+            });
+
+            let mut state = sink.shared.lock();
+            state.error = result.err();
+            state.finished = true;
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        }));
+
+        RenderStream { shared }
+    }
+}
+
+impl Stream for RenderStream {
+    type Item = Result<Bytes, RenderError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut state = self.shared.lock();
+        if let Some(chunk) = state.chunks.pop_front() {
+            self.shared.consumed.notify_one();
+            Poll::Ready(Some(Ok(chunk)))
+        } else if let Some(e) = state.error.take() {
+            Poll::Ready(Some(Err(e)))
+        } else if state.finished {
+            Poll::Ready(None)
+        } else {
+            state.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for RenderStream {
+    fn drop(&mut self) {
+        self.shared.lock().closed = true;
+        self.shared.consumed.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::task::Wake;
+
+    struct Unpark(thread::Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn collect(mut stream: RenderStream) -> Vec<Result<Bytes, RenderError>> {
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut items = Vec::new();
+        loop {
+            match Pin::new(&mut stream).poll_next(&mut cx) {
+                Poll::Ready(Some(item)) => items.push(item),
+                Poll::Ready(None) => return items,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn split_at_break_points() {
+        let stream = RenderStream::new(|sink| {
+            for s in ["a", "b", "", "c"] {
+                sink.buffer().push_str(s);
+                sink.break_point()?;
+                sink.checkpoint()?;
+            }
+            sink.buffer().push_str("d");
+            Ok(())
+        });
+
+        let chunks: Vec<_> = collect(stream).into_iter().map(Result::unwrap).collect();
+        assert_eq!(chunks, ["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn render_error() {
+        let stream = RenderStream::new(|sink| {
+            sink.buffer().push_str("a");
+            sink.break_point()?;
+            Err(RenderError::Msg("error".to_owned()))
+        });
+
+        let items = collect(stream);
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].as_ref().unwrap(), "a");
+        assert!(matches!(items[1], Err(RenderError::Msg(_))));
+
+        let stream = RenderStream::new(|_| panic!("panic inside template"));
+        assert!(matches!(collect(stream)[..], [Err(RenderError::Msg(_))]));
+    }
+
+    #[test]
+    fn stop_rendering_when_dropped() {
+        let (tx, rx) = mpsc::channel();
+        let stream = RenderStream::new(move |sink| {
+            let result = (0..).try_for_each(|_| {
+                sink.buffer().push_str("a");
+                sink.break_point()
+            });
+            tx.send(result).unwrap();
+            Ok(())
+        });
+
+        drop(stream);
+        assert!(matches!(rx.recv().unwrap(), Err(RenderError::Io(_))));
+    }
+}
@@ -1,5 +1,12 @@
 import * as vscode from "vscode";
 import * as prettier from "prettier";
+import {
+  LanguageClient,
+  LanguageClientOptions,
+  ServerOptions,
+} from "vscode-languageclient/node";
+
+let client: LanguageClient | undefined;
 
 /* eslint-disable */ // <-- Before function
 export function activate(context: vscode.ExtensionContext) {
@@ -19,9 +26,44 @@ export function activate(context: vscode.ExtensionContext) {
       return edits;
     },
   });
+
+  startLanguageServer(context);
 }
 /* eslint-enable */  // <-- After function
 
+export function deactivate(): Thenable<void> | undefined {
+  return client?.stop();
+}
+
+// Launch `sailfish lsp` command provided by sailfish-cli crate
+function startLanguageServer(context: vscode.ExtensionContext) {
+  const config = vscode.workspace.getConfiguration("sailfish.server");
+  if (!config.get<boolean>("enable", true)) {
+    return;
+  }
+
+  const serverOptions: ServerOptions = {
+    command: config.get<string>("path") || "sailfish",
+    args: ["lsp"],
+  };
+  const clientOptions: LanguageClientOptions = {
+    documentSelector: [{ scheme: "file", language: "sailfish" }],
+  };
+
+  client = new LanguageClient(
+    "sailfish",
+    "Sailfish Language Server",
+    serverOptions,
+    clientOptions
+  );
+  client.start().catch((err) => {
+    vscode.window.showWarningMessage(
+      `Failed to start sailfish language server (${err}). Install it with \`cargo install sailfish-cli\`, or set \`sailfish.server.path\`.`
+    );
+  });
+  context.subscriptions.push(client);
+}
+
 async function formatSailfishHTML(text: string): Promise<string> {
   const placeholders: { [key: string]: string } = {};
   let placeholderCounter = 0;